@@ -0,0 +1,60 @@
+use std::cmp::Reverse;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, db::ApiKeyRepository, models::api_key::ApiKeyStoreModel};
+
+use super::{MemoryStore, duplicate};
+
+#[rocket::async_trait]
+impl ApiKeyRepository for MemoryStore<ApiKeyStoreModel> {
+    async fn insert(&self, key :&ApiKeyStoreModel) -> Result<(), ApiError> {
+        let mut keys = self.items();
+
+        if keys.iter().any(|other| other._id == key._id) {
+            return Err(duplicate("_id"))
+        }
+
+        if keys.iter().any(|other| other.key_hash == key.key_hash) {
+            return Err(duplicate("key_hash"))
+        }
+
+        keys.push(key.clone());
+        Ok(())
+    }
+
+    async fn list(&self, user :&ObjectId) -> Result<Vec<ApiKeyStoreModel>, ApiError> {
+        let mut keys :Vec<ApiKeyStoreModel> = self.items().iter().filter(|key| &key.user == user).cloned().collect();
+        keys.sort_by_key(|key| Reverse(key.created.timestamp_millis()));
+
+        Ok(keys)
+    }
+
+    async fn delete(&self, id :&ObjectId, user :&ObjectId) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        let mut keys = self.items();
+
+        match keys.iter().position(|key| &key._id == id && &key.user == user) {
+            Some(index) => Ok(Some(keys.remove(index))),
+            None => Ok(None)
+        }
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|key| &key.user != user);
+        Ok(())
+    }
+
+    // Like the MongoDB backend, returns the key as it was before this use.
+    async fn authenticate(&self, key_hash :&str) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        match self.items().iter_mut().find(|key| key.key_hash == key_hash && key.expires.is_none_or(|expires| expires > now)) {
+            Some(key) => {
+                let used = key.clone();
+                key.last_used = Some(now);
+                Ok(Some(used))
+            },
+            None => Ok(None)
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::{errors::ApiError, db::CommentRepository, models::comment::CommentStoreModel};
+
+use super::{MemoryStore, duplicate};
+
+#[rocket::async_trait]
+impl CommentRepository for MemoryStore<CommentStoreModel> {
+    async fn find(&self, id :&ObjectId, post :&ObjectId) -> Result<Option<CommentStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|comment| &comment._id == id && &comment.post == post).cloned())
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<CommentStoreModel>, ApiError> {
+        let mut comments :Vec<CommentStoreModel> = self.items().iter().filter(|comment| &comment.post == post).cloned().collect();
+        comments.sort_by_key(|comment| comment.created.timestamp_millis());
+
+        Ok(comments)
+    }
+
+    async fn insert(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        let mut comments = self.items();
+
+        if comments.iter().any(|other| other._id == comment._id) {
+            return Err(duplicate("_id"))
+        }
+
+        comments.push(comment.clone());
+        Ok(())
+    }
+
+    async fn replace(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        if let Some(other) = self.items().iter_mut().find(|other| other._id == comment._id) {
+            *other = comment.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_by_post(&self, post :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|comment| &comment.post != post);
+        Ok(())
+    }
+
+    async fn ids_by_author_or_posts(&self, author :&ObjectId, posts :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        Ok(self.items().iter()
+            .filter(|comment| &comment.author == author || posts.contains(&comment.post))
+            .map(|comment| comment._id)
+            .collect())
+    }
+
+    async fn reply_ids(&self, parents :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        Ok(self.items().iter()
+            .filter(|comment| matches!(&comment.parent, Some(parent) if parents.contains(parent)))
+            .map(|comment| comment._id)
+            .collect())
+    }
+
+    async fn delete(&self, ids :&[ObjectId]) -> Result<(), ApiError> {
+        self.items().retain(|comment| !ids.contains(&comment._id));
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::cmp::Reverse;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, db::InviteRepository, models::invite::InviteStoreModel};
+
+use super::{MemoryStore, duplicate};
+
+#[rocket::async_trait]
+impl InviteRepository for MemoryStore<InviteStoreModel> {
+    async fn insert(&self, invite :&InviteStoreModel) -> Result<(), ApiError> {
+        let mut invites = self.items();
+
+        if invites.iter().any(|other| other._id == invite._id) {
+            return Err(duplicate("_id"))
+        }
+
+        if invites.iter().any(|other| other.code_hash == invite.code_hash) {
+            return Err(duplicate("code_hash"))
+        }
+
+        invites.push(invite.clone());
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<InviteStoreModel>, ApiError> {
+        let mut invites = self.items().clone();
+        invites.sort_by_key(|invite| Reverse(invite.created.timestamp_millis()));
+
+        Ok(invites)
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<InviteStoreModel>, ApiError> {
+        let mut invites = self.items();
+
+        match invites.iter().position(|invite| &invite._id == id) {
+            Some(index) => Ok(Some(invites.remove(index))),
+            None => Ok(None)
+        }
+    }
+
+    async fn redeem(&self, code_hash :&str) -> Result<Option<InviteStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        match self.items().iter_mut().find(|invite| invite.code_hash == code_hash && invite.expires > now && invite.uses < invite.max_uses) {
+            Some(invite) => {
+                invite.uses += 1;
+                Ok(Some(invite.clone()))
+            },
+            None => Ok(None)
+        }
+    }
+
+    async fn release(&self, id :&ObjectId) -> Result<(), ApiError> {
+        if let Some(invite) = self.items().iter_mut().find(|invite| &invite._id == id && invite.uses > 0) {
+            invite.uses -= 1;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::{cmp::Reverse, sync::MutexGuard};
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, db::{LoginAttemptRepository, LOGIN_ATTEMPT_TTL}, models::login_attempt::{LoginAttemptStoreModel, LoginAttemptKind}};
+
+use super::MemoryStore;
+
+impl MemoryStore<LoginAttemptStoreModel> {
+    // Drops the counters the TTL index would have expired.
+    fn attempts(&self) -> MutexGuard<'_, Vec<LoginAttemptStoreModel>> {
+        let expired = DateTime::now().timestamp_millis() - LOGIN_ATTEMPT_TTL as i64 * 1000;
+
+        let mut attempts = self.items();
+        attempts.retain(|attempt| attempt.last_failure.timestamp_millis() > expired);
+        attempts
+    }
+}
+
+#[rocket::async_trait]
+impl LoginAttemptRepository for MemoryStore<LoginAttemptStoreModel> {
+    async fn find_locked(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        Ok(self.attempts().iter()
+            .find(|attempt| attempt.kind == kind && attempt.subject == subject && matches!(attempt.locked_until, Some(until) if until > now))
+            .cloned())
+    }
+
+    async fn record_failure(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        let now = DateTime::now();
+        let mut attempts = self.attempts();
+
+        match attempts.iter_mut().find(|attempt| attempt.kind == kind && attempt.subject == subject) {
+            Some(attempt) => {
+                attempt.failures += 1;
+                attempt.last_failure = now;
+                Ok(Some(attempt.clone()))
+            },
+            None => {
+                let attempt = LoginAttemptStoreModel {
+                    _id: ObjectId::new(),
+                    kind,
+                    subject: subject.to_string(),
+                    failures: 1,
+                    last_failure: now,
+                    locked_until: None
+                };
+
+                attempts.push(attempt.clone());
+                Ok(Some(attempt))
+            }
+        }
+    }
+
+    async fn lock(&self, id :&ObjectId, until :DateTime) -> Result<(), ApiError> {
+        if let Some(attempt) = self.attempts().iter_mut().find(|attempt| &attempt._id == id) {
+            attempt.locked_until = Some(until);
+        }
+
+        Ok(())
+    }
+
+    async fn clear(&self, kind :LoginAttemptKind, subject :&str) -> Result<(), ApiError> {
+        self.attempts().retain(|attempt| attempt.kind != kind || attempt.subject != subject);
+        Ok(())
+    }
+
+    async fn list(&self, locked_only :bool) -> Result<Vec<LoginAttemptStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        let mut attempts :Vec<LoginAttemptStoreModel> = self.attempts().iter()
+            .filter(|attempt| !locked_only || matches!(attempt.locked_until, Some(until) if until > now))
+            .cloned()
+            .collect();
+        attempts.sort_by_key(|attempt| Reverse(attempt.last_failure.timestamp_millis()));
+
+        Ok(attempts)
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        let mut attempts = self.attempts();
+
+        match attempts.iter().position(|attempt| &attempt._id == id) {
+            Some(index) => Ok(Some(attempts.remove(index))),
+            None => Ok(None)
+        }
+    }
+}
@@ -0,0 +1,135 @@
+mod post;
+mod user;
+mod session;
+mod comment;
+mod revision;
+mod role;
+mod invite;
+mod api_key;
+mod login_attempt;
+
+use std::{cmp::Ordering, sync::{Arc, Mutex, MutexGuard}};
+
+use mongodb::bson::{self, Bson};
+use rocket::serde::Serialize;
+
+use crate::{errors::ApiError, models::{post::PostStoreModel, user::UserStoreModel, session::SessionStoreModel,
+    comment::CommentStoreModel, revision::RevisionStoreModel, role::RoleStoreModel, invite::InviteStoreModel, api_key::ApiKeyStoreModel,
+    login_attempt::LoginAttemptStoreModel, page::PageRequest}};
+
+use super::Db;
+
+// Keeps a collection in memory, for tests and for trying things out without a database. Nothing
+// survives a restart. The unique indexes of the MongoDB backend are enforced all the same.
+pub struct MemoryStore<T> {
+    items :Mutex<Vec<T>>
+}
+
+impl<T> MemoryStore<T> {
+    pub fn new() -> Self {
+        Self { items: Mutex::new(vec![]) }
+    }
+
+    fn items(&self) -> MutexGuard<'_, Vec<T>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Default for MemoryStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn connect() -> Db {
+    Db {
+        posts: Arc::new(MemoryStore::<PostStoreModel>::new()),
+        users: Arc::new(MemoryStore::<UserStoreModel>::new()),
+        sessions: Arc::new(MemoryStore::<SessionStoreModel>::new()),
+        comments: Arc::new(MemoryStore::<CommentStoreModel>::new()),
+        revisions: Arc::new(MemoryStore::<RevisionStoreModel>::new()),
+        roles: Arc::new(MemoryStore::<RoleStoreModel>::new()),
+        invites: Arc::new(MemoryStore::<InviteStoreModel>::new()),
+        api_keys: Arc::new(MemoryStore::<ApiKeyStoreModel>::new()),
+        login_attempts: Arc::new(MemoryStore::<LoginAttemptStoreModel>::new())
+    }
+}
+
+// Stands in for a unique index turning down a write.
+fn duplicate(key :&str) -> ApiError {
+    ApiError::Conflict(format!("Duplicate key {}.", key))
+}
+
+// Orders values the way MongoDB does, by type first and then by value.
+fn compare(a :&Bson, b :&Bson) -> Ordering {
+    fn rank(value :&Bson) -> u8 {
+        match value {
+            Bson::MinKey => 0,
+            Bson::Null | Bson::Undefined => 1,
+            Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) => 2,
+            Bson::String(_) | Bson::Symbol(_) => 3,
+            Bson::Document(_) => 4,
+            Bson::Array(_) => 5,
+            Bson::Binary(_) => 6,
+            Bson::ObjectId(_) => 7,
+            Bson::Boolean(_) => 8,
+            Bson::DateTime(_) => 9,
+            Bson::Timestamp(_) => 10,
+            Bson::MaxKey => 12,
+            _ => 11
+        }
+    }
+
+    fn number(value :&Bson) -> f64 {
+        match value {
+            Bson::Int32(n) => *n as f64,
+            Bson::Int64(n) => *n as f64,
+            Bson::Double(n) => *n,
+            _ => 0.0
+        }
+    }
+
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => a.cmp(b),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => a.bytes().cmp(&b.bytes()),
+        (Bson::Boolean(a), Bson::Boolean(b)) => a.cmp(b),
+        (Bson::DateTime(a), Bson::DateTime(b)) => a.timestamp_millis().cmp(&b.timestamp_millis()),
+        (a, b) if rank(a) == 2 && rank(b) == 2 => number(a).partial_cmp(&number(b)).unwrap_or(Ordering::Equal),
+        (a, b) => rank(a).cmp(&rank(b))
+    }
+}
+
+// Pages through the items the way the MongoDB backend does, by the sort field and then by `_id`.
+fn page<T :Serialize>(items :Vec<T>, page :&PageRequest) -> Result<(Vec<T>, u64), ApiError> {
+    let total = items.len() as u64;
+
+    let mut keyed :Vec<((Bson, Bson), T)> = vec![];
+    for item in items {
+        let document = match bson::to_document(&item) {
+            Ok(document) => document,
+            Err(e) => return Err(ApiError::Internal(e.to_string()))
+        };
+
+        let value = document.get(page.field).cloned().unwrap_or(Bson::Null);
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        keyed.push(((value, id), item));
+    }
+
+    let order = |a :&(Bson, Bson), b :&(Bson, Bson)| {
+        let ordering = compare(&a.0, &b.0).then_with(|| compare(&a.1, &b.1));
+        if page.descending { ordering.reverse() } else { ordering }
+    };
+    keyed.sort_by(|a, b| order(&a.0, &b.0));
+
+    let after = page.after.as_ref().map(|position| (position.value.clone(), position.id.clone()));
+    let items = keyed.into_iter()
+        .filter(|(key, _item)| match &after {
+            Some(after) => order(key, after) == Ordering::Greater,
+            None => true
+        })
+        .take(page.limit as usize)
+        .map(|(_key, item)| item)
+        .collect();
+
+    Ok((items, total))
+}
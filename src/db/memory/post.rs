@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, db::PostRepository, models::{post::{PostStoreModel, PostFilter, PostStatus},
+    page::PageRequest, search::{self, PostSearchHit}, tag::TagReadModel}};
+
+use super::{MemoryStore, duplicate, page};
+
+fn slug_taken(posts :&[PostStoreModel], slug :&str, except :&ObjectId) -> bool {
+    posts.iter().any(|post| &post._id != except && (post.slug == slug || post.slug_history.iter().any(|old| old == slug)))
+}
+
+#[rocket::async_trait]
+impl PostRepository for MemoryStore<PostStoreModel> {
+    async fn find(&self, key :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        Ok(self.items().iter()
+            .find(|post| post.slug == key || post.slug_history.iter().any(|old| old == key) || post._id.to_hex() == key)
+            .cloned())
+    }
+
+    async fn find_by_title(&self, title :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|post| post.title == title).cloned())
+    }
+
+    async fn slug_taken(&self, slug :&str, except :&ObjectId) -> Result<bool, ApiError> {
+        Ok(slug_taken(&self.items(), slug, except))
+    }
+
+    async fn insert(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        let mut posts = self.items();
+
+        if posts.iter().any(|other| other._id == post._id) {
+            return Err(duplicate("_id"))
+        }
+
+        if posts.iter().any(|other| other.slug == post.slug) {
+            return Err(duplicate("slug"))
+        }
+
+        if posts.iter().any(|other| other.title == post.title) {
+            return Err(duplicate("title"))
+        }
+
+        posts.push(post.clone());
+        Ok(())
+    }
+
+    async fn replace(&self, post :&PostStoreModel) -> Result<bool, ApiError> {
+        let mut posts = self.items();
+
+        if posts.iter().any(|other| other._id != post._id && other.slug == post.slug) {
+            return Err(duplicate("slug"))
+        }
+
+        if posts.iter().any(|other| other._id != post._id && other.title == post.title) {
+            return Err(duplicate("title"))
+        }
+
+        match posts.iter_mut().find(|other| other._id == post._id && other.revision + 1 == post.revision) {
+            Some(other) => {
+                *other = post.clone();
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    async fn set_status(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        if let Some(other) = self.items().iter_mut().find(|other| other._id == post._id) {
+            other.status = post.status;
+            other.publish_at = post.publish_at;
+            other.updated = post.updated;
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|post| &post._id != id);
+        Ok(())
+    }
+
+    async fn ids_by_author(&self, author :&ObjectId) -> Result<Vec<ObjectId>, ApiError> {
+        Ok(self.items().iter().filter(|post| &post.author == author).map(|post| post._id).collect())
+    }
+
+    async fn delete_by_author(&self, author :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|post| &post.author != author);
+        Ok(())
+    }
+
+    async fn page(&self, filter :&PostFilter, request :&PageRequest) -> Result<(Vec<PostStoreModel>, u64), ApiError> {
+        let posts :Vec<PostStoreModel> = self.items().iter().filter(|post| filter.matches(post)).cloned().collect();
+        page(posts, request)
+    }
+
+    // A plain substring search. Posts match if they contain any of the terms and none of the negated ones.
+    async fn search(&self, q :&str, filter :&PostFilter, skip :u64, limit :u32) -> Result<(Vec<PostSearchHit>, u64), ApiError> {
+        let terms :Vec<String> = search::terms(q).into_iter().map(|term| term.into_iter().collect()).collect();
+        let negated = search::negated_terms(q);
+
+        let mut hits :Vec<PostSearchHit> = vec![];
+        for post in self.items().iter().filter(|post| filter.matches(post)) {
+            let title = post.title.to_lowercase();
+            let content = post.content.to_lowercase();
+
+            if negated.iter().any(|term| title.contains(term.as_str()) || content.contains(term.as_str())) {
+                continue
+            }
+
+            let score :i64 = terms.iter()
+                .map(|term| title.matches(term.as_str()).count() as i64 * search::TITLE_WEIGHT + content.matches(term.as_str()).count() as i64)
+                .sum();
+
+            if score > 0 {
+                hits.push(PostSearchHit { post: post.clone(), score: score as f64 });
+            }
+        }
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| b.post._id.bytes().cmp(&a.post._id.bytes())));
+
+        let total = hits.len() as u64;
+        Ok((hits.into_iter().skip(skip as usize).take(limit as usize).collect(), total))
+    }
+
+    async fn latest(&self, filter :&PostFilter, limit :u32) -> Result<Vec<PostStoreModel>, ApiError> {
+        let mut posts :Vec<PostStoreModel> = self.items().iter().filter(|post| filter.matches(post)).cloned().collect();
+        posts.sort_by(|a, b| b.publish_at.map(|p| p.timestamp_millis()).cmp(&a.publish_at.map(|p| p.timestamp_millis()))
+            .then_with(|| b._id.bytes().cmp(&a._id.bytes())));
+        posts.truncate(limit as usize);
+
+        Ok(posts)
+    }
+
+    async fn tag_counts(&self, filter :&PostFilter) -> Result<Vec<TagReadModel>, ApiError> {
+        let mut counts :HashMap<String, u64> = HashMap::new();
+        for post in self.items().iter().filter(|post| filter.matches(post)) {
+            for tag in &post.tags {
+                *counts.entry(tag.clone()).or_default() += 1;
+            }
+        }
+
+        let mut tags :Vec<TagReadModel> = counts.into_iter().map(|(name, posts)| TagReadModel { name, posts }).collect();
+        tags.sort_by(|a, b| b.posts.cmp(&a.posts).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(tags)
+    }
+
+    async fn count_tag(&self, tag :&str) -> Result<u64, ApiError> {
+        Ok(self.items().iter().filter(|post| post.tags.iter().any(|other| other == tag)).count() as u64)
+    }
+
+    async fn retag(&self, from :&str, to :&str) -> Result<u64, ApiError> {
+        let mut replaced = 0;
+
+        for post in self.items().iter_mut() {
+            if !post.tags.iter().any(|tag| tag == from) {
+                continue
+            }
+
+            if post.tags.iter().any(|tag| tag == to) {
+                post.tags.retain(|tag| tag != from);
+                continue
+            }
+
+            for tag in post.tags.iter_mut().filter(|tag| *tag == from) {
+                *tag = to.to_string();
+            }
+            replaced += 1;
+        }
+
+        Ok(replaced)
+    }
+
+    async fn publish_scheduled(&self) -> Result<Option<DateTime>, ApiError> {
+        let now = DateTime::now();
+        let mut posts = self.items();
+
+        for post in posts.iter_mut().filter(|post| post.status == PostStatus::Scheduled) {
+            if matches!(post.publish_at, Some(publish_at) if publish_at <= now) {
+                post.status = PostStatus::Published;
+                post.updated = Some(now);
+            }
+        }
+
+        Ok(posts.iter()
+            .filter(|post| post.status == PostStatus::Scheduled)
+            .filter_map(|post| post.publish_at)
+            .min_by_key(|publish_at| publish_at.timestamp_millis()))
+    }
+
+    // Posts stored in memory always have every field.
+    async fn backfill(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn unrevisioned(&self) -> Result<Vec<PostStoreModel>, ApiError> {
+        Ok(self.items().iter().filter(|post| post.revision == 0).cloned().collect())
+    }
+}
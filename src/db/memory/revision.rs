@@ -0,0 +1,37 @@
+use std::cmp::Reverse;
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::{errors::ApiError, db::RevisionRepository, models::revision::RevisionStoreModel};
+
+use super::{MemoryStore, duplicate};
+
+#[rocket::async_trait]
+impl RevisionRepository for MemoryStore<RevisionStoreModel> {
+    async fn insert(&self, revision :&RevisionStoreModel) -> Result<(), ApiError> {
+        let mut revisions = self.items();
+
+        if revisions.iter().any(|other| other.post == revision.post && other.number == revision.number) {
+            return Err(duplicate("post, number"))
+        }
+
+        revisions.push(revision.clone());
+        Ok(())
+    }
+
+    async fn find(&self, post :&ObjectId, number :u32) -> Result<Option<RevisionStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|revision| &revision.post == post && revision.number == number).cloned())
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<RevisionStoreModel>, ApiError> {
+        let mut revisions :Vec<RevisionStoreModel> = self.items().iter().filter(|revision| &revision.post == post).cloned().collect();
+        revisions.sort_by_key(|revision| Reverse(revision.number));
+
+        Ok(revisions)
+    }
+
+    async fn delete_by_posts(&self, posts :&[ObjectId]) -> Result<(), ApiError> {
+        self.items().retain(|revision| !posts.contains(&revision.post));
+        Ok(())
+    }
+}
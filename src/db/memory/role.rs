@@ -0,0 +1,37 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::{errors::ApiError, db::RoleRepository, models::{role::{RoleStoreModel, Capability}, user::UserPermissionLevel}};
+
+use super::MemoryStore;
+
+#[rocket::async_trait]
+impl RoleRepository for MemoryStore<RoleStoreModel> {
+    async fn find(&self, role :UserPermissionLevel) -> Result<Option<RoleStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|other| other.name == role).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<RoleStoreModel>, ApiError> {
+        Ok(self.items().clone())
+    }
+
+    async fn insert_missing(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        let mut roles = self.items();
+
+        if !roles.iter().any(|other| other.name == role) {
+            roles.push(RoleStoreModel { _id: ObjectId::new(), name: role, capabilities: capabilities.to_vec() });
+        }
+
+        Ok(())
+    }
+
+    async fn set_capabilities(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        let mut roles = self.items();
+
+        match roles.iter_mut().find(|other| other.name == role) {
+            Some(other) => other.capabilities = capabilities.to_vec(),
+            None => roles.push(RoleStoreModel { _id: ObjectId::new(), name: role, capabilities: capabilities.to_vec() })
+        }
+
+        Ok(())
+    }
+}
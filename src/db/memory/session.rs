@@ -0,0 +1,77 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, db::SessionRepository, models::session::SessionStoreModel};
+
+use super::{MemoryStore, duplicate};
+
+#[rocket::async_trait]
+impl SessionRepository for MemoryStore<SessionStoreModel> {
+    async fn insert(&self, session :&SessionStoreModel) -> Result<(), ApiError> {
+        let mut sessions = self.items();
+
+        if sessions.iter().any(|other| other._id == session._id) {
+            return Err(duplicate("_id"))
+        }
+
+        sessions.push(session.clone());
+        Ok(())
+    }
+
+    async fn is_active(&self, id :&ObjectId) -> Result<bool, ApiError> {
+        let now = DateTime::now();
+        Ok(self.items().iter().any(|session| &session._id == id && !session.revoked && session.expires > now))
+    }
+
+    async fn rotate(&self, presented_hash :&str, token_hash :&str, expires :DateTime) -> Result<Option<SessionStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        match self.items().iter_mut().find(|session| session.token_hash == presented_hash && !session.revoked && session.expires > now) {
+            Some(session) => {
+                session.token_hash = token_hash.to_string();
+                session.expires = expires;
+                session.used_hashes.push(presented_hash.to_string());
+                Ok(Some(session.clone()))
+            },
+            None => Ok(None)
+        }
+    }
+
+    async fn revoke_reused(&self, presented_hash :&str) -> Result<bool, ApiError> {
+        match self.items().iter_mut().find(|session| session.used_hashes.iter().any(|hash| hash == presented_hash)) {
+            Some(session) => {
+                session.revoked = true;
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    async fn revoke(&self, id :&ObjectId) -> Result<(), ApiError> {
+        if let Some(session) = self.items().iter_mut().find(|session| &session._id == id) {
+            session.revoked = true;
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        for session in self.items().iter_mut().filter(|session| &session.user == user) {
+            session.revoked = true;
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_others(&self, user :&ObjectId, keep :&ObjectId) -> Result<(), ApiError> {
+        for session in self.items().iter_mut().filter(|session| &session.user == user && &session._id != keep) {
+            session.revoked = true;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|session| &session.user != user);
+        Ok(())
+    }
+}
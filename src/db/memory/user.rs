@@ -0,0 +1,145 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::{errors::ApiError, db::UserRepository, models::{user::UserStoreModel, totp::TotpStoreModel, page::PageRequest}};
+
+use super::{MemoryStore, duplicate, page};
+
+fn name_taken(users :&[UserStoreModel], user :&UserStoreModel) -> bool {
+    users.iter().any(|other| other._id != user._id && other.name == user.name)
+}
+
+fn email_taken(users :&[UserStoreModel], user :&UserStoreModel) -> bool {
+    user.email.is_some() && users.iter().any(|other| other._id != user._id && other.email == user.email)
+}
+
+#[rocket::async_trait]
+impl UserRepository for MemoryStore<UserStoreModel> {
+    async fn find(&self, id :&ObjectId) -> Result<Option<UserStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|user| &user._id == id).cloned())
+    }
+
+    async fn find_by_name(&self, name :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|user| user.name == name).cloned())
+    }
+
+    async fn find_by_email(&self, email :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        Ok(self.items().iter().find(|user| user.email.as_deref() == Some(email)).cloned())
+    }
+
+    async fn page(&self, request :&PageRequest) -> Result<(Vec<UserStoreModel>, u64), ApiError> {
+        let users = self.items().clone();
+        page(users, request)
+    }
+
+    async fn insert(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        let mut users = self.items();
+
+        if users.iter().any(|other| other._id == user._id) {
+            return Err(duplicate("_id"))
+        }
+
+        if name_taken(&users, user) {
+            return Err(duplicate("name"))
+        }
+
+        if email_taken(&users, user) {
+            return Err(duplicate("email"))
+        }
+
+        users.push(user.clone());
+        Ok(())
+    }
+
+    async fn replace(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        let mut users = self.items();
+
+        if name_taken(&users, user) {
+            return Err(duplicate("name"))
+        }
+
+        if email_taken(&users, user) {
+            return Err(duplicate("email"))
+        }
+
+        if let Some(other) = users.iter_mut().find(|other| other._id == user._id) {
+            *other = user.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        self.items().retain(|user| &user._id != id);
+        Ok(())
+    }
+
+    async fn set_password_hash(&self, id :&ObjectId, password_hash :&str) -> Result<(), ApiError> {
+        if let Some(user) = self.items().iter_mut().find(|user| &user._id == id) {
+            user.password_hash = password_hash.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn change_password_hash(&self, id :&ObjectId, from :&str, to :&str) -> Result<bool, ApiError> {
+        match self.items().iter_mut().find(|user| &user._id == id && user.password_hash == from) {
+            Some(user) => {
+                user.password_hash = to.to_string();
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    async fn verify_email(&self, id :&ObjectId, email :Option<&str>) -> Result<(), ApiError> {
+        if let Some(user) = self.items().iter_mut().find(|user| &user._id == id && user.email.as_deref() == email) {
+            user.email_verified = true;
+        }
+
+        Ok(())
+    }
+
+    async fn set_totp(&self, id :&ObjectId, totp :Option<&TotpStoreModel>) -> Result<(), ApiError> {
+        if let Some(user) = self.items().iter_mut().find(|user| &user._id == id) {
+            user.totp = totp.cloned();
+        }
+
+        Ok(())
+    }
+
+    async fn advance_totp_step(&self, id :&ObjectId, from :i64, to :i64) -> Result<bool, ApiError> {
+        let mut users = self.items();
+
+        match users.iter_mut().find(|user| &user._id == id).and_then(|user| user.totp.as_mut()) {
+            Some(totp) if totp.last_step == from => {
+                totp.last_step = to;
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    async fn use_recovery_code(&self, id :&ObjectId, digest :&str) -> Result<bool, ApiError> {
+        let mut users = self.items();
+
+        match users.iter_mut().find(|user| &user._id == id).and_then(|user| user.totp.as_mut()) {
+            Some(totp) if totp.recovery_codes.iter().any(|code| code == digest) => {
+                totp.recovery_codes.retain(|code| code != digest);
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    async fn set_recovery_codes(&self, id :&ObjectId, digests :&[String]) -> Result<(), ApiError> {
+        let mut users = self.items();
+
+        if let Some(totp) = users.iter_mut().find(|user| &user._id == id).and_then(|user| user.totp.as_mut()) {
+            if totp.enabled {
+                totp.recovery_codes = digests.to_vec();
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,19 +1,182 @@
-use mongodb::{Client, options::ClientOptions, error::Error, Collection};
+pub mod mongo;
+pub mod memory;
+pub mod sql;
 
-use crate::models::{post::PostStoreModel, user::UserStoreModel};
+use std::sync::Arc;
 
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{errors::ApiError, models::{post::{PostStoreModel, PostFilter}, user::UserStoreModel, session::SessionStoreModel,
+    comment::CommentStoreModel, revision::RevisionStoreModel, role::{RoleStoreModel, Capability}, invite::InviteStoreModel,
+    api_key::ApiKeyStoreModel, login_attempt::{LoginAttemptStoreModel, LoginAttemptKind}, user::UserPermissionLevel, totp::TotpStoreModel,
+    page::PageRequest, search::PostSearchHit, tag::TagReadModel}};
+
+// Failed sign in counters are dropped this many seconds after the last failure.
+pub const LOGIN_ATTEMPT_TTL :u64 = 24 * 3600;
+
+// The storage the routes work with. Every repository is implemented for MongoDB, for PostgreSQL and SQLite,
+// and in memory for tests.
 pub struct Db {
-    pub posts :Collection<PostStoreModel>,
-    pub users :Collection<UserStoreModel>
+    pub posts :Arc<dyn PostRepository>,
+    pub users :Arc<dyn UserRepository>,
+    pub sessions :Arc<dyn SessionRepository>,
+    pub comments :Arc<dyn CommentRepository>,
+    pub revisions :Arc<dyn RevisionRepository>,
+    pub roles :Arc<dyn RoleRepository>,
+    pub invites :Arc<dyn InviteRepository>,
+    pub api_keys :Arc<dyn ApiKeyRepository>,
+    pub login_attempts :Arc<dyn LoginAttemptRepository>
+}
+
+// Picks the backend by the scheme of the URI, `memory:` keeps everything in memory until the process exits.
+// The database name is only used by MongoDB, the SQL backends take theirs from the URI.
+pub async fn connect(uri :&str, db :&str) -> Result<Db, String> {
+    match uri.split(':').next() {
+        Some("memory") => Ok(memory::connect()),
+        Some("postgres") | Some("postgresql") | Some("sqlite") => sql::connect(uri).await.map_err(|e| e.to_string()),
+        _ => mongo::connect(uri, db).await
+    }
+}
+
+#[rocket::async_trait]
+pub trait PostRepository :Send + Sync {
+    // Finds a post by its `_id`, its current slug, or any slug it was previously known under.
+    async fn find(&self, key :&str) -> Result<Option<PostStoreModel>, ApiError>;
+    async fn find_by_title(&self, title :&str) -> Result<Option<PostStoreModel>, ApiError>;
+    // Whether a post other than `except` is, or used to be, reachable under the slug.
+    async fn slug_taken(&self, slug :&str, except :&ObjectId) -> Result<bool, ApiError>;
+    async fn insert(&self, post :&PostStoreModel) -> Result<(), ApiError>;
+    // Stores a new revision of the post in place of the one before it. Returns false if the stored post
+    // is no longer that revision, because someone else revised it in the meantime.
+    async fn replace(&self, post :&PostStoreModel) -> Result<bool, ApiError>;
+    async fn set_status(&self, post :&PostStoreModel) -> Result<(), ApiError>;
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError>;
+    async fn ids_by_author(&self, author :&ObjectId) -> Result<Vec<ObjectId>, ApiError>;
+    async fn delete_by_author(&self, author :&ObjectId) -> Result<(), ApiError>;
+    // A page of the posts matching the filter, and how many there are in total.
+    async fn page(&self, filter :&PostFilter, page :&PageRequest) -> Result<(Vec<PostStoreModel>, u64), ApiError>;
+    // Full-text search over titles and content, best matches first.
+    async fn search(&self, q :&str, filter :&PostFilter, skip :u64, limit :u32) -> Result<(Vec<PostSearchHit>, u64), ApiError>;
+    // The most recently published posts matching the filter.
+    async fn latest(&self, filter :&PostFilter, limit :u32) -> Result<Vec<PostStoreModel>, ApiError>;
+    // Counts the posts matching the filter under each of their tags, most used tags first.
+    async fn tag_counts(&self, filter :&PostFilter) -> Result<Vec<TagReadModel>, ApiError>;
+    async fn count_tag(&self, tag :&str) -> Result<u64, ApiError>;
+    // Replaces a tag with another one on every post, merging the two where a post already carries both.
+    // Returns how many posts had the tag replaced.
+    async fn retag(&self, from :&str, to :&str) -> Result<u64, ApiError>;
+    // Publishes the scheduled posts whose time has come, and returns when the next one is due.
+    async fn publish_scheduled(&self) -> Result<Option<DateTime>, ApiError>;
+    // Fills in fields introduced after a post was stored. See `PostStoreModel::backfill`.
+    async fn backfill(&self) -> Result<(), ApiError>;
+    // Posts stored before revisions were tracked.
+    async fn unrevisioned(&self) -> Result<Vec<PostStoreModel>, ApiError>;
+}
+
+#[rocket::async_trait]
+pub trait UserRepository :Send + Sync {
+    async fn find(&self, id :&ObjectId) -> Result<Option<UserStoreModel>, ApiError>;
+    async fn find_by_name(&self, name :&str) -> Result<Option<UserStoreModel>, ApiError>;
+    async fn find_by_email(&self, email :&str) -> Result<Option<UserStoreModel>, ApiError>;
+    async fn page(&self, page :&PageRequest) -> Result<(Vec<UserStoreModel>, u64), ApiError>;
+    async fn insert(&self, user :&UserStoreModel) -> Result<(), ApiError>;
+    async fn replace(&self, user :&UserStoreModel) -> Result<(), ApiError>;
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError>;
+    async fn set_password_hash(&self, id :&ObjectId, password_hash :&str) -> Result<(), ApiError>;
+    // Only goes through if the password is still the one the hash was read for. Returns whether it did.
+    async fn change_password_hash(&self, id :&ObjectId, from :&str, to :&str) -> Result<bool, ApiError>;
+    // Only goes through if the address is still the one that was verified.
+    async fn verify_email(&self, id :&ObjectId, email :Option<&str>) -> Result<(), ApiError>;
+    async fn set_totp(&self, id :&ObjectId, totp :Option<&TotpStoreModel>) -> Result<(), ApiError>;
+    // Moves the last accepted time step forward, unless a code was accepted since `from` was read.
+    async fn advance_totp_step(&self, id :&ObjectId, from :i64, to :i64) -> Result<bool, ApiError>;
+    // Removes a recovery code, unless it was already used up.
+    async fn use_recovery_code(&self, id :&ObjectId, digest :&str) -> Result<bool, ApiError>;
+    // Only applies to an enabled enrolment.
+    async fn set_recovery_codes(&self, id :&ObjectId, digests :&[String]) -> Result<(), ApiError>;
+}
+
+#[rocket::async_trait]
+pub trait SessionRepository :Send + Sync {
+    async fn insert(&self, session :&SessionStoreModel) -> Result<(), ApiError>;
+    // Whether the session is neither revoked nor expired.
+    async fn is_active(&self, id :&ObjectId) -> Result<bool, ApiError>;
+    // Exchanges a refresh token for a new one, atomically, so that it can only ever be exchanged once.
+    // Returns the session as it is after the exchange.
+    async fn rotate(&self, presented_hash :&str, token_hash :&str, expires :DateTime) -> Result<Option<SessionStoreModel>, ApiError>;
+    // Revokes the session a rotated-out refresh token belonged to. Returns whether there was one.
+    async fn revoke_reused(&self, presented_hash :&str) -> Result<bool, ApiError>;
+    async fn revoke(&self, id :&ObjectId) -> Result<(), ApiError>;
+    async fn revoke_by_user(&self, user :&ObjectId) -> Result<(), ApiError>;
+    // Revokes all sessions of the user except the one given.
+    async fn revoke_others(&self, user :&ObjectId, keep :&ObjectId) -> Result<(), ApiError>;
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError>;
+}
+
+#[rocket::async_trait]
+pub trait CommentRepository :Send + Sync {
+    async fn find(&self, id :&ObjectId, post :&ObjectId) -> Result<Option<CommentStoreModel>, ApiError>;
+    // All comments under the post, oldest first.
+    async fn list(&self, post :&ObjectId) -> Result<Vec<CommentStoreModel>, ApiError>;
+    async fn insert(&self, comment :&CommentStoreModel) -> Result<(), ApiError>;
+    async fn replace(&self, comment :&CommentStoreModel) -> Result<(), ApiError>;
+    async fn delete_by_post(&self, post :&ObjectId) -> Result<(), ApiError>;
+    // Comments written by the author, or under any of the posts.
+    async fn ids_by_author_or_posts(&self, author :&ObjectId, posts :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError>;
+    async fn reply_ids(&self, parents :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError>;
+    async fn delete(&self, ids :&[ObjectId]) -> Result<(), ApiError>;
+}
+
+#[rocket::async_trait]
+pub trait RevisionRepository :Send + Sync {
+    async fn insert(&self, revision :&RevisionStoreModel) -> Result<(), ApiError>;
+    async fn find(&self, post :&ObjectId, number :u32) -> Result<Option<RevisionStoreModel>, ApiError>;
+    // The revisions of the post, newest first.
+    async fn list(&self, post :&ObjectId) -> Result<Vec<RevisionStoreModel>, ApiError>;
+    async fn delete_by_posts(&self, posts :&[ObjectId]) -> Result<(), ApiError>;
 }
 
-pub async fn connect(uri :&str, db :&str) -> Result<Db, Error> {
-    let opts = ClientOptions::parse(uri).await?;
-    let client = Client::with_options(opts)?;
-    let db = client.database(db);
+#[rocket::async_trait]
+pub trait RoleRepository :Send + Sync {
+    async fn find(&self, role :UserPermissionLevel) -> Result<Option<RoleStoreModel>, ApiError>;
+    async fn list(&self) -> Result<Vec<RoleStoreModel>, ApiError>;
+    // Creates the role with the capabilities, unless it already exists.
+    async fn insert_missing(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError>;
+    async fn set_capabilities(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError>;
+}
 
-    let posts = db.collection::<PostStoreModel>("Post");
-    let users = db.collection::<UserStoreModel>("User");
+#[rocket::async_trait]
+pub trait InviteRepository :Send + Sync {
+    async fn insert(&self, invite :&InviteStoreModel) -> Result<(), ApiError>;
+    // All invites, newest first.
+    async fn list(&self) -> Result<Vec<InviteStoreModel>, ApiError>;
+    async fn delete(&self, id :&ObjectId) -> Result<Option<InviteStoreModel>, ApiError>;
+    // Uses up one registration of an unexpired invite, atomically, so that concurrent registrations
+    // can't go over the limit. Returns the invite as it is afterwards.
+    async fn redeem(&self, code_hash :&str) -> Result<Option<InviteStoreModel>, ApiError>;
+    async fn release(&self, id :&ObjectId) -> Result<(), ApiError>;
+}
 
-    Ok(Db { posts, users })
-}
\ No newline at end of file
+#[rocket::async_trait]
+pub trait ApiKeyRepository :Send + Sync {
+    async fn insert(&self, key :&ApiKeyStoreModel) -> Result<(), ApiError>;
+    // The user's keys, newest first.
+    async fn list(&self, user :&ObjectId) -> Result<Vec<ApiKeyStoreModel>, ApiError>;
+    async fn delete(&self, id :&ObjectId, user :&ObjectId) -> Result<Option<ApiKeyStoreModel>, ApiError>;
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError>;
+    // Finds an unexpired key by its digest and notes that it was used.
+    async fn authenticate(&self, key_hash :&str) -> Result<Option<ApiKeyStoreModel>, ApiError>;
+}
+
+#[rocket::async_trait]
+pub trait LoginAttemptRepository :Send + Sync {
+    // The counter, if it is locked out right now.
+    async fn find_locked(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError>;
+    // Counts a failure, creating the counter if there's none yet. Returns the counter afterwards.
+    async fn record_failure(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError>;
+    async fn lock(&self, id :&ObjectId, until :DateTime) -> Result<(), ApiError>;
+    async fn clear(&self, kind :LoginAttemptKind, subject :&str) -> Result<(), ApiError>;
+    // Most recent failures first.
+    async fn list(&self, locked_only :bool) -> Result<Vec<LoginAttemptStoreModel>, ApiError>;
+    async fn delete(&self, id :&ObjectId) -> Result<Option<LoginAttemptStoreModel>, ApiError>;
+}
@@ -0,0 +1,47 @@
+use mongodb::{bson::{doc, oid::ObjectId, DateTime}, Collection, options::FindOptions};
+
+use crate::{errors::ApiError, db::ApiKeyRepository, models::api_key::ApiKeyStoreModel};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl ApiKeyRepository for Collection<ApiKeyStoreModel> {
+    async fn insert(&self, key :&ApiKeyStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(key, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, user :&ObjectId) -> Result<Vec<ApiKeyStoreModel>, ApiError> {
+        find_all(self, doc!{"user": user}, FindOptions::builder().sort(doc!{"created": -1}).build()).await
+    }
+
+    async fn delete(&self, id :&ObjectId, user :&ObjectId) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        match self.find_one_and_delete(doc!{"_id": id, "user": user}, None).await {
+            Ok(maybe_key) => Ok(maybe_key),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"user": user}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn authenticate(&self, key_hash :&str) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        match self.find_one_and_update(
+            doc!{
+                "key_hash": key_hash,
+                "$or": [{"expires": null}, {"expires": {"$gt": DateTime::now()}}]
+            },
+            doc!{"$set": {"last_used": DateTime::now()}},
+            None
+        ).await {
+            Ok(maybe_key) => Ok(maybe_key),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
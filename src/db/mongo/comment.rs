@@ -0,0 +1,57 @@
+use mongodb::{bson::{doc, oid::ObjectId}, Collection, options::FindOptions};
+
+use crate::{errors::ApiError, db::CommentRepository, models::comment::CommentStoreModel};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl CommentRepository for Collection<CommentStoreModel> {
+    async fn find(&self, id :&ObjectId, post :&ObjectId) -> Result<Option<CommentStoreModel>, ApiError> {
+        match self.find_one(doc!{"_id": id, "post": post}, None).await {
+            Ok(maybe_comment) => Ok(maybe_comment),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<CommentStoreModel>, ApiError> {
+        find_all(self, doc!{"post": post}, FindOptions::builder().sort(doc!{"created": 1}).build()).await
+    }
+
+    async fn insert(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(comment, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn replace(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        match self.replace_one(doc!{"_id": &comment._id}, comment, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_post(&self, post :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"post": post}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn ids_by_author_or_posts(&self, author :&ObjectId, posts :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        let comments = find_all(self, doc!{"$or": [{"author": author}, {"post": {"$in": posts}}]}, None).await?;
+        Ok(comments.into_iter().map(|comment| comment._id).collect())
+    }
+
+    async fn reply_ids(&self, parents :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        let comments = find_all(self, doc!{"parent": {"$in": parents}}, None).await?;
+        Ok(comments.into_iter().map(|comment| comment._id).collect())
+    }
+
+    async fn delete(&self, ids :&[ObjectId]) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"_id": {"$in": ids}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
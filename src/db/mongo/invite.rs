@@ -0,0 +1,48 @@
+use mongodb::{bson::{doc, oid::ObjectId, DateTime}, Collection, options::{FindOptions, FindOneAndUpdateOptions, ReturnDocument}};
+
+use crate::{errors::ApiError, db::InviteRepository, models::invite::InviteStoreModel};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl InviteRepository for Collection<InviteStoreModel> {
+    async fn insert(&self, invite :&InviteStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(invite, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<InviteStoreModel>, ApiError> {
+        find_all(self, doc!{}, FindOptions::builder().sort(doc!{"created": -1}).build()).await
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<InviteStoreModel>, ApiError> {
+        match self.find_one_and_delete(doc!{"_id": id}, None).await {
+            Ok(maybe_invite) => Ok(maybe_invite),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn redeem(&self, code_hash :&str) -> Result<Option<InviteStoreModel>, ApiError> {
+        match self.find_one_and_update(
+            doc!{
+                "code_hash": code_hash,
+                "expires": {"$gt": DateTime::now()},
+                "$expr": {"$lt": ["$uses", "$max_uses"]}
+            },
+            doc!{"$inc": {"uses": 1}},
+            FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build()
+        ).await {
+            Ok(maybe_invite) => Ok(maybe_invite),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn release(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id, "uses": {"$gt": 0}}, doc!{"$inc": {"uses": -1}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use mongodb::{bson::{doc, oid::ObjectId, DateTime}, Collection, options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument}};
+
+use crate::{errors::ApiError, db::LoginAttemptRepository, models::login_attempt::{LoginAttemptStoreModel, LoginAttemptKind}};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl LoginAttemptRepository for Collection<LoginAttemptStoreModel> {
+    async fn find_locked(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        match self.find_one(doc!{"kind": kind, "subject": subject, "locked_until": {"$gt": DateTime::now()}}, None).await {
+            Ok(maybe_attempt) => Ok(maybe_attempt),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn record_failure(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        match self.find_one_and_update(
+            doc!{"kind": kind, "subject": subject},
+            doc!{"$inc": {"failures": 1}, "$set": {"last_failure": DateTime::now()}},
+            FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build()
+        ).await {
+            Ok(maybe_attempt) => Ok(maybe_attempt),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn lock(&self, id :&ObjectId, until :DateTime) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id}, doc!{"$set": {"locked_until": until}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn clear(&self, kind :LoginAttemptKind, subject :&str) -> Result<(), ApiError> {
+        match self.delete_one(doc!{"kind": kind, "subject": subject}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, locked_only :bool) -> Result<Vec<LoginAttemptStoreModel>, ApiError> {
+        let filter = match locked_only {
+            true => doc!{"locked_until": {"$gt": DateTime::now()}},
+            false => doc!{}
+        };
+
+        find_all(self, filter, FindOptions::builder().sort(doc!{"last_failure": -1}).build()).await
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        match self.find_one_and_delete(doc!{"_id": id}, None).await {
+            Ok(maybe_attempt) => Ok(maybe_attempt),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use mongodb::{Database, IndexModel, error::Error, options::IndexOptions, bson::{doc, DateTime, Document}};
+use rocket::futures::future::BoxFuture;
+
+use crate::{errors::ApiError, db::LOGIN_ATTEMPT_TTL, models::{post::PostStoreModel, revision::RevisionStoreModel}};
+
+use super::internal;
+
+type Migration = for<'a> fn(&'a Database) -> BoxFuture<'a, Result<(), ApiError>>;
+
+// Applied in order, each one exactly once, and recorded in `_migrations`. Migrations that were released
+// must never change, changes to indexes or documents go into a new one.
+const MIGRATIONS :&[(&str, Migration)] = &[
+    ("create indexes", |db| Box::pin(create_indexes(db))),
+    ("backfill posts", |db| Box::pin(backfill_posts(db))),
+    ("unique user names and post titles", |db| Box::pin(unique_names(db)))
+];
+
+pub async fn migrate(db :&Database) -> Result<(), String> {
+    let applied = db.collection::<Document>("_migrations");
+
+    let done = match applied.count_documents(doc!{}, None).await {
+        Ok(done) => done as usize,
+        Err(e) => return Err(e.to_string())
+    };
+
+    for (version, (name, migration)) in MIGRATIONS.iter().enumerate().skip(done) {
+        let version = version + 1;
+
+        if let Err(e) = migration(db).await {
+            return Err(format!("Migration {} ({}) failed: {}", version, name, e.message()))
+        }
+
+        // Fails on the `_id` if another instance applied the migration in the meantime.
+        if let Err(e) = applied.insert_one(doc!{"_id": version as i64, "name": *name, "applied": DateTime::now()}, None).await {
+            return Err(e.to_string())
+        }
+
+        info!("Applied migration {}: {}.", version, name);
+    }
+
+    Ok(())
+}
+
+async fn create_indexes(db :&Database) -> Result<(), ApiError> {
+    indexes(db).await.map_err(internal)
+}
+
+async fn indexes(db :&Database) -> Result<(), Error> {
+    let posts = db.collection::<Document>("Post");
+    let users = db.collection::<Document>("User");
+    let comments = db.collection::<Document>("Comment");
+    let revisions = db.collection::<Document>("Revision");
+    let roles = db.collection::<Document>("Role");
+    let invites = db.collection::<Document>("Invite");
+    let api_keys = db.collection::<Document>("ApiKey");
+    let login_attempts = db.collection::<Document>("LoginAttempt");
+
+    // Indexes backing the sort orders and filters of the list endpoints
+    posts.create_index(IndexModel::builder().keys(doc!{"title": 1, "_id": 1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"author": 1, "_id": 1}).build(), None).await?;
+    // Posts without a slug yet are left out, so that they can still be backfilled.
+    posts.create_index(IndexModel::builder().keys(doc!{"slug": 1}).options(
+        IndexOptions::builder().unique(true).partial_filter_expression(doc!{"slug": {"$type": "string"}}).build()
+    ).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"slug_history": 1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"status": 1, "publish_at": 1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"tags": 1, "_id": 1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"category": 1, "_id": 1}).build(), None).await?;
+    posts.create_index(IndexModel::builder().keys(doc!{"title": "text", "content": "text"}).options(
+        IndexOptions::builder().weights(doc!{"title": 5, "content": 1}).name("search".to_string()).build()
+    ).build(), None).await?;
+    users.create_index(IndexModel::builder().keys(doc!{"name": 1, "_id": 1}).build(), None).await?;
+    users.create_index(IndexModel::builder().keys(doc!{"email": 1}).options(
+        IndexOptions::builder().unique(true).partial_filter_expression(doc!{"email": {"$type": "string"}}).build()
+    ).build(), None).await?;
+    comments.create_index(IndexModel::builder().keys(doc!{"post": 1, "created": 1}).build(), None).await?;
+    revisions.create_index(IndexModel::builder().keys(doc!{"post": 1, "number": 1}).options(IndexOptions::builder().unique(true).build()).build(), None).await?;
+
+    roles.create_index(IndexModel::builder().keys(doc!{"name": 1}).options(IndexOptions::builder().unique(true).build()).build(), None).await?;
+
+    invites.create_index(IndexModel::builder().keys(doc!{"code_hash": 1}).options(IndexOptions::builder().unique(true).build()).build(), None).await?;
+
+    api_keys.create_index(IndexModel::builder().keys(doc!{"key_hash": 1}).options(IndexOptions::builder().unique(true).build()).build(), None).await?;
+    api_keys.create_index(IndexModel::builder().keys(doc!{"user": 1, "created": -1}).build(), None).await?;
+
+    login_attempts.create_index(IndexModel::builder().keys(doc!{"kind": 1, "subject": 1}).options(IndexOptions::builder().unique(true).build()).build(), None).await?;
+    login_attempts.create_index(IndexModel::builder().keys(doc!{"last_failure": 1}).options(
+        IndexOptions::builder().expire_after(Duration::from_secs(LOGIN_ATTEMPT_TTL)).build()
+    ).build(), None).await?;
+
+    Ok(())
+}
+
+// Fills in the fields posts stored by earlier versions lack, see `PostStoreModel::backfill`.
+async fn backfill_posts(db :&Database) -> Result<(), ApiError> {
+    let posts = db.collection::<PostStoreModel>("Post");
+    let revisions = db.collection::<RevisionStoreModel>("Revision");
+
+    PostStoreModel::backfill(&posts, &revisions).await
+}
+
+// Names and titles used to be checked for before every insert only, which two requests at once could both pass.
+// Fails if duplicates made it in before, those have to be renamed by hand.
+async fn unique_names(db :&Database) -> Result<(), ApiError> {
+    let users = db.collection::<Document>("User");
+    let posts = db.collection::<Document>("Post");
+
+    if let Err(e) = users.create_index(IndexModel::builder().keys(doc!{"name": 1}).options(
+        IndexOptions::builder().unique(true).name("name_unique".to_string()).build()
+    ).build(), None).await {
+        return Err(internal(e))
+    }
+
+    match posts.create_index(IndexModel::builder().keys(doc!{"title": 1}).options(
+        IndexOptions::builder().unique(true).name("title_unique".to_string()).build()
+    ).build(), None).await {
+        Ok(_ok) => Ok(()),
+        Err(e) => Err(internal(e))
+    }
+}
@@ -0,0 +1,131 @@
+mod post;
+mod user;
+mod session;
+mod comment;
+mod revision;
+mod role;
+mod invite;
+mod api_key;
+mod login_attempt;
+mod migrations;
+
+use std::sync::Arc;
+
+use mongodb::{Client, options::{ClientOptions, FindOptions}, error::{Error, ErrorKind, WriteFailure}, Collection, bson::{doc, Bson, Document}};
+use rocket::{serde::de::DeserializeOwned, futures::TryStreamExt};
+
+use crate::{errors::ApiError, models::{post::PostStoreModel, user::UserStoreModel, session::SessionStoreModel,
+    comment::CommentStoreModel, revision::RevisionStoreModel, role::RoleStoreModel, invite::InviteStoreModel, api_key::ApiKeyStoreModel,
+    login_attempt::LoginAttemptStoreModel, page::PageRequest}};
+
+use super::Db;
+
+pub async fn connect(uri :&str, db :&str) -> Result<Db, String> {
+    let opts = ClientOptions::parse(uri).await.map_err(|e| e.to_string())?;
+    let client = Client::with_options(opts).map_err(|e| e.to_string())?;
+    let db = client.database(db);
+
+    let posts = db.collection::<PostStoreModel>("Post");
+    let users = db.collection::<UserStoreModel>("User");
+    let sessions = db.collection::<SessionStoreModel>("Session");
+    let comments = db.collection::<CommentStoreModel>("Comment");
+    let revisions = db.collection::<RevisionStoreModel>("Revision");
+    let roles = db.collection::<RoleStoreModel>("Role");
+    let invites = db.collection::<InviteStoreModel>("Invite");
+    let api_keys = db.collection::<ApiKeyStoreModel>("ApiKey");
+    let login_attempts = db.collection::<LoginAttemptStoreModel>("LoginAttempt");
+
+    migrations::migrate(&db).await?;
+
+    Ok(Db {
+        posts: Arc::new(posts),
+        users: Arc::new(users),
+        sessions: Arc::new(sessions),
+        comments: Arc::new(comments),
+        revisions: Arc::new(revisions),
+        roles: Arc::new(roles),
+        invites: Arc::new(invites),
+        api_keys: Arc::new(api_keys),
+        login_attempts: Arc::new(login_attempts)
+    })
+}
+
+fn internal(e :Error) -> ApiError {
+    ApiError::from(e)
+}
+
+const DUPLICATE_KEY :i32 = 11000;
+
+// Unique indexes back up the checks the routes make before writing, which concurrent requests can race past.
+fn write_error(e :Error) -> ApiError {
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(failure)) if failure.code == DUPLICATE_KEY => {
+            ApiError::Conflict("Duplicate key.".to_string())
+        },
+        _ => internal(e)
+    }
+}
+
+async fn find_all<T>(collection :&Collection<T>, filter :Document, options :impl Into<Option<FindOptions>>) -> Result<Vec<T>, ApiError>
+where T :DeserializeOwned + Unpin + Send + Sync {
+    let mut results = match collection.find(filter, options).await {
+        Ok(results) => results,
+        Err(e) => return Err(internal(e))
+    };
+
+    let mut items :Vec<T> = vec![];
+    while let Ok(Some(i)) = results.try_next().await {
+        items.push(i);
+    }
+
+    Ok(items)
+}
+
+// Restricts the filter to documents that come after the page position in its sort order.
+fn after(filter :Document, page :&PageRequest) -> Document {
+    let position = match &page.after {
+        Some(position) => position,
+        None => return filter
+    };
+    let op = if page.descending { "$lt" } else { "$gt" };
+
+    // Nulls sort before any other value, and `$gt`/`$lt` only ever match values of the same type,
+    // so they are stepped over explicitly.
+    let position = match (page.field, &position.value, page.descending) {
+        ("_id", _, _) => doc!{"_id": {op: &position.id}},
+        (field, Bson::Null, false) => doc!{"$or": [
+            {field: {"$ne": Bson::Null}},
+            {field: Bson::Null, "_id": {op: &position.id}}
+        ]},
+        (field, Bson::Null, true) => doc!{field: Bson::Null, "_id": {op: &position.id}},
+        (field, value, false) => doc!{"$or": [
+            {field: {op: value}},
+            {field: value, "_id": {op: &position.id}}
+        ]},
+        (field, value, true) => doc!{"$or": [
+            {field: {op: value}},
+            {field: value, "_id": {op: &position.id}},
+            {field: Bson::Null}
+        ]}
+    };
+
+    doc!{"$and": [filter, position]}
+}
+
+// Fetches the page of documents matching the filter, along with the total number of matching documents.
+async fn find_page<T>(collection :&Collection<T>, filter :Document, page :&PageRequest) -> Result<(Vec<T>, u64), ApiError>
+where T :DeserializeOwned + Unpin + Send + Sync {
+    let total = match collection.count_documents(filter.clone(), None).await {
+        Ok(total) => total,
+        Err(e) => return Err(internal(e))
+    };
+
+    let order = if page.descending { -1 } else { 1 };
+    let sort = match page.field {
+        "_id" => doc!{"_id": order},
+        field => doc!{field: order, "_id": order}
+    };
+
+    let options = FindOptions::builder().sort(sort).limit(page.limit as i64).build();
+    Ok((find_all(collection, after(filter, page), options).await?, total))
+}
@@ -0,0 +1,255 @@
+use mongodb::{bson::{self, doc, oid::ObjectId, Bson, DateTime, Document}, Collection, options::{FindOptions, FindOneOptions}};
+use rocket::futures::TryStreamExt;
+
+use crate::{errors::ApiError, db::PostRepository, models::{post::{PostStoreModel, PostFilter, PostVisibility, PostStatus},
+    page::PageRequest, search::PostSearchHit, tag::TagReadModel}};
+
+use super::{internal, write_error, find_all, find_page};
+
+fn filter(filter :&PostFilter) -> Document {
+    let mut conditions = vec![];
+
+    match &filter.visibility {
+        PostVisibility::Published => conditions.push(doc!{"status": PostStatus::Published}),
+        PostVisibility::PublishedOrAuthor(author) => conditions.push(doc!{"$or": [{"status": PostStatus::Published}, {"author": author}]}),
+        PostVisibility::All => ()
+    }
+
+    match filter.status {
+        Some(status) => conditions.push(doc!{"status": status}),
+        None => conditions.push(doc!{"status": {"$ne": PostStatus::Archived}})
+    }
+
+    if let Some(author) = &filter.author {
+        conditions.push(doc!{"author": author});
+    }
+
+    if let Some(tag) = &filter.tag {
+        conditions.push(doc!{"tags": tag});
+    }
+
+    if let Some(category) = &filter.category {
+        conditions.push(doc!{"category": category});
+    }
+
+    doc!{"$and": conditions}
+}
+
+#[rocket::async_trait]
+impl PostRepository for Collection<PostStoreModel> {
+    async fn find(&self, key :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        let mut filter = vec![doc!{"slug": key}, doc!{"slug_history": key}];
+        if let Ok(id) = ObjectId::parse_str(key) {
+            filter.push(doc!{"_id": id});
+        }
+
+        match self.find_one(doc!{"$or": filter}, None).await {
+            Ok(maybe_post) => Ok(maybe_post),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_title(&self, title :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        match self.find_one(doc!{"title": title}, None).await {
+            Ok(maybe_post) => Ok(maybe_post),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn slug_taken(&self, slug :&str, except :&ObjectId) -> Result<bool, ApiError> {
+        let taken = doc!{
+            "$or": [{"slug": slug}, {"slug_history": slug}],
+            "_id": {"$ne": except}
+        };
+
+        match self.count_documents(taken, None).await {
+            Ok(count) => Ok(count > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn insert(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(post, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(write_error(e))
+        }
+    }
+
+    async fn replace(&self, post :&PostStoreModel) -> Result<bool, ApiError> {
+        match self.replace_one(doc!{"_id": &post._id, "revision": post.revision - 1}, post, None).await {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(e) => Err(write_error(e))
+        }
+    }
+
+    async fn set_status(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": &post._id}, doc!{"$set": {"status": post.status, "publish_at": post.publish_at, "updated": post.updated}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_one(doc!{"_id": id}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn ids_by_author(&self, author :&ObjectId) -> Result<Vec<ObjectId>, ApiError> {
+        Ok(find_all(self, doc!{"author": author}, None).await?.into_iter().map(|post| post._id).collect())
+    }
+
+    async fn delete_by_author(&self, author :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"author": author}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn page(&self, posts :&PostFilter, page :&PageRequest) -> Result<(Vec<PostStoreModel>, u64), ApiError> {
+        find_page(self, filter(posts), page).await
+    }
+
+    async fn search(&self, q :&str, posts :&PostFilter, skip :u64, limit :u32) -> Result<(Vec<PostSearchHit>, u64), ApiError> {
+        let filter = doc!{"$and": [{"$text": {"$search": q}}, filter(posts)]};
+
+        let total = match self.count_documents(filter.clone(), None).await {
+            Ok(total) => total,
+            Err(e) => return Err(internal(e))
+        };
+
+        let options = FindOptions::builder()
+            .projection(doc!{"score": {"$meta": "textScore"}})
+            .sort(doc!{"score": {"$meta": "textScore"}, "_id": -1})
+            .skip(skip)
+            .limit(limit as i64)
+            .build();
+
+        // The relevance score isn't part of the post itself, so the raw documents are read first.
+        let mut results = match self.clone_with_type::<Document>().find(filter, options).await {
+            Ok(results) => results,
+            Err(e) => return Err(internal(e))
+        };
+
+        let mut hits :Vec<PostSearchHit> = vec![];
+        while let Ok(Some(mut i)) = results.try_next().await {
+            let score = match i.remove("score") {
+                Some(score) => score.as_f64().unwrap_or_default(),
+                None => 0.0
+            };
+
+            match bson::from_document(i) {
+                Ok(post) => hits.push(PostSearchHit { post, score }),
+                Err(e) => return Err(ApiError::Internal(e.to_string()))
+            }
+        }
+
+        Ok((hits, total))
+    }
+
+    async fn latest(&self, posts :&PostFilter, limit :u32) -> Result<Vec<PostStoreModel>, ApiError> {
+        let options = FindOptions::builder().sort(doc!{"publish_at": -1, "_id": -1}).limit(limit as i64).build();
+        find_all(self, filter(posts), options).await
+    }
+
+    async fn tag_counts(&self, posts :&PostFilter) -> Result<Vec<TagReadModel>, ApiError> {
+        let pipeline = vec![
+            doc!{"$match": filter(posts)},
+            doc!{"$unwind": "$tags"},
+            doc!{"$group": {"_id": "$tags", "posts": {"$sum": 1}}},
+            doc!{"$sort": {"posts": -1, "_id": 1}},
+            doc!{"$project": {"_id": 0, "name": "$_id", "posts": 1}}
+        ];
+
+        let mut results = match self.aggregate(pipeline, None).await {
+            Ok(results) => results,
+            Err(e) => return Err(internal(e))
+        };
+
+        let mut tags :Vec<TagReadModel> = vec![];
+        while let Ok(Some(i)) = results.try_next().await {
+            match bson::from_document(i) {
+                Ok(tag) => tags.push(tag),
+                Err(e) => return Err(ApiError::Internal(e.to_string()))
+            }
+        }
+
+        Ok(tags)
+    }
+
+    async fn count_tag(&self, tag :&str) -> Result<u64, ApiError> {
+        match self.count_documents(doc!{"tags": tag}, None).await {
+            Ok(count) => Ok(count),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn retag(&self, from :&str, to :&str) -> Result<u64, ApiError> {
+        if let Err(e) = self.update_many(doc!{"$and": [{"tags": from}, {"tags": to}]}, doc!{"$pull": {"tags": from}}, None).await {
+            return Err(internal(e))
+        }
+
+        match self.update_many(doc!{"tags": from}, doc!{"$set": {"tags.$": to}}, None).await {
+            Ok(result) => Ok(result.modified_count),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn publish_scheduled(&self) -> Result<Option<DateTime>, ApiError> {
+        let now = DateTime::now();
+
+        if let Err(e) = self.update_many(
+            doc!{"status": PostStatus::Scheduled, "publish_at": {"$lte": now}},
+            doc!{"$set": {"status": PostStatus::Published, "updated": now}},
+            None
+        ).await {
+            return Err(internal(e))
+        }
+
+        let next = FindOneOptions::builder().sort(doc!{"publish_at": 1}).build();
+        match self.find_one(doc!{"status": PostStatus::Scheduled}, next).await {
+            Ok(maybe_post) => Ok(maybe_post.and_then(|post| post.publish_at)),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    // Works on the raw documents, since posts missing the newer fields can't be read as posts.
+    async fn backfill(&self) -> Result<(), ApiError> {
+        let raw = self.clone_with_type::<Document>();
+
+        if let Err(e) = raw.update_many(
+            doc!{"status": {"$exists": false}},
+            doc!{"$set": {"status": PostStatus::Published, "publish_at": Bson::Null}},
+            None
+        ).await {
+            return Err(internal(e))
+        }
+
+        let mut results = match raw.find(doc!{"slug": {"$exists": false}}, None).await {
+            Ok(posts) => posts,
+            Err(e) => return Err(internal(e))
+        };
+
+        while let Ok(Some(post)) = results.try_next().await {
+            let (id, title) = match (post.get_object_id("_id"), post.get_str("title")) {
+                (Ok(id), Ok(title)) => (id, title),
+                _ => continue
+            };
+
+            let slug = PostStoreModel::unique_slug(self, title, &id).await?;
+            if let Err(e) = raw.update_one(doc!{"_id": id}, doc!{"$set": {"slug": slug, "slug_history": []}}, None).await {
+                return Err(internal(e))
+            }
+        }
+
+        match raw.update_many(doc!{"revision": {"$exists": false}}, doc!{"$set": {"revision": 0}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn unrevisioned(&self) -> Result<Vec<PostStoreModel>, ApiError> {
+        find_all(self, doc!{"revision": 0}, None).await
+    }
+}
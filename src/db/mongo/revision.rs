@@ -0,0 +1,33 @@
+use mongodb::{bson::{doc, oid::ObjectId}, Collection, options::FindOptions};
+
+use crate::{errors::ApiError, db::RevisionRepository, models::revision::RevisionStoreModel};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl RevisionRepository for Collection<RevisionStoreModel> {
+    async fn insert(&self, revision :&RevisionStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(revision, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find(&self, post :&ObjectId, number :u32) -> Result<Option<RevisionStoreModel>, ApiError> {
+        match self.find_one(doc!{"post": post, "number": number}, None).await {
+            Ok(maybe_revision) => Ok(maybe_revision),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<RevisionStoreModel>, ApiError> {
+        find_all(self, doc!{"post": post}, FindOptions::builder().sort(doc!{"number": -1}).build()).await
+    }
+
+    async fn delete_by_posts(&self, posts :&[ObjectId]) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"post": {"$in": posts}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use mongodb::{bson::{self, doc}, Collection, options::UpdateOptions};
+
+use crate::{errors::ApiError, db::RoleRepository, models::{role::{RoleStoreModel, Capability}, user::UserPermissionLevel}};
+
+use super::{internal, find_all};
+
+#[rocket::async_trait]
+impl RoleRepository for Collection<RoleStoreModel> {
+    async fn find(&self, role :UserPermissionLevel) -> Result<Option<RoleStoreModel>, ApiError> {
+        match self.find_one(doc!{"name": role}, None).await {
+            Ok(maybe_role) => Ok(maybe_role),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<RoleStoreModel>, ApiError> {
+        find_all(self, doc!{}, None).await
+    }
+
+    async fn insert_missing(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        let capabilities = match bson::to_bson(capabilities) {
+            Ok(capabilities) => capabilities,
+            Err(e) => return Err(ApiError::Internal(e.to_string()))
+        };
+
+        match self.update_one(
+            doc!{"name": role},
+            doc!{"$setOnInsert": {"capabilities": capabilities}},
+            UpdateOptions::builder().upsert(true).build()
+        ).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_capabilities(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        let capabilities = match bson::to_bson(capabilities) {
+            Ok(capabilities) => capabilities,
+            Err(e) => return Err(ApiError::Internal(e.to_string()))
+        };
+
+        match self.update_one(
+            doc!{"name": role},
+            doc!{"$set": {"capabilities": capabilities}},
+            UpdateOptions::builder().upsert(true).build()
+        ).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use mongodb::{bson::{doc, oid::ObjectId, DateTime}, Collection, options::{FindOneAndUpdateOptions, ReturnDocument}};
+
+use crate::{errors::ApiError, db::SessionRepository, models::session::SessionStoreModel};
+
+use super::internal;
+
+#[rocket::async_trait]
+impl SessionRepository for Collection<SessionStoreModel> {
+    async fn insert(&self, session :&SessionStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(session, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn is_active(&self, id :&ObjectId) -> Result<bool, ApiError> {
+        match self.find_one(doc!{"_id": id, "revoked": false, "expires": {"$gt": DateTime::now()}}, None).await {
+            Ok(maybe_session) => Ok(maybe_session.is_some()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn rotate(&self, presented_hash :&str, token_hash :&str, expires :DateTime) -> Result<Option<SessionStoreModel>, ApiError> {
+        match self.find_one_and_update(
+            doc!{"token_hash": presented_hash, "revoked": false, "expires": {"$gt": DateTime::now()}},
+            doc!{
+                "$set": {"token_hash": token_hash, "expires": expires},
+                "$push": {"used_hashes": presented_hash}
+            },
+            FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build()
+        ).await {
+            Ok(maybe_session) => Ok(maybe_session),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_reused(&self, presented_hash :&str) -> Result<bool, ApiError> {
+        match self.update_one(doc!{"used_hashes": presented_hash}, doc!{"$set": {"revoked": true}}, None).await {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id}, doc!{"$set": {"revoked": true}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        match self.update_many(doc!{"user": user}, doc!{"$set": {"revoked": true}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_others(&self, user :&ObjectId, keep :&ObjectId) -> Result<(), ApiError> {
+        match self.update_many(doc!{"user": user, "_id": {"$ne": keep}}, doc!{"$set": {"revoked": true}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_many(doc!{"user": user}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use mongodb::{bson::{self, doc, oid::ObjectId}, Collection};
+
+use crate::{errors::ApiError, db::UserRepository, models::{user::UserStoreModel, totp::TotpStoreModel, page::PageRequest}};
+
+use super::{internal, write_error, find_page};
+
+#[rocket::async_trait]
+impl UserRepository for Collection<UserStoreModel> {
+    async fn find(&self, id :&ObjectId) -> Result<Option<UserStoreModel>, ApiError> {
+        match self.find_one(doc!{"_id": id}, None).await {
+            Ok(maybe_user) => Ok(maybe_user),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_name(&self, name :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        match self.find_one(doc!{"name": name}, None).await {
+            Ok(maybe_user) => Ok(maybe_user),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_email(&self, email :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        match self.find_one(doc!{"email": email}, None).await {
+            Ok(maybe_user) => Ok(maybe_user),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn page(&self, page :&PageRequest) -> Result<(Vec<UserStoreModel>, u64), ApiError> {
+        find_page(self, doc!{}, page).await
+    }
+
+    async fn insert(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        match self.insert_one(user, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(write_error(e))
+        }
+    }
+
+    async fn replace(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        match self.replace_one(doc!{"_id": &user._id}, user, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(write_error(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match self.delete_one(doc!{"_id": id}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_password_hash(&self, id :&ObjectId, password_hash :&str) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id}, doc!{"$set": {"password_hash": password_hash}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn change_password_hash(&self, id :&ObjectId, from :&str, to :&str) -> Result<bool, ApiError> {
+        match self.update_one(doc!{"_id": id, "password_hash": from}, doc!{"$set": {"password_hash": to}}, None).await {
+            Ok(result) => Ok(result.modified_count > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn verify_email(&self, id :&ObjectId, email :Option<&str>) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id, "email": email}, doc!{"$set": {"email_verified": true}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_totp(&self, id :&ObjectId, totp :Option<&TotpStoreModel>) -> Result<(), ApiError> {
+        let totp = match bson::to_bson(&totp) {
+            Ok(totp) => totp,
+            Err(e) => return Err(ApiError::Internal(e.to_string()))
+        };
+
+        match self.update_one(doc!{"_id": id}, doc!{"$set": {"totp": totp}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn advance_totp_step(&self, id :&ObjectId, from :i64, to :i64) -> Result<bool, ApiError> {
+        match self.update_one(doc!{"_id": id, "totp.last_step": from}, doc!{"$set": {"totp.last_step": to}}, None).await {
+            Ok(result) => Ok(result.modified_count > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn use_recovery_code(&self, id :&ObjectId, digest :&str) -> Result<bool, ApiError> {
+        match self.update_one(doc!{"_id": id, "totp.recovery_codes": digest}, doc!{"$pull": {"totp.recovery_codes": digest}}, None).await {
+            Ok(result) => Ok(result.modified_count > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_recovery_codes(&self, id :&ObjectId, digests :&[String]) -> Result<(), ApiError> {
+        match self.update_one(doc!{"_id": id, "totp.enabled": true}, doc!{"$set": {"totp.recovery_codes": digests}}, None).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::ApiKeyRepository, models::api_key::ApiKeyStoreModel};
+
+use super::{SqlStore, query, internal, id, text, flag, datetime, maybe_datetime, to_json, json};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, user_id, name, key_hash, prefix, capabilities, mfa, CAST(created AS TEXT) AS created,
+    CAST(expires AS TEXT) AS expires, CAST(last_used AS TEXT) AS last_used";
+
+fn key(row :&AnyRow) -> Result<ApiKeyStoreModel, ApiError> {
+    Ok(ApiKeyStoreModel {
+        _id: id(row, "id")?,
+        user: id(row, "user_id")?,
+        name: text(row, "name")?,
+        key_hash: text(row, "key_hash")?,
+        prefix: text(row, "prefix")?,
+        capabilities: json(row, "capabilities")?,
+        mfa: flag(row, "mfa")?,
+        created: datetime(row, "created")?,
+        expires: maybe_datetime(row, "expires")?,
+        last_used: maybe_datetime(row, "last_used")?
+    })
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for SqlStore<ApiKeyStoreModel> {
+    async fn insert(&self, key :&ApiKeyStoreModel) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO api_keys (id, user_id, name, key_hash, prefix, capabilities, mfa, created, expires, last_used)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, CAST($9 AS BIGINT), CAST($10 AS BIGINT))",
+            &[
+                (&key._id).into(), (&key.user).into(), (&key.name).into(), (&key.key_hash).into(), (&key.prefix).into(),
+                to_json(&key.capabilities)?.into(), key.mfa.into(), key.created.into(), key.expires.into(), key.last_used.into()
+            ]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, user :&ObjectId) -> Result<Vec<ApiKeyStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM api_keys WHERE user_id = $1 ORDER BY api_keys.created DESC", COLUMNS), &[user.into()]).fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(key).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId, user :&ObjectId) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        match query(&format!("DELETE FROM api_keys WHERE id = $1 AND user_id = $2 RETURNING {}", COLUMNS), &[id.into(), user.into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(key(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        match query("DELETE FROM api_keys WHERE user_id = $1", &[user.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    // Returns the key as it was before this use, like the other backends do.
+    async fn authenticate(&self, key_hash :&str) -> Result<Option<ApiKeyStoreModel>, ApiError> {
+        let now = DateTime::now();
+
+        let used = match query(
+            &format!("SELECT {} FROM api_keys WHERE key_hash = $1 AND (expires IS NULL OR expires > $2)", COLUMNS),
+            &[key_hash.into(), now.into()]
+        ).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => key(&row)?,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(internal(e))
+        };
+
+        match query("UPDATE api_keys SET last_used = $2 WHERE id = $1", &[(&used._id).into(), now.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(Some(used)),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use mongodb::bson::oid::ObjectId;
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::CommentRepository, models::comment::CommentStoreModel};
+
+use super::{SqlStore, query, params, internal, id, maybe_id, text, datetime, maybe_datetime};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, post, parent, content, author, CAST(created AS TEXT) AS created, CAST(edited AS TEXT) AS edited";
+
+fn comment(row :&AnyRow) -> Result<CommentStoreModel, ApiError> {
+    Ok(CommentStoreModel {
+        _id: id(row, "id")?,
+        post: id(row, "post")?,
+        parent: maybe_id(row, "parent")?,
+        content: text(row, "content")?,
+        author: id(row, "author")?,
+        created: datetime(row, "created")?,
+        edited: maybe_datetime(row, "edited")?
+    })
+}
+
+fn ids(rows :Vec<AnyRow>) -> Result<Vec<ObjectId>, ApiError> {
+    rows.iter().map(|row| id(row, "id")).collect()
+}
+
+#[rocket::async_trait]
+impl CommentRepository for SqlStore<CommentStoreModel> {
+    async fn find(&self, id :&ObjectId, post :&ObjectId) -> Result<Option<CommentStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM comments WHERE id = $1 AND post = $2", COLUMNS), &[id.into(), post.into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(comment(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<CommentStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM comments WHERE post = $1 ORDER BY comments.created, id", COLUMNS), &[post.into()]).fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(comment).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn insert(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO comments (id, post, parent, content, author, created, edited) VALUES ($1, $2, CAST($3 AS TEXT), $4, $5, $6, CAST($7 AS BIGINT))",
+            &[
+                (&comment._id).into(), (&comment.post).into(), comment.parent.into(), (&comment.content).into(),
+                (&comment.author).into(), comment.created.into(), comment.edited.into()
+            ]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn replace(&self, comment :&CommentStoreModel) -> Result<(), ApiError> {
+        match query(
+            "UPDATE comments SET post = $2, parent = CAST($3 AS TEXT), content = $4, author = $5, created = $6, edited = CAST($7 AS BIGINT) WHERE id = $1",
+            &[
+                (&comment._id).into(), (&comment.post).into(), comment.parent.into(), (&comment.content).into(),
+                (&comment.author).into(), comment.created.into(), comment.edited.into()
+            ]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_post(&self, post :&ObjectId) -> Result<(), ApiError> {
+        match query("DELETE FROM comments WHERE post = $1", &[post.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn ids_by_author_or_posts(&self, author :&ObjectId, posts :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        let mut values = vec![author.into()];
+        let select = format!("SELECT id FROM comments WHERE author = $1 OR post IN {}", params(&mut values, posts));
+
+        match query(&select, &values).fetch_all(&self.pool).await {
+            Ok(rows) => ids(rows),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn reply_ids(&self, parents :&[ObjectId]) -> Result<Vec<ObjectId>, ApiError> {
+        let mut values = vec![];
+        let select = format!("SELECT id FROM comments WHERE parent IN {}", params(&mut values, parents));
+
+        match query(&select, &values).fetch_all(&self.pool).await {
+            Ok(rows) => ids(rows),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, ids :&[ObjectId]) -> Result<(), ApiError> {
+        let mut values = vec![];
+        let delete = format!("DELETE FROM comments WHERE id IN {}", params(&mut values, ids));
+
+        match query(&delete, &values).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::InviteRepository, models::invite::InviteStoreModel};
+
+use super::{SqlStore, query, internal, id, text, int, datetime};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, code_hash, created_by, CAST(created AS TEXT) AS created, CAST(expires AS TEXT) AS expires, max_uses, uses";
+
+fn invite(row :&AnyRow) -> Result<InviteStoreModel, ApiError> {
+    Ok(InviteStoreModel {
+        _id: id(row, "id")?,
+        code_hash: text(row, "code_hash")?,
+        created_by: id(row, "created_by")?,
+        created: datetime(row, "created")?,
+        expires: datetime(row, "expires")?,
+        max_uses: int(row, "max_uses")? as u32,
+        uses: int(row, "uses")? as u32
+    })
+}
+
+#[rocket::async_trait]
+impl InviteRepository for SqlStore<InviteStoreModel> {
+    async fn insert(&self, invite :&InviteStoreModel) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO invites (id, code_hash, created_by, created, expires, max_uses, uses) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[
+                (&invite._id).into(), (&invite.code_hash).into(), (&invite.created_by).into(), invite.created.into(),
+                invite.expires.into(), invite.max_uses.into(), invite.uses.into()
+            ]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<InviteStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM invites ORDER BY invites.created DESC", COLUMNS), &[]).fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(invite).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<InviteStoreModel>, ApiError> {
+        match query(&format!("DELETE FROM invites WHERE id = $1 RETURNING {}", COLUMNS), &[id.into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(invite(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn redeem(&self, code_hash :&str) -> Result<Option<InviteStoreModel>, ApiError> {
+        match query(
+            &format!("UPDATE invites SET uses = uses + 1 WHERE code_hash = $1 AND expires > $2 AND uses < max_uses RETURNING {}", COLUMNS),
+            &[code_hash.into(), DateTime::now().into()]
+        ).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(invite(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn release(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match query("UPDATE invites SET uses = uses - 1 WHERE id = $1 AND uses > 0", &[id.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
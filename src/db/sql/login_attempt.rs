@@ -0,0 +1,100 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::{LoginAttemptRepository, LOGIN_ATTEMPT_TTL}, models::login_attempt::{LoginAttemptStoreModel, LoginAttemptKind}};
+
+use super::{SqlStore, query, internal, id, text, int, datetime, maybe_datetime, name, named};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, kind, subject, failures, CAST(last_failure AS TEXT) AS last_failure, CAST(locked_until AS TEXT) AS locked_until";
+
+fn attempt(row :&AnyRow) -> Result<LoginAttemptStoreModel, ApiError> {
+    Ok(LoginAttemptStoreModel {
+        _id: id(row, "id")?,
+        kind: named(row, "kind")?,
+        subject: text(row, "subject")?,
+        failures: int(row, "failures")? as u32,
+        last_failure: datetime(row, "last_failure")?,
+        locked_until: maybe_datetime(row, "locked_until")?
+    })
+}
+
+impl SqlStore<LoginAttemptStoreModel> {
+    // Drops the counters the TTL index would have expired.
+    async fn prune(&self) -> Result<(), ApiError> {
+        let expired = DateTime::now().timestamp_millis() - LOGIN_ATTEMPT_TTL as i64 * 1000;
+
+        match query("DELETE FROM login_attempts WHERE last_failure <= $1", &[expired.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl LoginAttemptRepository for SqlStore<LoginAttemptStoreModel> {
+    async fn find_locked(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        self.prune().await?;
+
+        match query(
+            &format!("SELECT {} FROM login_attempts WHERE kind = $1 AND subject = $2 AND locked_until > $3", COLUMNS),
+            &[name(&kind).into(), subject.into(), DateTime::now().into()]
+        ).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(attempt(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn record_failure(&self, kind :LoginAttemptKind, subject :&str) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        self.prune().await?;
+
+        match query(
+            &format!("INSERT INTO login_attempts (id, kind, subject, failures, last_failure) VALUES ($1, $2, $3, 1, $4)
+            ON CONFLICT (kind, subject) DO UPDATE SET failures = login_attempts.failures + 1, last_failure = excluded.last_failure
+            RETURNING {}", COLUMNS),
+            &[ObjectId::new().into(), name(&kind).into(), subject.into(), DateTime::now().into()]
+        ).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(attempt(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn lock(&self, id :&ObjectId, until :DateTime) -> Result<(), ApiError> {
+        match query("UPDATE login_attempts SET locked_until = $2 WHERE id = $1", &[id.into(), until.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn clear(&self, kind :LoginAttemptKind, subject :&str) -> Result<(), ApiError> {
+        match query("DELETE FROM login_attempts WHERE kind = $1 AND subject = $2", &[name(&kind).into(), subject.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, locked_only :bool) -> Result<Vec<LoginAttemptStoreModel>, ApiError> {
+        self.prune().await?;
+
+        let rows = match locked_only {
+            true => query(&format!("SELECT {} FROM login_attempts WHERE locked_until > $1 ORDER BY login_attempts.last_failure DESC", COLUMNS), &[DateTime::now().into()])
+                .fetch_all(&self.pool).await,
+            false => query(&format!("SELECT {} FROM login_attempts ORDER BY login_attempts.last_failure DESC", COLUMNS), &[]).fetch_all(&self.pool).await
+        };
+
+        match rows {
+            Ok(rows) => rows.iter().map(attempt).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<Option<LoginAttemptStoreModel>, ApiError> {
+        match query(&format!("DELETE FROM login_attempts WHERE id = $1 RETURNING {}", COLUMNS), &[id.into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(attempt(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
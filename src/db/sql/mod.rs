@@ -0,0 +1,505 @@
+mod post;
+mod user;
+mod session;
+mod comment;
+mod revision;
+mod role;
+mod invite;
+mod api_key;
+mod login_attempt;
+
+use std::{marker::PhantomData, sync::Arc};
+
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use rocket::serde::{Serialize, de::DeserializeOwned, json::{Value, serde_json}};
+use sqlx::{any::{AnyPoolOptions, AnyRow, AnyArguments}, query::Query, Any, AnyPool, Row, ValueRef, TypeInfo, Decode, Type, Error};
+
+use crate::{errors::ApiError, models::{post::PostStoreModel, user::UserStoreModel, session::SessionStoreModel,
+    comment::CommentStoreModel, revision::RevisionStoreModel, role::RoleStoreModel, invite::InviteStoreModel, api_key::ApiKeyStoreModel,
+    login_attempt::LoginAttemptStoreModel, page::PageRequest}};
+
+use super::Db;
+
+// Keeps a collection in a table of a PostgreSQL or SQLite database. Documents become rows, and the
+// lists inside them rows of their own tables, so that they can be queried like MongoDB queries them.
+pub struct SqlStore<T> {
+    pool :AnyPool,
+    model :PhantomData<fn() -> T>
+}
+
+impl<T> SqlStore<T> {
+    pub fn new(pool :AnyPool) -> Self {
+        Self { pool, model: PhantomData }
+    }
+}
+
+// Applied in order, each one exactly once, and recorded in `_migrations`. Migrations that were released
+// must never change, changes to the schema go into a new one.
+const MIGRATIONS :&[(&str, &[&str])] = &[
+    ("create tables", &[
+        "CREATE TABLE posts (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            slug TEXT NOT NULL UNIQUE,
+            content TEXT NOT NULL,
+            author TEXT NOT NULL,
+            status TEXT NOT NULL,
+            publish_at BIGINT,
+            revision BIGINT NOT NULL,
+            category TEXT,
+            updated BIGINT
+        )",
+        "CREATE INDEX posts_title ON posts (title, id)",
+        "CREATE INDEX posts_author ON posts (author, id)",
+        "CREATE INDEX posts_status ON posts (status, publish_at)",
+        "CREATE INDEX posts_category ON posts (category, id)",
+        // The slugs a post was previously reachable under, in the order it left them behind.
+        "CREATE TABLE post_slugs (
+            post TEXT NOT NULL,
+            ordinal BIGINT NOT NULL,
+            slug TEXT NOT NULL,
+            PRIMARY KEY (post, ordinal)
+        )",
+        "CREATE INDEX post_slugs_slug ON post_slugs (slug)",
+        "CREATE TABLE post_tags (
+            post TEXT NOT NULL,
+            ordinal BIGINT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (post, ordinal)
+        )",
+        "CREATE INDEX post_tags_tag ON post_tags (tag, post)",
+        "CREATE TABLE users (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            password_hash TEXT NOT NULL,
+            permissions TEXT NOT NULL,
+            email TEXT UNIQUE,
+            email_verified BIGINT NOT NULL,
+            totp_secret TEXT,
+            totp_enabled BIGINT,
+            totp_last_step BIGINT,
+            bio TEXT NOT NULL
+        )",
+        "CREATE INDEX users_name ON users (name, id)",
+        "CREATE TABLE recovery_codes (
+            user_id TEXT NOT NULL,
+            ordinal BIGINT NOT NULL,
+            digest TEXT NOT NULL,
+            PRIMARY KEY (user_id, ordinal)
+        )",
+        "CREATE TABLE sessions (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            token_hash TEXT NOT NULL,
+            revoked BIGINT NOT NULL,
+            mfa BIGINT NOT NULL,
+            created BIGINT NOT NULL,
+            expires BIGINT NOT NULL
+        )",
+        "CREATE INDEX sessions_token_hash ON sessions (token_hash)",
+        "CREATE INDEX sessions_user ON sessions (user_id)",
+        // Refresh tokens already rotated out of a session.
+        "CREATE TABLE session_used_hashes (
+            session TEXT NOT NULL,
+            ordinal BIGINT NOT NULL,
+            token_hash TEXT NOT NULL,
+            PRIMARY KEY (session, ordinal)
+        )",
+        "CREATE INDEX session_used_hashes_token_hash ON session_used_hashes (token_hash)",
+        "CREATE TABLE comments (
+            id TEXT PRIMARY KEY,
+            post TEXT NOT NULL,
+            parent TEXT,
+            content TEXT NOT NULL,
+            author TEXT NOT NULL,
+            created BIGINT NOT NULL,
+            edited BIGINT
+        )",
+        "CREATE INDEX comments_post ON comments (post, created)",
+        "CREATE INDEX comments_parent ON comments (parent)",
+        "CREATE INDEX comments_author ON comments (author)",
+        "CREATE TABLE revisions (
+            id TEXT PRIMARY KEY,
+            post TEXT NOT NULL,
+            number BIGINT NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            author TEXT NOT NULL,
+            created BIGINT NOT NULL,
+            restored_from BIGINT,
+            UNIQUE (post, number)
+        )",
+        // Capabilities are stored as a JSON array, they are only ever read and written as a whole.
+        "CREATE TABLE roles (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            capabilities TEXT NOT NULL
+        )",
+        "CREATE TABLE invites (
+            id TEXT PRIMARY KEY,
+            code_hash TEXT NOT NULL UNIQUE,
+            created_by TEXT NOT NULL,
+            created BIGINT NOT NULL,
+            expires BIGINT NOT NULL,
+            max_uses BIGINT NOT NULL,
+            uses BIGINT NOT NULL
+        )",
+        "CREATE TABLE api_keys (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            key_hash TEXT NOT NULL UNIQUE,
+            prefix TEXT NOT NULL,
+            capabilities TEXT NOT NULL,
+            mfa BIGINT NOT NULL,
+            created BIGINT NOT NULL,
+            expires BIGINT,
+            last_used BIGINT
+        )",
+        "CREATE INDEX api_keys_user ON api_keys (user_id, created)",
+        "CREATE TABLE login_attempts (
+            id TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            failures BIGINT NOT NULL,
+            last_failure BIGINT NOT NULL,
+            locked_until BIGINT,
+            UNIQUE (kind, subject)
+        )",
+        "CREATE INDEX login_attempts_last_failure ON login_attempts (last_failure)"
+    ]),
+    // Fails if duplicates made it in before, those have to be renamed by hand.
+    ("unique user names and post titles", &[
+        "CREATE UNIQUE INDEX users_name_unique ON users (name)",
+        "CREATE UNIQUE INDEX posts_title_unique ON posts (title)"
+    ])
+];
+
+// Connects to `postgres://` and `sqlite:` URIs. SQLite databases are created if missing, `sqlite::memory:`
+// lives as long as the process.
+pub async fn connect(uri :&str) -> Result<Db, Error> {
+    sqlx::any::install_default_drivers();
+
+    let pool = match uri.starts_with("sqlite:") {
+        true => {
+            let uri = match uri.contains(":memory:") || uri.contains('?') {
+                true => uri.to_string(),
+                false => format!("{}?mode=rwc", uri)
+            };
+
+            // SQLite allows one writer at a time anyway, and an in-memory database only exists for the
+            // connection that opened it, so it has to be kept open.
+            AnyPoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect(&uri).await?
+        },
+        false => AnyPoolOptions::new().connect(uri).await?
+    };
+
+    migrate(&pool).await?;
+
+    Ok(Db {
+        posts: Arc::new(SqlStore::<PostStoreModel>::new(pool.clone())),
+        users: Arc::new(SqlStore::<UserStoreModel>::new(pool.clone())),
+        sessions: Arc::new(SqlStore::<SessionStoreModel>::new(pool.clone())),
+        comments: Arc::new(SqlStore::<CommentStoreModel>::new(pool.clone())),
+        revisions: Arc::new(SqlStore::<RevisionStoreModel>::new(pool.clone())),
+        roles: Arc::new(SqlStore::<RoleStoreModel>::new(pool.clone())),
+        invites: Arc::new(SqlStore::<InviteStoreModel>::new(pool.clone())),
+        api_keys: Arc::new(SqlStore::<ApiKeyStoreModel>::new(pool.clone())),
+        login_attempts: Arc::new(SqlStore::<LoginAttemptStoreModel>::new(pool))
+    })
+}
+
+async fn migrate(pool :&AnyPool) -> Result<(), Error> {
+    query("CREATE TABLE IF NOT EXISTS _migrations (version BIGINT PRIMARY KEY, name TEXT NOT NULL, applied BIGINT NOT NULL)", &[])
+        .execute(pool).await?;
+
+    let applied :i64 = query("SELECT COUNT(*) FROM _migrations", &[]).fetch_one(pool).await?.try_get(0)?;
+
+    for (version, (name, statements)) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        let mut tx = pool.begin().await?;
+
+        for statement in statements.iter() {
+            query(statement, &[]).execute(&mut *tx).await?;
+        }
+
+        // Fails on the primary key if another instance applied the migration in the meantime.
+        query("INSERT INTO _migrations (version, name, applied) VALUES ($1, $2, $3)", &[
+            (version as i64 + 1).into(), (*name).into(), DateTime::now().into()
+        ]).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        info!("Applied migration {}: {}.", version + 1, name);
+    }
+
+    Ok(())
+}
+
+// A value bound to a statement.
+pub enum Param {
+    Text(String),
+    Int(i64),
+    // Goes to Postgres as an integer, so statements cast nullable text parameters, `CAST($1 AS TEXT)`.
+    Null
+}
+
+impl From<&str> for Param {
+    fn from(value :&str) -> Self {
+        Param::Text(value.to_string())
+    }
+}
+
+impl From<String> for Param {
+    fn from(value :String) -> Self {
+        Param::Text(value)
+    }
+}
+
+impl From<&String> for Param {
+    fn from(value :&String) -> Self {
+        Param::Text(value.clone())
+    }
+}
+
+impl From<i64> for Param {
+    fn from(value :i64) -> Self {
+        Param::Int(value)
+    }
+}
+
+impl From<u32> for Param {
+    fn from(value :u32) -> Self {
+        Param::Int(value as i64)
+    }
+}
+
+impl From<bool> for Param {
+    fn from(value :bool) -> Self {
+        Param::Int(value as i64)
+    }
+}
+
+impl From<&ObjectId> for Param {
+    fn from(value :&ObjectId) -> Self {
+        Param::Text(value.to_hex())
+    }
+}
+
+impl From<ObjectId> for Param {
+    fn from(value :ObjectId) -> Self {
+        Param::Text(value.to_hex())
+    }
+}
+
+// Points in time are stored as milliseconds since the epoch.
+impl From<DateTime> for Param {
+    fn from(value :DateTime) -> Self {
+        Param::Int(value.timestamp_millis())
+    }
+}
+
+impl<T :Into<Param>> From<Option<T>> for Param {
+    fn from(value :Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Param::Null
+        }
+    }
+}
+
+// Parameters are numbered, `$1`, `$2` and so on, which both Postgres and SQLite understand.
+fn query<'q>(sql :&'q str, params :&'q [Param]) -> Query<'q, Any, AnyArguments<'q>> {
+    params.iter().fold(sqlx::query(sql), |query, param| match param {
+        Param::Text(value) => query.bind(value.as_str()),
+        Param::Int(value) => query.bind(*value),
+        Param::Null => query.bind(Option::<i64>::None)
+    })
+}
+
+// Adds a parameter to a statement that is being put together, and returns its placeholder.
+fn param(params :&mut Vec<Param>, value :impl Into<Param>) -> String {
+    params.push(value.into());
+    format!("${}", params.len())
+}
+
+// Placeholders for a list of values, for `IN`. An empty list matches nothing.
+fn params<T>(params :&mut Vec<Param>, values :&[T]) -> String
+where for<'a> &'a T :Into<Param> {
+    if values.is_empty() {
+        return "(NULL)".to_string()
+    }
+
+    let placeholders :Vec<String> = values.iter().map(|value| param(params, value)).collect();
+    format!("({})", placeholders.join(", "))
+}
+
+fn internal(e :Error) -> ApiError {
+    ApiError::from(e)
+}
+
+// Unique indexes back up the checks the routes make before writing, which concurrent requests can race past.
+fn write_error(e :Error) -> ApiError {
+    match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => ApiError::Conflict("Duplicate key.".to_string()),
+        _ => internal(e)
+    }
+}
+
+fn id(row :&AnyRow, column :&str) -> Result<ObjectId, ApiError> {
+    let hex :String = row.try_get(column).map_err(internal)?;
+    match ObjectId::parse_str(&hex) {
+        Ok(id) => Ok(id),
+        Err(e) => Err(ApiError::Internal(e.to_string()))
+    }
+}
+
+// The `Any` driver hands NULLs to `Option` as values of their own type, so they are looked for first.
+fn nullable<'r, T :Decode<'r, Any> + Type<Any>>(row :&'r AnyRow, column :&str) -> Result<Option<T>, ApiError> {
+    let value = row.try_get_raw(column).map_err(internal)?;
+    match value.type_info().name() {
+        "NULL" => Ok(None),
+        _ => row.try_get(column).map(Some).map_err(internal)
+    }
+}
+
+fn maybe_id(row :&AnyRow, column :&str) -> Result<Option<ObjectId>, ApiError> {
+    let hex :Option<String> = nullable(row, column)?;
+    match hex.as_deref().map(ObjectId::parse_str) {
+        Some(Ok(id)) => Ok(Some(id)),
+        Some(Err(e)) => Err(ApiError::Internal(e.to_string())),
+        None => Ok(None)
+    }
+}
+
+fn text(row :&AnyRow, column :&str) -> Result<String, ApiError> {
+    row.try_get(column).map_err(internal)
+}
+
+fn maybe_text(row :&AnyRow, column :&str) -> Result<Option<String>, ApiError> {
+    nullable(row, column)
+}
+
+fn int(row :&AnyRow, column :&str) -> Result<i64, ApiError> {
+    row.try_get(column).map_err(internal)
+}
+
+fn maybe_int(row :&AnyRow, column :&str) -> Result<Option<i64>, ApiError> {
+    nullable(row, column)
+}
+
+fn flag(row :&AnyRow, column :&str) -> Result<bool, ApiError> {
+    Ok(int(row, column)? != 0)
+}
+
+// SQLite hands every integer to the Any driver as a 32 bit one, which cuts timestamps short, so the stores
+// select them as text, `CAST(created AS TEXT) AS created`. Ordering by one has to name its table then, or
+// SQLite orders by the text.
+fn millis(value :&str) -> Result<DateTime, ApiError> {
+    match value.parse() {
+        Ok(millis) => Ok(DateTime::from_millis(millis)),
+        Err(_e) => Err(ApiError::Internal(format!("Invalid timestamp {}.", value)))
+    }
+}
+
+fn datetime(row :&AnyRow, column :&str) -> Result<DateTime, ApiError> {
+    millis(&text(row, column)?)
+}
+
+fn maybe_datetime(row :&AnyRow, column :&str) -> Result<Option<DateTime>, ApiError> {
+    match maybe_text(row, column)? {
+        Some(value) => Ok(Some(millis(&value)?)),
+        None => Ok(None)
+    }
+}
+
+// Enums are stored under the names they serialize to, the same ones MongoDB stores.
+fn name<T :Serialize>(value :&T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        _ => String::new()
+    }
+}
+
+fn named<T :DeserializeOwned>(row :&AnyRow, column :&str) -> Result<T, ApiError> {
+    match serde_json::from_value(Value::String(text(row, column)?)) {
+        Ok(value) => Ok(value),
+        Err(e) => Err(ApiError::Internal(e.to_string()))
+    }
+}
+
+fn to_json<T :Serialize>(value :&T) -> Result<String, ApiError> {
+    match serde_json::to_string(value) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(ApiError::Internal(e.to_string()))
+    }
+}
+
+fn json<T :DeserializeOwned>(row :&AnyRow, column :&str) -> Result<T, ApiError> {
+    match serde_json::from_str(&text(row, column)?) {
+        Ok(value) => Ok(value),
+        Err(e) => Err(ApiError::Internal(e.to_string()))
+    }
+}
+
+// Posts that were never published sort before all others, as they do in MongoDB.
+const NEVER :i64 = i64::MIN;
+
+fn where_clause(conditions :&[String]) -> String {
+    match conditions.is_empty() {
+        true => String::new(),
+        false => format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+// Fetches the page of rows matching the conditions, along with the total number of matching rows. Rows are
+// ordered by the sort field and then by `id`, the same way the MongoDB backend orders documents.
+async fn find_page(pool :&AnyPool, table :&str, columns :&str, mut conditions :Vec<String>, mut params :Vec<Param>, page :&PageRequest) -> Result<(Vec<AnyRow>, u64), ApiError> {
+    let count = format!("SELECT COUNT(*) FROM {} {}", table, where_clause(&conditions));
+    let total :i64 = match query(&count, &params).fetch_one(pool).await {
+        Ok(row) => row.try_get(0).map_err(internal)?,
+        Err(e) => return Err(internal(e))
+    };
+
+    let column = match page.field {
+        "_id" => "id".to_string(),
+        "publish_at" => format!("COALESCE({}.publish_at, {})", table, NEVER),
+        field => field.to_string()
+    };
+    let (op, direction) = if page.descending { ("<", "DESC") } else { (">", "ASC") };
+
+    if let Some(position) = &page.after {
+        let id = param(&mut params, position_param(&position.id));
+        match page.field {
+            "_id" => conditions.push(format!("id {} {}", op, id)),
+            _ => {
+                let value = param(&mut params, position_param(&position.value));
+                conditions.push(format!("({} {} {} OR ({} = {} AND id {} {}))", column, op, value, column, value, op, id));
+            }
+        }
+    }
+
+    let order = match page.field {
+        "_id" => format!("id {}", direction),
+        _ => format!("{} {}, id {}", column, direction, direction)
+    };
+
+    let select = format!("SELECT {} FROM {} {} ORDER BY {} LIMIT {}", columns, table, where_clause(&conditions), order, page.limit);
+    match query(&select, &params).fetch_all(pool).await {
+        Ok(rows) => Ok((rows, total as u64)),
+        Err(e) => Err(internal(e))
+    }
+}
+
+fn position_param(value :&Bson) -> Param {
+    match value {
+        Bson::String(value) => Param::Text(value.clone()),
+        Bson::ObjectId(value) => Param::Text(value.to_hex()),
+        Bson::DateTime(value) => Param::Int(value.timestamp_millis()),
+        Bson::Int32(value) => Param::Int(*value as i64),
+        Bson::Int64(value) => Param::Int(*value),
+        _ => Param::Int(NEVER)
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use sqlx::{any::AnyRow, Any, Transaction};
+
+use crate::{errors::ApiError, db::PostRepository, models::{post::{PostStoreModel, PostFilter, PostVisibility, PostStatus},
+    page::PageRequest, search::{self, PostSearchHit}, tag::TagReadModel}};
+
+use super::{SqlStore, Param, query, param, params, internal, write_error, where_clause, find_page, id, text, maybe_text, int, maybe_datetime,
+    name, named, NEVER};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, title, slug, content, author, status, CAST(publish_at AS TEXT) AS publish_at, revision, category,
+    CAST(updated AS TEXT) AS updated";
+
+// Tags and previous slugs are read separately, see `SqlStore::load`.
+fn post(row :&AnyRow) -> Result<PostStoreModel, ApiError> {
+    Ok(PostStoreModel {
+        _id: id(row, "id")?,
+        title: text(row, "title")?,
+        slug: text(row, "slug")?,
+        slug_history: vec![],
+        content: text(row, "content")?,
+        author: id(row, "author")?,
+        status: named(row, "status")?,
+        publish_at: maybe_datetime(row, "publish_at")?,
+        revision: int(row, "revision")? as u32,
+        tags: vec![],
+        category: maybe_text(row, "category")?,
+        updated: maybe_datetime(row, "updated")?
+    })
+}
+
+fn filter(filter :&PostFilter, params :&mut Vec<Param>) -> Vec<String> {
+    let mut conditions = vec![];
+
+    match &filter.visibility {
+        PostVisibility::Published => conditions.push(format!("status = {}", param(params, name(&PostStatus::Published)))),
+        PostVisibility::PublishedOrAuthor(author) => {
+            let published = param(params, name(&PostStatus::Published));
+            conditions.push(format!("(status = {} OR author = {})", published, param(params, author)));
+        },
+        PostVisibility::All => ()
+    }
+
+    match filter.status {
+        Some(status) => conditions.push(format!("status = {}", param(params, name(&status)))),
+        None => conditions.push(format!("status <> {}", param(params, name(&PostStatus::Archived))))
+    }
+
+    if let Some(author) = &filter.author {
+        conditions.push(format!("author = {}", param(params, author)));
+    }
+
+    if let Some(tag) = &filter.tag {
+        conditions.push(format!("id IN (SELECT post FROM post_tags WHERE tag = {})", param(params, tag)));
+    }
+
+    if let Some(category) = &filter.category {
+        conditions.push(format!("category = {}", param(params, category)));
+    }
+
+    conditions
+}
+
+// Writes the tags and previous slugs of the post, in place of the ones it had.
+async fn write_lists(tx :&mut Transaction<'_, Any>, post :&PostStoreModel) -> Result<(), ApiError> {
+    let id = post._id.to_hex();
+
+    if let Err(e) = query("DELETE FROM post_tags WHERE post = $1", &[id.as_str().into()]).execute(&mut **tx).await {
+        return Err(internal(e))
+    }
+
+    if let Err(e) = query("DELETE FROM post_slugs WHERE post = $1", &[id.as_str().into()]).execute(&mut **tx).await {
+        return Err(internal(e))
+    }
+
+    for (ordinal, tag) in post.tags.iter().enumerate() {
+        if let Err(e) = query("INSERT INTO post_tags (post, ordinal, tag) VALUES ($1, $2, $3)", &[
+            id.as_str().into(), (ordinal as i64).into(), tag.into()
+        ]).execute(&mut **tx).await {
+            return Err(internal(e))
+        }
+    }
+
+    for (ordinal, slug) in post.slug_history.iter().enumerate() {
+        if let Err(e) = query("INSERT INTO post_slugs (post, ordinal, slug) VALUES ($1, $2, $3)", &[
+            id.as_str().into(), (ordinal as i64).into(), slug.into()
+        ]).execute(&mut **tx).await {
+            return Err(internal(e))
+        }
+    }
+
+    Ok(())
+}
+
+impl SqlStore<PostStoreModel> {
+    // Reads the posts of the rows, along with their tags and previous slugs.
+    async fn load(&self, rows :Vec<AnyRow>) -> Result<Vec<PostStoreModel>, ApiError> {
+        let mut posts :Vec<PostStoreModel> = vec![];
+        for row in &rows {
+            posts.push(post(row)?);
+        }
+
+        if posts.is_empty() {
+            return Ok(posts)
+        }
+
+        let index :HashMap<String, usize> = posts.iter().enumerate().map(|(i, post)| (post._id.to_hex(), i)).collect();
+        let ids :Vec<ObjectId> = posts.iter().map(|post| post._id).collect();
+
+        let mut tag_params = vec![];
+        let tags = format!("SELECT post, tag FROM post_tags WHERE post IN {} ORDER BY post, ordinal", params(&mut tag_params, &ids));
+        let rows = match query(&tags, &tag_params).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        for row in &rows {
+            if let Some(i) = index.get(&text(row, "post")?) {
+                posts[*i].tags.push(text(row, "tag")?);
+            }
+        }
+
+        let mut slug_params = vec![];
+        let slugs = format!("SELECT post, slug FROM post_slugs WHERE post IN {} ORDER BY post, ordinal", params(&mut slug_params, &ids));
+        let rows = match query(&slugs, &slug_params).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        for row in &rows {
+            if let Some(i) = index.get(&text(row, "post")?) {
+                posts[*i].slug_history.push(text(row, "slug")?);
+            }
+        }
+
+        Ok(posts)
+    }
+
+    async fn load_one(&self, row :Option<AnyRow>) -> Result<Option<PostStoreModel>, ApiError> {
+        match row {
+            Some(row) => Ok(self.load(vec![row]).await?.pop()),
+            None => Ok(None)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl PostRepository for SqlStore<PostStoreModel> {
+    async fn find(&self, key :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        let mut params :Vec<Param> = vec![key.into()];
+        let mut conditions = vec!["slug = $1".to_string(), "id IN (SELECT post FROM post_slugs WHERE slug = $1)".to_string()];
+        if let Ok(id) = ObjectId::parse_str(key) {
+            conditions.push(format!("id = {}", param(&mut params, id)));
+        }
+
+        let select = format!("SELECT {} FROM posts WHERE {} LIMIT 1", COLUMNS, conditions.join(" OR "));
+        match query(&select, &params).fetch_optional(&self.pool).await {
+            Ok(row) => self.load_one(row).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_title(&self, title :&str) -> Result<Option<PostStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM posts WHERE title = $1 LIMIT 1", COLUMNS), &[title.into()]).fetch_optional(&self.pool).await {
+            Ok(row) => self.load_one(row).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn slug_taken(&self, slug :&str, except :&ObjectId) -> Result<bool, ApiError> {
+        match query(
+            "SELECT COUNT(*) AS taken FROM posts WHERE id <> $2 AND (slug = $1 OR id IN (SELECT post FROM post_slugs WHERE slug = $1))",
+            &[slug.into(), except.into()]
+        ).fetch_one(&self.pool).await {
+            Ok(row) => Ok(int(&row, "taken")? > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn insert(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        if let Err(e) = query(
+            "INSERT INTO posts (id, title, slug, content, author, status, publish_at, revision, category, updated)
+            VALUES ($1, $2, $3, $4, $5, $6, CAST($7 AS BIGINT), $8, CAST($9 AS TEXT), CAST($10 AS BIGINT))",
+            &[
+                (&post._id).into(), (&post.title).into(), (&post.slug).into(), (&post.content).into(), (&post.author).into(),
+                name(&post.status).into(), post.publish_at.into(), post.revision.into(), post.category.as_ref().into(), post.updated.into()
+            ]
+        ).execute(&mut *tx).await {
+            return Err(write_error(e))
+        }
+
+        write_lists(&mut tx, post).await?;
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn replace(&self, post :&PostStoreModel) -> Result<bool, ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        let replaced = match query(
+            "UPDATE posts SET title = $2, slug = $3, content = $4, author = $5, status = $6, publish_at = CAST($7 AS BIGINT),
+            revision = $8, category = CAST($9 AS TEXT), updated = CAST($10 AS BIGINT) WHERE id = $1 AND revision = $11",
+            &[
+                (&post._id).into(), (&post.title).into(), (&post.slug).into(), (&post.content).into(), (&post.author).into(),
+                name(&post.status).into(), post.publish_at.into(), post.revision.into(), post.category.as_ref().into(), post.updated.into(),
+                (post.revision as i64 - 1).into()
+            ]
+        ).execute(&mut *tx).await {
+            Ok(result) => result.rows_affected() > 0,
+            Err(e) => return Err(write_error(e))
+        };
+
+        // Nothing was written, dropping the transaction rolls it back.
+        if !replaced {
+            return Ok(false)
+        }
+
+        write_lists(&mut tx, post).await?;
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(true),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_status(&self, post :&PostStoreModel) -> Result<(), ApiError> {
+        match query(
+            "UPDATE posts SET status = $2, publish_at = CAST($3 AS BIGINT), updated = CAST($4 AS BIGINT) WHERE id = $1",
+            &[(&post._id).into(), name(&post.status).into(), post.publish_at.into(), post.updated.into()]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        for statement in ["DELETE FROM post_tags WHERE post = $1", "DELETE FROM post_slugs WHERE post = $1", "DELETE FROM posts WHERE id = $1"] {
+            if let Err(e) = query(statement, &[id.into()]).execute(&mut *tx).await {
+                return Err(internal(e))
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn ids_by_author(&self, author :&ObjectId) -> Result<Vec<ObjectId>, ApiError> {
+        let rows = match query("SELECT id FROM posts WHERE author = $1", &[author.into()]).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        rows.iter().map(|row| id(row, "id")).collect()
+    }
+
+    async fn delete_by_author(&self, author :&ObjectId) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        for statement in [
+            "DELETE FROM post_tags WHERE post IN (SELECT id FROM posts WHERE author = $1)",
+            "DELETE FROM post_slugs WHERE post IN (SELECT id FROM posts WHERE author = $1)",
+            "DELETE FROM posts WHERE author = $1"
+        ] {
+            if let Err(e) = query(statement, &[author.into()]).execute(&mut *tx).await {
+                return Err(internal(e))
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn page(&self, posts :&PostFilter, page :&PageRequest) -> Result<(Vec<PostStoreModel>, u64), ApiError> {
+        let mut params = vec![];
+        let conditions = filter(posts, &mut params);
+
+        let (rows, total) = find_page(&self.pool, "posts", COLUMNS, conditions, params, page).await?;
+        Ok((self.load(rows).await?, total))
+    }
+
+    // There's no text index shared by Postgres and SQLite, so matches are counted by hand: how much shorter
+    // the title and content get with every occurrence of a term removed, weighed like the text index would.
+    async fn search(&self, q :&str, posts :&PostFilter, skip :u64, limit :u32) -> Result<(Vec<PostSearchHit>, u64), ApiError> {
+        let terms :Vec<String> = search::terms(q).into_iter().map(|term| term.into_iter().collect()).collect();
+        if terms.is_empty() {
+            return Ok((vec![], 0))
+        }
+
+        let mut params = vec![];
+        let mut conditions = filter(posts, &mut params);
+
+        for term in search::negated_terms(q) {
+            let term = param(&mut params, term);
+            conditions.push(format!(
+                "LENGTH(REPLACE(LOWER(title), {}, '')) = LENGTH(title) AND LENGTH(REPLACE(LOWER(content), {}, '')) = LENGTH(content)",
+                term, term
+            ));
+        }
+
+        let mut score :Vec<String> = vec![];
+        for term in terms {
+            let length = param(&mut params, term.chars().count() as i64);
+            let term = param(&mut params, term);
+            score.push(format!(
+                "(LENGTH(title) - LENGTH(REPLACE(LOWER(title), {term}, ''))) / {length} * {weight} + (LENGTH(content) - LENGTH(REPLACE(LOWER(content), {term}, ''))) / {length}",
+                term = term, length = length, weight = search::TITLE_WEIGHT
+            ));
+        }
+
+        let hits = format!(
+            "(SELECT {}, CAST({} AS BIGINT) AS score FROM posts {}) AS hits WHERE score > 0",
+            COLUMNS, score.join(" + "), where_clause(&conditions)
+        );
+
+        let total = match query(&format!("SELECT COUNT(*) AS total FROM {}", hits), &params).fetch_one(&self.pool).await {
+            Ok(row) => int(&row, "total")?,
+            Err(e) => return Err(internal(e))
+        };
+
+        let select = format!("SELECT * FROM {} ORDER BY score DESC, id DESC LIMIT {} OFFSET {}", hits, limit, skip);
+        let rows = match query(&select, &params).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        let mut scores :Vec<f64> = vec![];
+        for row in &rows {
+            scores.push(int(row, "score")? as f64);
+        }
+
+        let posts = self.load(rows).await?;
+        Ok((posts.into_iter().zip(scores).map(|(post, score)| PostSearchHit { post, score }).collect(), total as u64))
+    }
+
+    async fn latest(&self, posts :&PostFilter, limit :u32) -> Result<Vec<PostStoreModel>, ApiError> {
+        let mut params = vec![];
+        let conditions = filter(posts, &mut params);
+
+        let select = format!(
+            "SELECT {} FROM posts {} ORDER BY COALESCE(posts.publish_at, {}) DESC, id DESC LIMIT {}",
+            COLUMNS, where_clause(&conditions), NEVER, limit
+        );
+
+        match query(&select, &params).fetch_all(&self.pool).await {
+            Ok(rows) => self.load(rows).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn tag_counts(&self, posts :&PostFilter) -> Result<Vec<TagReadModel>, ApiError> {
+        let mut params = vec![];
+        let conditions = filter(posts, &mut params);
+
+        let select = format!(
+            "SELECT tag, COUNT(*) AS tagged FROM post_tags WHERE post IN (SELECT id FROM posts {}) GROUP BY tag ORDER BY tagged DESC, tag ASC",
+            where_clause(&conditions)
+        );
+
+        let rows = match query(&select, &params).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        let mut tags :Vec<TagReadModel> = vec![];
+        for row in &rows {
+            tags.push(TagReadModel { name: text(row, "tag")?, posts: int(row, "tagged")? as u64 });
+        }
+
+        Ok(tags)
+    }
+
+    async fn count_tag(&self, tag :&str) -> Result<u64, ApiError> {
+        match query("SELECT COUNT(DISTINCT post) AS tagged FROM post_tags WHERE tag = $1", &[tag.into()]).fetch_one(&self.pool).await {
+            Ok(row) => Ok(int(&row, "tagged")? as u64),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn retag(&self, from :&str, to :&str) -> Result<u64, ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        if let Err(e) = query(
+            "DELETE FROM post_tags WHERE tag = $1 AND post IN (SELECT post FROM post_tags WHERE tag = $2)",
+            &[from.into(), to.into()]
+        ).execute(&mut *tx).await {
+            return Err(internal(e))
+        }
+
+        let replaced = match query("UPDATE post_tags SET tag = $2 WHERE tag = $1", &[from.into(), to.into()]).execute(&mut *tx).await {
+            Ok(result) => result.rows_affected(),
+            Err(e) => return Err(internal(e))
+        };
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(replaced),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn publish_scheduled(&self) -> Result<Option<DateTime>, ApiError> {
+        let now = DateTime::now();
+        let scheduled = name(&PostStatus::Scheduled);
+
+        if let Err(e) = query(
+            "UPDATE posts SET status = $1, updated = $2 WHERE status = $3 AND publish_at <= $2",
+            &[name(&PostStatus::Published).into(), now.into(), scheduled.as_str().into()]
+        ).execute(&self.pool).await {
+            return Err(internal(e))
+        }
+
+        match query("SELECT CAST(MIN(publish_at) AS TEXT) AS next FROM posts WHERE status = $1", &[scheduled.into()]).fetch_one(&self.pool).await {
+            Ok(row) => maybe_datetime(&row, "next"),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    // The schema has every field from the start, there's nothing to fill in.
+    async fn backfill(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn unrevisioned(&self) -> Result<Vec<PostStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM posts WHERE revision = 0", COLUMNS), &[]).fetch_all(&self.pool).await {
+            Ok(rows) => self.load(rows).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use mongodb::bson::oid::ObjectId;
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::RevisionRepository, models::revision::RevisionStoreModel};
+
+use super::{SqlStore, query, params, internal, id, text, int, maybe_int, datetime};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, post, number, title, content, author, CAST(created AS TEXT) AS created, restored_from";
+
+fn revision(row :&AnyRow) -> Result<RevisionStoreModel, ApiError> {
+    Ok(RevisionStoreModel {
+        _id: id(row, "id")?,
+        post: id(row, "post")?,
+        number: int(row, "number")? as u32,
+        title: text(row, "title")?,
+        content: text(row, "content")?,
+        author: id(row, "author")?,
+        created: datetime(row, "created")?,
+        restored_from: maybe_int(row, "restored_from")?.map(|number| number as u32)
+    })
+}
+
+#[rocket::async_trait]
+impl RevisionRepository for SqlStore<RevisionStoreModel> {
+    async fn insert(&self, revision :&RevisionStoreModel) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO revisions (id, post, number, title, content, author, created, restored_from)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CAST($8 AS BIGINT))",
+            &[
+                (&revision._id).into(), (&revision.post).into(), revision.number.into(), (&revision.title).into(),
+                (&revision.content).into(), (&revision.author).into(), revision.created.into(), revision.restored_from.into()
+            ]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find(&self, post :&ObjectId, number :u32) -> Result<Option<RevisionStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM revisions WHERE post = $1 AND number = $2", COLUMNS), &[post.into(), number.into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(revision(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self, post :&ObjectId) -> Result<Vec<RevisionStoreModel>, ApiError> {
+        match query(&format!("SELECT {} FROM revisions WHERE post = $1 ORDER BY number DESC", COLUMNS), &[post.into()]).fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(revision).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_posts(&self, posts :&[ObjectId]) -> Result<(), ApiError> {
+        let mut values = vec![];
+        let delete = format!("DELETE FROM revisions WHERE post IN {}", params(&mut values, posts));
+
+        match query(&delete, &values).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
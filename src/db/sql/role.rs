@@ -0,0 +1,52 @@
+use mongodb::bson::oid::ObjectId;
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::RoleRepository, models::{role::{RoleStoreModel, Capability}, user::UserPermissionLevel}};
+
+use super::{SqlStore, query, internal, id, name, named, to_json, json};
+
+fn role(row :&AnyRow) -> Result<RoleStoreModel, ApiError> {
+    Ok(RoleStoreModel {
+        _id: id(row, "id")?,
+        name: named(row, "name")?,
+        capabilities: json(row, "capabilities")?
+    })
+}
+
+#[rocket::async_trait]
+impl RoleRepository for SqlStore<RoleStoreModel> {
+    async fn find(&self, role :UserPermissionLevel) -> Result<Option<RoleStoreModel>, ApiError> {
+        match query("SELECT * FROM roles WHERE name = $1", &[name(&role).into()]).fetch_optional(&self.pool).await {
+            Ok(Some(row)) => Ok(Some(self::role(&row)?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<RoleStoreModel>, ApiError> {
+        match query("SELECT * FROM roles ORDER BY id", &[]).fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(role).collect(),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn insert_missing(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO roles (id, name, capabilities) VALUES ($1, $2, $3) ON CONFLICT (name) DO NOTHING",
+            &[ObjectId::new().into(), name(&role).into(), to_json(&capabilities)?.into()]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_capabilities(&self, role :UserPermissionLevel, capabilities :&[Capability]) -> Result<(), ApiError> {
+        match query(
+            "INSERT INTO roles (id, name, capabilities) VALUES ($1, $2, $3) ON CONFLICT (name) DO UPDATE SET capabilities = excluded.capabilities",
+            &[ObjectId::new().into(), name(&role).into(), to_json(&capabilities)?.into()]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use sqlx::any::AnyRow;
+
+use crate::{errors::ApiError, db::SessionRepository, models::session::SessionStoreModel};
+
+use super::{SqlStore, query, internal, id, text, flag, datetime};
+
+// Timestamps are read as text, see `datetime`.
+const COLUMNS :&str = "id, user_id, token_hash, revoked, mfa, CAST(created AS TEXT) AS created, CAST(expires AS TEXT) AS expires";
+
+fn session(row :&AnyRow, used_hashes :Vec<String>) -> Result<SessionStoreModel, ApiError> {
+    Ok(SessionStoreModel {
+        _id: id(row, "id")?,
+        user: id(row, "user_id")?,
+        token_hash: text(row, "token_hash")?,
+        used_hashes,
+        revoked: flag(row, "revoked")?,
+        mfa: flag(row, "mfa")?,
+        created: datetime(row, "created")?,
+        expires: datetime(row, "expires")?
+    })
+}
+
+#[rocket::async_trait]
+impl SessionRepository for SqlStore<SessionStoreModel> {
+    async fn insert(&self, session :&SessionStoreModel) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        if let Err(e) = query(
+            "INSERT INTO sessions (id, user_id, token_hash, revoked, mfa, created, expires) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[
+                (&session._id).into(), (&session.user).into(), (&session.token_hash).into(), session.revoked.into(),
+                session.mfa.into(), session.created.into(), session.expires.into()
+            ]
+        ).execute(&mut *tx).await {
+            return Err(internal(e))
+        }
+
+        for (ordinal, hash) in session.used_hashes.iter().enumerate() {
+            if let Err(e) = query("INSERT INTO session_used_hashes (session, ordinal, token_hash) VALUES ($1, $2, $3)", &[
+                (&session._id).into(), (ordinal as i64).into(), hash.into()
+            ]).execute(&mut *tx).await {
+                return Err(internal(e))
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn is_active(&self, id :&ObjectId) -> Result<bool, ApiError> {
+        match query(
+            "SELECT id FROM sessions WHERE id = $1 AND revoked = 0 AND expires > $2",
+            &[id.into(), DateTime::now().into()]
+        ).fetch_optional(&self.pool).await {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn rotate(&self, presented_hash :&str, token_hash :&str, expires :DateTime) -> Result<Option<SessionStoreModel>, ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        // The update takes the row, a concurrent rotation of the same token finds the new hash in it and matches nothing.
+        let row = match query(
+            &format!("UPDATE sessions SET token_hash = $2, expires = $3 WHERE token_hash = $1 AND revoked = 0 AND expires > $4 RETURNING {}", COLUMNS),
+            &[presented_hash.into(), token_hash.into(), expires.into(), DateTime::now().into()]
+        ).fetch_optional(&mut *tx).await {
+            Ok(Some(row)) => row,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(internal(e))
+        };
+        let session_id = id(&row, "id")?;
+
+        if let Err(e) = query(
+            "INSERT INTO session_used_hashes (session, ordinal, token_hash)
+            SELECT $1, COALESCE(MAX(ordinal) + 1, 0), $2 FROM session_used_hashes WHERE session = $1",
+            &[(&session_id).into(), presented_hash.into()]
+        ).execute(&mut *tx).await {
+            return Err(internal(e))
+        }
+
+        let used_hashes = match query(
+            "SELECT token_hash FROM session_used_hashes WHERE session = $1 ORDER BY ordinal",
+            &[(&session_id).into()]
+        ).fetch_all(&mut *tx).await {
+            Ok(rows) => rows.iter().map(|row| text(row, "token_hash")).collect::<Result<Vec<String>, ApiError>>()?,
+            Err(e) => return Err(internal(e))
+        };
+
+        let session = session(&row, used_hashes)?;
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(Some(session)),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_reused(&self, presented_hash :&str) -> Result<bool, ApiError> {
+        match query(
+            "UPDATE sessions SET revoked = 1 WHERE id IN (SELECT session FROM session_used_hashes WHERE token_hash = $1)",
+            &[presented_hash.into()]
+        ).execute(&self.pool).await {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke(&self, id :&ObjectId) -> Result<(), ApiError> {
+        match query("UPDATE sessions SET revoked = 1 WHERE id = $1", &[id.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        match query("UPDATE sessions SET revoked = 1 WHERE user_id = $1", &[user.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn revoke_others(&self, user :&ObjectId, keep :&ObjectId) -> Result<(), ApiError> {
+        match query("UPDATE sessions SET revoked = 1 WHERE user_id = $1 AND id <> $2", &[user.into(), keep.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete_by_user(&self, user :&ObjectId) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        for statement in [
+            "DELETE FROM session_used_hashes WHERE session IN (SELECT id FROM sessions WHERE user_id = $1)",
+            "DELETE FROM sessions WHERE user_id = $1"
+        ] {
+            if let Err(e) = query(statement, &[user.into()]).execute(&mut *tx).await {
+                return Err(internal(e))
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
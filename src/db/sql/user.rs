@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use sqlx::{any::AnyRow, Any, Transaction};
+
+use crate::{errors::ApiError, db::UserRepository, models::{user::UserStoreModel, totp::TotpStoreModel, page::PageRequest}};
+
+use super::{SqlStore, query, params, internal, write_error, find_page, id, text, maybe_text, maybe_int, flag, name, named};
+
+// Recovery codes are read separately, see `SqlStore::load`.
+fn user(row :&AnyRow) -> Result<UserStoreModel, ApiError> {
+    let totp = match maybe_text(row, "totp_secret")? {
+        Some(secret) => Some(TotpStoreModel {
+            secret,
+            enabled: maybe_int(row, "totp_enabled")?.unwrap_or(0) != 0,
+            last_step: maybe_int(row, "totp_last_step")?.unwrap_or(-1),
+            recovery_codes: vec![]
+        }),
+        None => None
+    };
+
+    Ok(UserStoreModel {
+        _id: id(row, "id")?,
+        name: text(row, "name")?,
+        password_hash: text(row, "password_hash")?,
+        permissions: named(row, "permissions")?,
+        email: maybe_text(row, "email")?,
+        email_verified: flag(row, "email_verified")?,
+        totp,
+        bio: text(row, "bio")?
+    })
+}
+
+async fn write_recovery_codes(tx :&mut Transaction<'_, Any>, id :&ObjectId, digests :&[String]) -> Result<(), ApiError> {
+    if let Err(e) = query("DELETE FROM recovery_codes WHERE user_id = $1", &[id.into()]).execute(&mut **tx).await {
+        return Err(internal(e))
+    }
+
+    for (ordinal, digest) in digests.iter().enumerate() {
+        if let Err(e) = query("INSERT INTO recovery_codes (user_id, ordinal, digest) VALUES ($1, $2, $3)", &[
+            id.into(), (ordinal as i64).into(), digest.into()
+        ]).execute(&mut **tx).await {
+            return Err(internal(e))
+        }
+    }
+
+    Ok(())
+}
+
+// Writes the enrolment of the user, in place of the one they had.
+async fn write_totp(tx :&mut Transaction<'_, Any>, id :&ObjectId, totp :Option<&TotpStoreModel>) -> Result<(), ApiError> {
+    if let Err(e) = query(
+        "UPDATE users SET totp_secret = CAST($2 AS TEXT), totp_enabled = CAST($3 AS BIGINT), totp_last_step = CAST($4 AS BIGINT) WHERE id = $1",
+        &[id.into(), totp.map(|totp| &totp.secret).into(), totp.map(|totp| totp.enabled).into(), totp.map(|totp| totp.last_step).into()]
+    ).execute(&mut **tx).await {
+        return Err(internal(e))
+    }
+
+    match totp {
+        Some(totp) => write_recovery_codes(tx, id, &totp.recovery_codes).await,
+        None => write_recovery_codes(tx, id, &[]).await
+    }
+}
+
+impl SqlStore<UserStoreModel> {
+    // Reads the users of the rows, along with the recovery codes of the ones enrolled.
+    async fn load(&self, rows :Vec<AnyRow>) -> Result<Vec<UserStoreModel>, ApiError> {
+        let mut users :Vec<UserStoreModel> = vec![];
+        for row in &rows {
+            users.push(user(row)?);
+        }
+
+        let ids :Vec<ObjectId> = users.iter().filter(|user| user.totp.is_some()).map(|user| user._id).collect();
+        if ids.is_empty() {
+            return Ok(users)
+        }
+
+        let index :HashMap<String, usize> = users.iter().enumerate().map(|(i, user)| (user._id.to_hex(), i)).collect();
+
+        let mut codes_params = vec![];
+        let codes = format!(
+            "SELECT user_id, digest FROM recovery_codes WHERE user_id IN {} ORDER BY user_id, ordinal",
+            params(&mut codes_params, &ids)
+        );
+        let rows = match query(&codes, &codes_params).fetch_all(&self.pool).await {
+            Ok(rows) => rows,
+            Err(e) => return Err(internal(e))
+        };
+
+        for row in &rows {
+            if let Some(totp) = index.get(&text(row, "user_id")?).and_then(|i| users[*i].totp.as_mut()) {
+                totp.recovery_codes.push(text(row, "digest")?);
+            }
+        }
+
+        Ok(users)
+    }
+
+    async fn load_one(&self, row :Option<AnyRow>) -> Result<Option<UserStoreModel>, ApiError> {
+        match row {
+            Some(row) => Ok(self.load(vec![row]).await?.pop()),
+            None => Ok(None)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for SqlStore<UserStoreModel> {
+    async fn find(&self, id :&ObjectId) -> Result<Option<UserStoreModel>, ApiError> {
+        match query("SELECT * FROM users WHERE id = $1", &[id.into()]).fetch_optional(&self.pool).await {
+            Ok(row) => self.load_one(row).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_name(&self, name :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        match query("SELECT * FROM users WHERE name = $1 LIMIT 1", &[name.into()]).fetch_optional(&self.pool).await {
+            Ok(row) => self.load_one(row).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn find_by_email(&self, email :&str) -> Result<Option<UserStoreModel>, ApiError> {
+        match query("SELECT * FROM users WHERE email = $1", &[email.into()]).fetch_optional(&self.pool).await {
+            Ok(row) => self.load_one(row).await,
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn page(&self, page :&PageRequest) -> Result<(Vec<UserStoreModel>, u64), ApiError> {
+        let (rows, total) = find_page(&self.pool, "users", "*", vec![], vec![], page).await?;
+        Ok((self.load(rows).await?, total))
+    }
+
+    async fn insert(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        if let Err(e) = query(
+            "INSERT INTO users (id, name, password_hash, permissions, email, email_verified, bio) VALUES ($1, $2, $3, $4, CAST($5 AS TEXT), $6, $7)",
+            &[
+                (&user._id).into(), (&user.name).into(), (&user.password_hash).into(), name(&user.permissions).into(),
+                user.email.as_ref().into(), user.email_verified.into(), (&user.bio).into()
+            ]
+        ).execute(&mut *tx).await {
+            return Err(write_error(e))
+        }
+
+        write_totp(&mut tx, &user._id, user.totp.as_ref()).await?;
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn replace(&self, user :&UserStoreModel) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        let replaced = match query(
+            "UPDATE users SET name = $2, password_hash = $3, permissions = $4, email = CAST($5 AS TEXT), email_verified = $6, bio = $7 WHERE id = $1",
+            &[
+                (&user._id).into(), (&user.name).into(), (&user.password_hash).into(), name(&user.permissions).into(),
+                user.email.as_ref().into(), user.email_verified.into(), (&user.bio).into()
+            ]
+        ).execute(&mut *tx).await {
+            Ok(result) => result.rows_affected() > 0,
+            Err(e) => return Err(write_error(e))
+        };
+
+        if replaced {
+            write_totp(&mut tx, &user._id, user.totp.as_ref()).await?;
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn delete(&self, id :&ObjectId) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        for statement in ["DELETE FROM recovery_codes WHERE user_id = $1", "DELETE FROM users WHERE id = $1"] {
+            if let Err(e) = query(statement, &[id.into()]).execute(&mut *tx).await {
+                return Err(internal(e))
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_password_hash(&self, id :&ObjectId, password_hash :&str) -> Result<(), ApiError> {
+        match query("UPDATE users SET password_hash = $2 WHERE id = $1", &[id.into(), password_hash.into()]).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn change_password_hash(&self, id :&ObjectId, from :&str, to :&str) -> Result<bool, ApiError> {
+        match query(
+            "UPDATE users SET password_hash = $3 WHERE id = $1 AND password_hash = $2",
+            &[id.into(), from.into(), to.into()]
+        ).execute(&self.pool).await {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn verify_email(&self, id :&ObjectId, email :Option<&str>) -> Result<(), ApiError> {
+        match query(
+            "UPDATE users SET email_verified = 1 WHERE id = $1 AND (email = CAST($2 AS TEXT) OR (email IS NULL AND CAST($2 AS TEXT) IS NULL))",
+            &[id.into(), email.into()]
+        ).execute(&self.pool).await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_totp(&self, id :&ObjectId, totp :Option<&TotpStoreModel>) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        write_totp(&mut tx, id, totp).await?;
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn advance_totp_step(&self, id :&ObjectId, from :i64, to :i64) -> Result<bool, ApiError> {
+        match query(
+            "UPDATE users SET totp_last_step = $3 WHERE id = $1 AND totp_last_step = $2",
+            &[id.into(), from.into(), to.into()]
+        ).execute(&self.pool).await {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn use_recovery_code(&self, id :&ObjectId, digest :&str) -> Result<bool, ApiError> {
+        match query("DELETE FROM recovery_codes WHERE user_id = $1 AND digest = $2", &[id.into(), digest.into()]).execute(&self.pool).await {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(internal(e))
+        }
+    }
+
+    async fn set_recovery_codes(&self, id :&ObjectId, digests :&[String]) -> Result<(), ApiError> {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => return Err(internal(e))
+        };
+
+        let enabled = match query("SELECT id FROM users WHERE id = $1 AND totp_enabled = 1", &[id.into()]).fetch_optional(&mut *tx).await {
+            Ok(row) => row.is_some(),
+            Err(e) => return Err(internal(e))
+        };
+
+        if enabled {
+            write_recovery_codes(&mut tx, id, digests).await?;
+        }
+
+        match tx.commit().await {
+            Ok(_ok) => Ok(()),
+            Err(e) => Err(internal(e))
+        }
+    }
+}
@@ -1,38 +1,145 @@
 use std::io::Cursor;
 use rocket::{http::{Status, ContentType}, Response, serde::json::serde_json::json};
 
+use crate::middlewares::rate_limit::RateLimitState;
+
 #[derive(Debug)]
-pub struct ApiError {
-    pub status: Status,
-    pub message: String
+pub enum ApiError {
+    Validation(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Unprocessable(String),
+    TooManyRequests(String),
+    // Holds the cause for the logs, clients only get a generic detail.
+    Internal(String)
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::Validation(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Unprocessable(_) => Status::UnprocessableEntity,
+            ApiError::TooManyRequests(_) => Status::TooManyRequests,
+            ApiError::Internal(_) => Status::InternalServerError
+        }
+    }
+
+    // Stable across releases, clients can match on it rather than on the detail.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unprocessable(_) => "unprocessable",
+            ApiError::TooManyRequests(_) => "too_many_requests",
+            ApiError::Internal(_) => "internal"
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::Validation(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Unprocessable(message)
+            | ApiError::TooManyRequests(message)
+            | ApiError::Internal(message) => message
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for ApiError {
+    fn from(e :mongodb::error::Error) -> Self {
+        ApiError::Internal(format!("Database error: {}", e))
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e :sqlx::Error) -> Self {
+        ApiError::Internal(format!("Database error: {}", e))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(e :jsonwebtoken::errors::Error) -> Self {
+        ApiError::Internal(format!("Token error: {}", e))
+    }
 }
 
+// Answered as an RFC 7807 problem, with the stable code as an extension member.
 impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for ApiError {
-    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let status = self.status();
+        let detail = match &self {
+            ApiError::Internal(cause) => {
+                error!("{} {} failed: {}", request.method(), request.uri(), cause);
+                "Something went wrong on our side."
+            },
+            _ => self.message()
+        };
+
         let body = json!({
-            "message": self.message
+            "type": "about:blank",
+            "title": status.reason().unwrap_or_default(),
+            "status": status.code,
+            "detail": detail,
+            "code": self.code()
         }).to_string();
 
         Response::build()
             .sized_body(body.len(), Cursor::new(body))
-            .status(self.status)
-            .header(ContentType::JSON)
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
             .ok()
     }
 }
 
+#[catch(400)]
+pub fn bad_request(_ :&rocket::Request) -> ApiError {
+    ApiError::Validation(String::from("The request is malformed."))
+}
+
 #[catch(401)]
 pub fn unauthorized(_ :&rocket::Request) -> ApiError {
-    ApiError { 
-        status: Status::Unauthorized, 
-        message: String::from("You need to authenticate to access this resource.")
-    }
+    ApiError::Unauthorized(String::from("You need to authenticate to access this resource."))
 }
 
 #[catch(403)]
 pub fn forbidden(_ :&rocket::Request) -> ApiError {
-    ApiError { 
-        status: Status::Unauthorized, 
-        message: String::from("You don't have permission to access this resource.")
-    }
-}
\ No newline at end of file
+    ApiError::Forbidden(String::from("You don't have permission to access this resource."))
+}
+
+#[catch(404)]
+pub fn not_found(_ :&rocket::Request) -> ApiError {
+    ApiError::NotFound(String::from("The resource doesn't exist."))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(_ :&rocket::Request) -> ApiError {
+    ApiError::Unprocessable(String::from("The request body couldn't be read."))
+}
+
+#[catch(429)]
+pub fn too_many_requests(request :&rocket::Request) -> ApiError {
+    let message = match request.local_cache(|| None::<RateLimitState>).and_then(|state| state.retry_after) {
+        Some(retry_after) => format!("Too many requests, try again in {} seconds.", retry_after),
+        None => String::from("Too many requests, try again later.")
+    };
+
+    ApiError::TooManyRequests(message)
+}
+
+#[catch(500)]
+pub fn internal_error(_ :&rocket::Request) -> ApiError {
+    ApiError::Internal(String::from("Unhandled server error."))
+}
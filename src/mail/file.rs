@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::errors::ApiError;
+
+use super::{Email, Mailer, MailSettings};
+
+// Doesn't send anything. Emails are written to a directory, or only logged when there's none
+// configured, so that the flows can be tried out locally.
+pub struct FileMailer {
+    from :String,
+    dir :Option<PathBuf>
+}
+
+impl FileMailer {
+    pub fn new(settings :&MailSettings) -> Self {
+        Self {
+            from: settings.from.clone(),
+            dir: settings.dir.as_ref().map(PathBuf::from)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email :Email) -> Result<(), ApiError> {
+        let message = format!("From: {}\nTo: {}\nSubject: {}\n\n{}", self.from, email.to, email.subject, email.body);
+
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => {
+                info!("Email not sent, no mail transport configured:\n{}", message);
+                return Ok(())
+            }
+        };
+
+        if let Err(e) = rocket::tokio::fs::create_dir_all(dir).await {
+            return Err(ApiError::Internal(e.to_string()))
+        }
+
+        let path = dir.join(format!("{}.eml", ObjectId::new().to_hex()));
+        match rocket::tokio::fs::write(&path, message).await {
+            Ok(_ok) => {
+                info!("Email to {} written to {}.", email.to, path.display());
+                Ok(())
+            },
+            Err(e) => Err(ApiError::Internal(e.to_string()))
+        }
+    }
+}
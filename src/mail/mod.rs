@@ -0,0 +1,100 @@
+pub mod smtp;
+pub mod file;
+
+use std::sync::Arc;
+
+use rocket::serde::Deserialize;
+
+use crate::{errors::ApiError, middlewares::auth::SecretKeyWrapper, models::user::UserStoreModel,
+    security::action::{self, ActionPurpose}};
+
+use self::{smtp::SmtpMailer, file::FileMailer};
+
+// The `mail` section of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct MailSettings {
+    // Either `smtp`, or `file` for local testing.
+    pub transport :String,
+    pub from :String,
+    // Address of the frontend, which the links in the emails lead to.
+    pub link :String,
+    pub smtp_host :String,
+    pub smtp_port :u16,
+    pub smtp_username :Option<String>,
+    pub smtp_password :Option<String>,
+    // Where the file transport puts the emails. Without it they are only logged.
+    pub dir :Option<String>
+}
+
+impl Default for MailSettings {
+    fn default() -> Self {
+        Self {
+            transport: "file".to_string(),
+            from: "RkBlog <noreply@localhost>".to_string(),
+            link: "http://localhost:3000".to_string(),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            dir: None
+        }
+    }
+}
+
+pub struct Email {
+    pub to :String,
+    pub subject :String,
+    pub body :String
+}
+
+#[rocket::async_trait]
+pub trait Mailer :Send + Sync {
+    async fn send(&self, email :Email) -> Result<(), ApiError>;
+}
+
+pub fn mailer(settings :&MailSettings) -> Result<Arc<dyn Mailer>, String> {
+    match settings.transport.as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::new(settings)?)),
+        "file" => Ok(Arc::new(FileMailer::new(settings))),
+        transport => Err(format!("Unknown mail transport {}.", transport))
+    }
+}
+
+// Asks the user to confirm that the address they gave is theirs.
+pub async fn send_verification(mailer :&dyn Mailer, settings :&MailSettings, secret :&SecretKeyWrapper, user :&UserStoreModel) -> Result<(), ApiError> {
+    let to = match &user.email {
+        Some(email) => email.clone(),
+        None => return Ok(())
+    };
+
+    let token = action::sign(secret, ActionPurpose::VerifyEmail, user)?;
+
+    mailer.send(Email {
+        to,
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nplease confirm your email address by following the link below:\n\n{}/verify?token={}\n\nThe link is valid for {} hours.\n",
+            user.name, settings.link, token, ActionPurpose::VerifyEmail.lifetime() / 3600
+        )
+    }).await
+}
+
+// Only builds the email, the caller decides when to send it.
+pub fn password_reset(settings :&MailSettings, secret :&SecretKeyWrapper, user :&UserStoreModel) -> Result<Option<Email>, ApiError> {
+    let to = match &user.email {
+        Some(email) => email.clone(),
+        None => return Ok(None)
+    };
+
+    let token = action::sign(secret, ActionPurpose::ResetPassword, user)?;
+
+    Ok(Some(Email {
+        to,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nsomeone asked to reset the password of your account. If it was you, follow the link below to choose a new one:\n\n{}/reset?token={}\n\nThe link is valid for {} minutes. If you didn't ask for this, you can ignore this email.\n",
+            user.name, settings.link, token, ActionPurpose::ResetPassword.lifetime() / 60
+        )
+    }))
+}
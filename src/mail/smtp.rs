@@ -0,0 +1,51 @@
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox, transport::smtp::authentication::Credentials};
+
+use crate::errors::ApiError;
+
+use super::{Email, Mailer, MailSettings};
+
+pub struct SmtpMailer {
+    from :Mailbox,
+    transport :AsyncSmtpTransport<Tokio1Executor>
+}
+
+impl SmtpMailer {
+    pub fn new(settings :&MailSettings) -> Result<Self, String> {
+        let from = match settings.from.parse::<Mailbox>() {
+            Ok(from) => from,
+            Err(e) => return Err(e.to_string())
+        };
+
+        // STARTTLS is required, credentials are never sent in the clear.
+        let mut transport = match AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.smtp_host) {
+            Ok(transport) => transport.port(settings.smtp_port),
+            Err(e) => return Err(e.to_string())
+        };
+
+        if let (Some(username), Some(password)) = (&settings.smtp_username, &settings.smtp_password) {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self { from, transport: transport.build() })
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email :Email) -> Result<(), ApiError> {
+        let to = match email.to.parse::<Mailbox>() {
+            Ok(to) => to,
+            Err(e) => return Err(ApiError::Validation(e.to_string()))
+        };
+
+        let message = match Message::builder().from(self.from.clone()).to(to).subject(email.subject).body(email.body) {
+            Ok(message) => message,
+            Err(e) => return Err(ApiError::Internal(e.to_string()))
+        };
+
+        match self.transport.send(message).await {
+            Ok(_response) => Ok(()),
+            Err(e) => Err(ApiError::Internal(e.to_string()))
+        }
+    }
+}
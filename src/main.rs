@@ -1,28 +1,67 @@
 #[macro_use] 
 extern crate rocket;
 
-use dotenv;
-
 mod routes;
 mod db;
 mod models;
 mod errors;
 mod middlewares;
-use middlewares::{auth::SecretKeyWrapper};
-use rocket::http::Method;
+mod security;
+mod tasks;
+mod render;
+mod mail;
+#[cfg(test)]
+mod tests;
+use middlewares::{auth::{SecretKeyWrapper, AuthSettings}, client::ProxySettings, rate_limit::{RateLimiter, RateLimitSettings}};
+use rocket::{Rocket, Build, http::Method};
 use rocket_cors::{CorsOptions, AllowedOrigins};
-use routes::{post, user, auth};
+use routes::{post, user, auth, comment, revision, tag, feed, role, invite, totp, api_key, lockout};
+use models::role::RoleStoreModel;
+use tasks::scheduler::PostScheduler;
+use render::{markdown::MarkdownRenderer, feed::FeedSettings};
+use mail::MailSettings;
+use security::jwt::{JwtKeys, JwtSettings};
 
 #[launch]
 async fn rocket() -> _ {
     dotenv::dotenv().ok();
-    
-    let rkt = rocket::build();
 
-    let db_uri = std::env::var("RKBLOG_URI").unwrap();
-    let db_name = std::env::var("RKBLOG_DATABASE").unwrap();
-    let db = db::connect(&db_uri, &db_name).await.unwrap();
-    let key = rocket::Config::from(rkt.figment()).secret_key;
+    // `mongodb://`, `postgres://` or `sqlite:`, the scheme picks the backend.
+    let db_uri = match std::env::var("RKBLOG_URI") {
+        Ok(uri) => uri,
+        Err(_e) => panic!("RKBLOG_URI is not set, it should point at the database.")
+    };
+    // Only needed for MongoDB.
+    let db_name = std::env::var("RKBLOG_DATABASE").unwrap_or_default();
+    let db = match db::connect(&db_uri, &db_name).await {
+        Ok(db) => db,
+        Err(e) => panic!("Could not connect to the database: {}", e)
+    };
+
+    app(rocket::build(), db).await
+}
+
+// Everything but the choice of storage and configuration, which the tests make for themselves.
+async fn app(rkt :Rocket<Build>, db :db::Db) -> Rocket<Build> {
+    RoleStoreModel::seed(db.roles.as_ref()).await.unwrap();
+    // Rocket doesn't hand out the bytes of its secret key, so the configured one is read directly. Without
+    // one, as in debug builds, tokens are signed with a random key that doesn't survive a restart.
+    let key = match rkt.figment().extract_inner::<String>("secret_key") {
+        Ok(key) => key.into_bytes(),
+        Err(_e) => {
+            warn!("No secret_key configured, tokens won't survive a restart.");
+            security::token::generate().into_bytes()
+        }
+    };
+    let secret = SecretKeyWrapper {key};
+    let jwt_settings = rkt.figment().extract_inner::<JwtSettings>("jwt").unwrap_or_default();
+    let jwt_keys = JwtKeys::load(jwt_settings, &secret).unwrap();
+    let feed_settings = rkt.figment().extract_inner::<FeedSettings>("feed").unwrap_or_default();
+    let auth_settings = rkt.figment().extract_inner::<AuthSettings>("auth").unwrap_or_default();
+    let rate_limit_settings = rkt.figment().extract_inner::<RateLimitSettings>("rate_limit").unwrap_or_default();
+    let proxy_settings = rkt.figment().extract_inner::<ProxySettings>("proxy").unwrap_or_default();
+    let mail_settings = rkt.figment().extract_inner::<MailSettings>("mail").unwrap_or_default();
+    let mailer = mail::mailer(&mail_settings).unwrap();
 
     let cors = CorsOptions::default()
     .allowed_origins(AllowedOrigins::all())
@@ -37,15 +76,43 @@ async fn rocket() -> _ {
 
     rkt
     .attach(cors)
-    .manage(SecretKeyWrapper {key})
+    .attach(PostScheduler::fairing())
+    .attach(RateLimiter::fairing())
+    .manage(secret)
+    .manage(jwt_keys)
+    .manage(auth_settings)
+    .manage(RateLimiter::new(rate_limit_settings))
+    .manage(proxy_settings)
+    .manage(PostScheduler::default())
+    .manage(MarkdownRenderer::default())
+    .manage(feed_settings)
+    .manage(mail_settings)
+    .manage(mailer)
     .manage(db.posts)
     .manage(db.users)
+    .manage(db.sessions)
+    .manage(db.comments)
+    .manage(db.revisions)
+    .manage(db.roles)
+    .manage(db.invites)
+    .manage(db.api_keys)
+    .manage(db.login_attempts)
     .mount("/posts", routes![
         post::list, 
+        post::search,
         post::get, 
         post::create, 
         post::update,
-        post::delete
+        post::delete,
+        post::transition,
+        comment::list,
+        comment::create,
+        comment::update,
+        comment::delete,
+        revision::list,
+        revision::get,
+        revision::diff,
+        revision::restore
     ])
     .mount("/users", routes![
         user::list,
@@ -53,10 +120,55 @@ async fn rocket() -> _ {
         user::create,
         user::update,
         user::delete
+    ]).mount("/tags", routes![
+        tag::list,
+        tag::posts,
+        tag::rename,
+        tag::merge
+    ]).mount("/roles", routes![
+        role::list,
+        role::update
+    ]).mount("/invites", routes![
+        invite::list,
+        invite::create,
+        invite::delete
+    ]).mount("/keys", routes![
+        api_key::list,
+        api_key::create,
+        api_key::delete
+    ]).mount("/lockouts", routes![
+        lockout::list,
+        lockout::delete
     ]).mount("/auth", routes![
-        auth::get_token
+        auth::get_token,
+        auth::register,
+        auth::forgot,
+        auth::reset,
+        auth::verify,
+        auth::resend_verification,
+        totp::enrol,
+        totp::confirm,
+        totp::disable,
+        totp::recovery_codes,
+        totp::sign_in,
+        auth::refresh,
+        auth::logout
+    ]).mount("/.well-known", routes![
+        auth::jwks
+    ]).mount("/", routes![
+        feed::rss,
+        feed::atom,
+        feed::author_rss,
+        feed::author_atom,
+        feed::tag_rss,
+        feed::tag_atom
     ]).register("/", catchers![
+        errors::bad_request,
         errors::unauthorized,
-        errors::forbidden
+        errors::forbidden,
+        errors::not_found,
+        errors::unprocessable_entity,
+        errors::too_many_requests,
+        errors::internal_error
     ])
 }
\ No newline at end of file
@@ -1,18 +1,44 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
 
-use rocket::{request::{Outcome, FromRequest}, http::Status, State, outcome::Outcome::{Success}};
+use mongodb::bson::oid::ObjectId;
+use rocket::{request::{Outcome, FromRequest}, http::Status, State, outcome::Outcome::{Success}, serde::Deserialize};
 
-use crate::{models::user::{UserAuthClaimsModel, UserPermissionLevel}};
+use crate::{models::{user::{UserAuthClaimsModel, UserPermissionLevel}, role::{RoleStoreModel, Capability}, api_key::ApiKeyStoreModel},
+    db::{SessionRepository, ApiKeyRepository, UserRepository, RoleRepository}, security::jwt::JwtKeys};
 
+// Rocket doesn't hand out the bytes of its own secret key, see `main`.
 pub struct SecretKeyWrapper {
-    pub key :rocket::config::SecretKey
+    pub key :Vec<u8>
+}
+
+// The `auth` section of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    // Admins only get their capabilities in sessions started with a second factor.
+    pub require_admin_2fa :bool,
+    // Name the accounts show up under in authenticator apps.
+    pub totp_issuer :String,
+    // Failed sign ins in a row before an account, or an address, gets locked out for a while.
+    pub max_failed_logins :u32,
+    pub max_failed_logins_per_address :u32
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            require_admin_2fa: false,
+            totp_issuer: "RkBlog".to_string(),
+            max_failed_logins: 5,
+            max_failed_logins_per_address: 50
+        }
+    }
 }
 
 pub trait Authorize {
     fn authorize(claim :&UserAuthClaimsModel) -> bool;
 }
 pub struct UserAuthorization {}
-pub struct AdminPermissionAuthorization {}
 
 impl Authorize for UserAuthorization {
     fn authorize(_claim :&UserAuthClaimsModel) -> bool {
@@ -20,60 +46,235 @@ impl Authorize for UserAuthorization {
     }
 }
 
-impl Authorize for AdminPermissionAuthorization {
+pub trait RequiredCapability {
+    const CAPABILITY :Capability;
+}
+
+// Lets the request through if the user's role grants the capability `C` stands for.
+pub struct CapabilityAuthorization<C :RequiredCapability> {
+    capability :PhantomData<C>
+}
+
+impl<C :RequiredCapability> Authorize for CapabilityAuthorization<C> {
     fn authorize(claim :&UserAuthClaimsModel) -> bool {
-        claim.permissions == UserPermissionLevel::Admin
+        claim.can(C::CAPABILITY)
     }
 }
 
+macro_rules! required_capabilities {
+    ($($marker:ident => $capability:ident),*) => {
+        $(
+            pub struct $marker {}
+
+            impl RequiredCapability for $marker {
+                const CAPABILITY :Capability = Capability::$capability;
+            }
+        )*
+    };
+}
+
+required_capabilities!(
+    PostCreateCapability => PostCreate,
+    CommentCreateCapability => CommentCreate,
+    TagManageCapability => TagManage,
+    UserManageCapability => UserManage,
+    RoleManageCapability => RoleManage
+);
+
 pub struct AuthorizeToken <T :Authorize> {
     token_type :PhantomData<T>,
     pub claim :UserAuthClaimsModel
 }
 
+// Like AuthorizeToken, but lets anonymous requests through with no claim. A token that is sent
+// along still has to be valid, so a broken client doesn't silently fall back to anonymous access.
+pub struct MaybeAuthorizeToken {
+    pub claim :Option<UserAuthClaimsModel>
+}
+
+// The ways a request can authenticate.
+enum Credentials<'r> {
+    Bearer(&'r str),
+    ApiKey(&'r str)
+}
+
+fn credentials<'r>(request :&'r rocket::Request<'_>) -> Result<Option<Credentials<'r>>, Status> {
+    if let Some(key) = request.headers().get_one("X-Api-Key") {
+        return Ok(Some(Credentials::ApiKey(key)))
+    }
+
+    let header = match request.headers().get_one("Authorization") {
+        Some(header) => header,
+        None => return Ok(None)
+    };
+
+    let mut spl = header.split(' ');
+    let scheme = spl.next();
+    match (scheme, spl.next()) {
+        (Some("Bearer"), Some(token)) => Ok(Some(Credentials::Bearer(token))),
+        (Some("ApiKey"), Some(key)) => Ok(Some(Credentials::ApiKey(key))),
+        _ => Err(Status::BadRequest)
+    }
+}
+
+async fn validate_token(request: &rocket::Request<'_>, token :&str) -> Result<UserAuthClaimsModel, Status> {
+    let keys = match request.guard::<&State<JwtKeys>>().await {
+        Success(keys) => keys,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    let claim = match keys.decode::<UserAuthClaimsModel>(token) {
+        Ok(claim) => claim,
+        Err(_e) => return Err(Status::Forbidden)
+    };
+    
+    if claim.exp < jsonwebtoken::get_current_timestamp() {
+        return Err(Status::Unauthorized)
+    }
+
+    // Access tokens are only valid as long as the session they were issued for.
+    let sessions = match request.guard::<&State<Arc<dyn SessionRepository>>>().await {
+        Success(sessions) => sessions,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    let sid = match ObjectId::parse_str(&claim.sid) {
+        Ok(sid) => sid,
+        Err(_e) => return Err(Status::Forbidden)
+    };
+
+    match sessions.is_active(&sid).await {
+        Ok(true) => (),
+        Ok(false) => return Err(Status::Unauthorized),
+        Err(_e) => return Err(Status::InternalServerError)
+    }
+
+    // The role in the token is the one the user had when signing in, so the current one is looked up
+    // to let a change of role take effect right away.
+    let users = match request.guard::<&State<Arc<dyn UserRepository>>>().await {
+        Success(users) => users,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    let id = match ObjectId::parse_str(&claim._id) {
+        Ok(id) => id,
+        Err(_e) => return Err(Status::Forbidden)
+    };
+
+    let claim = match users.find(&id).await {
+        Ok(Some(user)) => UserAuthClaimsModel { permissions: user.permissions, ..claim },
+        Ok(None) => return Err(Status::Unauthorized),
+        Err(_e) => return Err(Status::InternalServerError)
+    };
+
+    resolve_capabilities(request, claim, None).await
+}
+
+async fn validate_api_key(request: &rocket::Request<'_>, key :&str) -> Result<UserAuthClaimsModel, Status> {
+    let keys = match request.guard::<&State<Arc<dyn ApiKeyRepository>>>().await {
+        Success(keys) => keys,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    let key = match ApiKeyStoreModel::authenticate(keys.as_ref(), key).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return Err(Status::Unauthorized),
+        Err(_e) => return Err(Status::InternalServerError)
+    };
+
+    let users = match request.guard::<&State<Arc<dyn UserRepository>>>().await {
+        Success(users) => users,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    let user = match users.find(&key.user).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(Status::Unauthorized),
+        Err(_e) => return Err(Status::InternalServerError)
+    };
+
+    // Keys stand in for the user, with the same claims a token of theirs would carry.
+    let claim = UserAuthClaimsModel {
+        exp: key.expires.map(|expires| (expires.timestamp_millis() / 1000) as u64).unwrap_or(u64::MAX),
+        sid: String::new(),
+        _id: user._id.to_hex(),
+        name: user.name,
+        permissions: user.permissions,
+        mfa: key.mfa,
+        capabilities: vec![],
+        api_key: Some(key._id.to_hex())
+    };
+
+    resolve_capabilities(request, claim, Some(key.capabilities)).await
+}
+
+// Works out what the user may do. Requests made with an API key are further limited to its scope.
+async fn resolve_capabilities(request: &rocket::Request<'_>, mut claim :UserAuthClaimsModel, scope :Option<Vec<Capability>>) -> Result<UserAuthClaimsModel, Status> {
+    let roles = match request.guard::<&State<Arc<dyn RoleRepository>>>().await {
+        Success(roles) => roles,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    claim.capabilities = match RoleStoreModel::capabilities(roles.as_ref(), claim.permissions).await {
+        Ok(capabilities) => capabilities,
+        Err(_e) => return Err(Status::InternalServerError)
+    };
+
+    if let Some(scope) = scope {
+        claim.capabilities.retain(|capability| scope.contains(capability));
+    }
+
+    let settings = match request.guard::<&State<AuthSettings>>().await {
+        Success(settings) => settings,
+        _ => return Err(Status::InternalServerError)
+    };
+
+    // Without a second factor admins can still sign in, e.g. to enrol, but get no capabilities until they do.
+    if settings.require_admin_2fa && claim.permissions == UserPermissionLevel::Admin && !claim.mfa {
+        claim.capabilities = vec![];
+    }
+
+    Ok(claim)
+}
+
+// The outcome is cached for the request, as several guards may need it.
+pub async fn authenticate(request: &rocket::Request<'_>) -> Result<Option<UserAuthClaimsModel>, Status> {
+    request.local_cache_async(async {
+        match credentials(request)? {
+            Some(Credentials::Bearer(token)) => validate_token(request, token).await.map(Some),
+            Some(Credentials::ApiKey(key)) => validate_api_key(request, key).await.map(Some),
+            None => Ok(None)
+        }
+    }).await.clone()
+}
+
 #[rocket::async_trait]
 impl<T :Authorize, 'r> FromRequest<'r> for AuthorizeToken<T> {
     type Error = ();
 
     async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
-        let token = match request.headers().get_one("Authorization") {
-            Some(token) => {
-                let mut spl = token.split(' ');
-                if let Some(name) = spl.nth(0) {
-                    if name != "Bearer" {
-                        return Outcome::Failure((Status::BadRequest, ()))
-                    }
-                };
-                match spl.nth(0) {
-                    Some(token) => token,
-                    None => return Outcome::Failure((Status::BadRequest, ()))
-                }
-            },
-            None => return Outcome::Failure((Status::Unauthorized, ()))
-        };
-
-        let secret = match request.guard::<&State<SecretKeyWrapper>>().await.map(|conf| &conf.key) {
-            Success(key) => key,
-            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        let claim = match authenticate(request).await {
+            Ok(Some(claim)) => claim,
+            Ok(None) => return Outcome::Failure((Status::Unauthorized, ())),
+            Err(status) => return Outcome::Failure((status, ()))
         };
 
-        let claim = match jsonwebtoken::decode::<UserAuthClaimsModel>(
-            token, 
-            &jsonwebtoken::DecodingKey::from_secret(secret.to_string().as_bytes()), 
-            &jsonwebtoken::Validation::default()
-        ) {
-            Ok(claim) => claim.claims,
-            Err(_e) => return Outcome::Failure((Status::Forbidden, ()))
-        };
-        
-        if &claim.exp < &jsonwebtoken::get_current_timestamp() {
-            return Outcome::Failure((Status::Unauthorized, ()))
-        }
-
         if !T::authorize(&claim) {
             return Outcome::Failure((Status::Forbidden, ()))
         }
 
-        return Outcome::Success(AuthorizeToken {claim, token_type: PhantomData::default()});
+        return Outcome::Success(AuthorizeToken {claim, token_type: PhantomData});
     }
-}
\ No newline at end of file
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MaybeAuthorizeToken {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match authenticate(request).await {
+            Ok(claim) => Outcome::Success(MaybeAuthorizeToken { claim }),
+            Err(status) => Outcome::Failure((status, ()))
+        }
+    }
+}
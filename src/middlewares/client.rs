@@ -0,0 +1,26 @@
+use std::net::IpAddr;
+
+use rocket::serde::Deserialize;
+
+// The `proxy` section of the Rocket configuration.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ProxySettings {
+    // Whether the app runs behind a proxy that sets Rocket's `ip_header`, `X-Real-IP` unless configured
+    // otherwise. Without one the header is whatever the client chose to send.
+    pub trusted :bool
+}
+
+// The address a request came from, as far as it can be trusted. Rate limits and lockouts are keyed on it,
+// so a client mustn't be able to pick a new one for every request.
+pub fn client_address(request :&rocket::Request<'_>) -> Option<IpAddr> {
+    let trusted = match request.rocket().state::<ProxySettings>() {
+        Some(settings) => settings.trusted,
+        None => false
+    };
+
+    match trusted {
+        true => request.client_ip(),
+        false => request.remote().map(|remote| remote.ip())
+    }
+}
@@ -0,0 +1,32 @@
+use rocket::request::{Outcome, FromRequest};
+
+// The `If-None-Match` header of a conditional GET request. `If-Modified-Since` is left alone: a post
+// that gets unpublished or deleted leaves the feed without making anything in it newer, so a date
+// can't tell that the cached copy is out of date.
+pub struct ConditionalRequest {
+    pub if_none_match :Option<String>
+}
+
+impl ConditionalRequest {
+    // Whether the client's cached copy is still current.
+    pub fn is_fresh(&self, etag :&str) -> bool {
+        match &self.if_none_match {
+            Some(if_none_match) => if_none_match.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            }),
+            None => false
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ConditionalRequest {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ConditionalRequest {
+            if_none_match: request.headers().get_one("If-None-Match").map(str::to_string)
+        })
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use rocket::{request::{Outcome, FromRequest}, http::Status, State, outcome::Outcome::Success};
+
+use crate::{models::login_attempt::LoginAttemptStoreModel, db::LoginAttemptRepository, errors::ApiError};
+
+use super::{auth::AuthSettings, client::client_address};
+
+// The failed sign ins counted against an account and the address the request came from, for the
+// routes that check a password or a code. See `LoginAttemptStoreModel`.
+pub struct SignInAttempts<'r> {
+    attempts :&'r dyn LoginAttemptRepository,
+    settings :&'r AuthSettings,
+    address :Option<String>
+}
+
+impl<'r> SignInAttempts<'r> {
+    pub async fn ensure_unlocked(&self, name :&str) -> Result<(), ApiError> {
+        LoginAttemptStoreModel::ensure_unlocked(self.attempts, name, self.address.as_deref()).await
+    }
+
+    pub async fn record_failure(&self, name :&str) -> Result<(), ApiError> {
+        LoginAttemptStoreModel::record_failure(self.attempts, self.settings, name, self.address.as_deref()).await
+    }
+
+    pub async fn record_success(&self, name :&str) -> Result<(), ApiError> {
+        LoginAttemptStoreModel::record_success(self.attempts, name).await
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SignInAttempts<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let attempts = match request.guard::<&State<Arc<dyn LoginAttemptRepository>>>().await {
+            Success(attempts) => attempts,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+        let settings = match request.guard::<&State<AuthSettings>>().await {
+            Success(settings) => settings,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+
+        Outcome::Success(SignInAttempts {
+            attempts: attempts.inner().as_ref(),
+            settings: settings.inner(),
+            address: client_address(request).map(|address| address.to_string())
+        })
+    }
+}
@@ -1 +1,5 @@
 pub mod auth;
+pub mod client;
+pub mod conditional;
+pub mod lockout;
+pub mod rate_limit;
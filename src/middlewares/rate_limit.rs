@@ -0,0 +1,215 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Mutex, time::{Duration, Instant}};
+
+use rocket::{request::{Outcome, FromRequest}, http::{Status, Header}, State, outcome::Outcome::Success, fairing::AdHoc, serde::Deserialize};
+
+use super::{auth, client::client_address};
+
+// Buckets are swept for ones that filled back up once the map grows past this size, and at most
+// once per interval, so that a flood of new clients doesn't turn every request into a full scan.
+const SWEEP_THRESHOLD :usize = 10_000;
+const SWEEP_INTERVAL :Duration = Duration::from_secs(60);
+
+// How many requests a client may make in a row, and how quickly that allowance comes back.
+#[derive(Deserialize, Clone, Copy)]
+pub struct RateLimitBudget {
+    pub burst :u32,
+    pub per_minute :u32
+}
+
+// The `rate_limit` section of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub enabled :bool,
+    // Signing in, registering and everything else under `/auth`, kept tight to slow down guessing.
+    pub auth :RateLimitBudget,
+    // Requests that change something.
+    pub write :RateLimitBudget,
+    pub read :RateLimitBudget
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auth: RateLimitBudget { burst: 10, per_minute: 10 },
+            write: RateLimitBudget { burst: 30, per_minute: 30 },
+            read: RateLimitBudget { burst: 300, per_minute: 300 }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RateLimitGroup {
+    Auth, Write, Read
+}
+
+// What a request was charged against, reported back to the client in the `X-RateLimit-*` headers.
+#[derive(Clone, Copy)]
+pub struct RateLimitState {
+    pub limit :u32,
+    pub remaining :u32,
+    // Seconds until the bucket is full again.
+    pub reset :u64,
+    // Seconds until the next request would be let through, if this one wasn't.
+    pub retry_after :Option<u64>
+}
+
+struct Bucket {
+    tokens :f64,
+    updated :Instant
+}
+
+// In-memory token buckets, one per route group and client. Limits are per process, so with several
+// instances behind a load balancer every instance hands out its own budget.
+pub struct RateLimiter {
+    settings :RateLimitSettings,
+    buckets :Mutex<HashMap<(RateLimitGroup, String), Bucket>>,
+    swept :Mutex<Instant>
+}
+
+impl RateLimiter {
+    pub fn new(settings :RateLimitSettings) -> Self {
+        Self { settings, buckets: Mutex::new(HashMap::new()), swept: Mutex::new(Instant::now()) }
+    }
+
+    fn budget(&self, group :RateLimitGroup) -> RateLimitBudget {
+        match group {
+            RateLimitGroup::Auth => self.settings.auth,
+            RateLimitGroup::Write => self.settings.write,
+            RateLimitGroup::Read => self.settings.read
+        }
+    }
+
+    fn sweep_due(&self, now :Instant) -> bool {
+        let mut swept = self.swept.lock().unwrap_or_else(|e| e.into_inner());
+        match now.duration_since(*swept) >= SWEEP_INTERVAL {
+            true => {
+                *swept = now;
+                true
+            },
+            false => false
+        }
+    }
+
+    // Takes a token from the client's bucket. Errs with the state to report if the bucket is empty.
+    pub fn take(&self, group :RateLimitGroup, client :&str) -> Result<RateLimitState, RateLimitState> {
+        let budget = self.budget(group);
+        let burst = budget.burst.max(1) as f64;
+        let rate = budget.per_minute.max(1) as f64 / 60.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > SWEEP_THRESHOLD && self.sweep_due(now) {
+            buckets.retain(|(group, _client), bucket| {
+                let budget = self.budget(*group);
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * budget.per_minute.max(1) as f64 / 60.0 < budget.burst.max(1) as f64
+            });
+        }
+
+        let bucket = buckets.entry((group, client.to_string())).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let state = RateLimitState {
+            limit: burst as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((burst - bucket.tokens) / rate).ceil() as u64,
+            retry_after: match allowed {
+                true => None,
+                false => Some(((1.0 - bucket.tokens) / rate).ceil() as u64)
+            }
+        };
+
+        match allowed {
+            true => Ok(state),
+            false => Err(state)
+        }
+    }
+
+    // Adds the `X-RateLimit-*` headers, and `Retry-After` once the limit is hit, to every response
+    // of a request that went through a rate limit.
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_response("Rate limit headers", |request, response| Box::pin(async move {
+            if let Some(state) = request.local_cache(|| None::<RateLimitState>) {
+                response.set_header(Header::new("X-RateLimit-Limit", state.limit.to_string()));
+                response.set_header(Header::new("X-RateLimit-Remaining", state.remaining.to_string()));
+                response.set_header(Header::new("X-RateLimit-Reset", state.reset.to_string()));
+
+                if let Some(retry_after) = state.retry_after {
+                    response.set_header(Header::new("Retry-After", retry_after.to_string()));
+                }
+            }
+        }))
+    }
+}
+
+pub trait RateLimitedGroup {
+    const GROUP :RateLimitGroup;
+}
+
+macro_rules! rate_limit_groups {
+    ($($marker:ident => $group:ident),*) => {
+        $(
+            pub struct $marker {}
+
+            impl RateLimitedGroup for $marker {
+                const GROUP :RateLimitGroup = RateLimitGroup::$group;
+            }
+        )*
+    };
+}
+
+rate_limit_groups!(
+    AuthLimit => Auth,
+    WriteLimit => Write,
+    ReadLimit => Read
+);
+
+// Charges the request against the budget of group `G`. Authenticated users get a bucket of their own,
+// everyone else shares one per address.
+pub struct RateLimit<G :RateLimitedGroup> {
+    group :PhantomData<G>
+}
+
+#[rocket::async_trait]
+impl<G :RateLimitedGroup, 'r> FromRequest<'r> for RateLimit<G> {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = match request.guard::<&State<RateLimiter>>().await {
+            Success(limiter) => limiter,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+
+        if !limiter.settings.enabled {
+            return Outcome::Success(RateLimit { group: PhantomData })
+        }
+
+        // Bad credentials are left for the auth guards to reject, and count against the address.
+        let client = match auth::authenticate(request).await {
+            Ok(Some(claim)) => format!("user:{}", claim._id),
+            _ => match client_address(request) {
+                Some(address) => format!("address:{}", address),
+                None => "address:unknown".to_string()
+            }
+        };
+
+        let result = limiter.take(G::GROUP, &client);
+        let state = match result {
+            Ok(state) | Err(state) => state
+        };
+        request.local_cache(|| Some(state));
+
+        match result {
+            Ok(_state) => Outcome::Success(RateLimit { group: PhantomData }),
+            Err(_state) => Outcome::Failure((Status::TooManyRequests, ()))
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::ApiKeyRepository, security::token};
+
+use super::role::Capability;
+
+// Makes keys easy to recognise, e.g. by secret scanners.
+const KEY_PREFIX :&str = "rkb_";
+
+#[derive(Deserialize)]
+pub struct ApiKeyWriteModel {
+    pub name :String,
+    // What the key may be used for. Never more than the user's role allows, whatever is asked for here.
+    pub capabilities :Vec<Capability>,
+    // RFC 3339 timestamp, keys without one never expire.
+    pub expires :Option<String>
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyReadModel {
+    pub _id :String,
+    pub name :String,
+    // Only ever shown once, right after the key is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key :Option<String>,
+    // The start of the key, to tell keys apart by.
+    pub prefix :String,
+    pub capabilities :Vec<Capability>,
+    pub created :String,
+    pub expires :Option<String>,
+    pub last_used :Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiKeyStoreModel {
+    pub _id :ObjectId,
+    pub user :ObjectId,
+    pub name :String,
+    pub key_hash :String,
+    pub prefix :String,
+    pub capabilities :Vec<Capability>,
+    // Whether the key was created in a session started with a second factor.
+    pub mfa :bool,
+    pub created :DateTime,
+    pub expires :Option<DateTime>,
+    pub last_used :Option<DateTime>
+}
+
+impl ApiKeyStoreModel {
+    pub fn new(key :ApiKeyWriteModel, user :ObjectId, mfa :bool) -> Result<(Self, String), ApiError> {
+        if key.name.trim().is_empty() {
+            return Err(ApiError::Validation("Key name must not be empty.".to_string()))
+        }
+
+        let expires = match key.expires.as_deref().map(DateTime::parse_rfc3339_str) {
+            Some(Ok(expires)) => Some(expires),
+            Some(Err(e)) => return Err(ApiError::Validation(e.to_string())),
+            None => None
+        };
+
+        if let Some(expires) = expires {
+            if expires <= DateTime::now() {
+                return Err(ApiError::Validation("Keys have to expire in the future.".to_string()))
+            }
+        }
+
+        let mut capabilities :Vec<Capability> = vec![];
+        for capability in key.capabilities {
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+
+        let secret = format!("{}{}", KEY_PREFIX, token::generate());
+
+        Ok((Self {
+            _id: ObjectId::new(),
+            user,
+            name: key.name.trim().to_string(),
+            key_hash: token::digest(&secret),
+            prefix: secret[..KEY_PREFIX.len() + 8].to_string(),
+            capabilities,
+            mfa,
+            created: DateTime::now(),
+            expires,
+            last_used: None
+        }, secret))
+    }
+
+    // Looks up the key a request was made with and notes that it was used.
+    pub async fn authenticate(key_ref :&dyn ApiKeyRepository, key :&str) -> Result<Option<Self>, ApiError> {
+        key_ref.authenticate(&token::digest(key)).await
+    }
+
+    pub fn to(self, key :Option<String>) -> ApiKeyReadModel {
+        ApiKeyReadModel {
+            _id: self._id.to_hex(),
+            name: self.name,
+            key,
+            prefix: self.prefix,
+            capabilities: self.capabilities,
+            created: self.created.try_to_rfc3339_string().unwrap_or_default(),
+            expires: self.expires.and_then(|expires| expires.try_to_rfc3339_string().ok()),
+            last_used: self.last_used.and_then(|last_used| last_used.try_to_rfc3339_string().ok())
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::{CommentRepository, UserRepository}};
+
+use super::{post::PostStoreModel, user::UserReadBriefModel};
+
+#[derive(Deserialize)]
+pub struct CommentWriteModel {
+    pub content :String,
+    pub parent :Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct CommentEditModel {
+    pub content :String
+}
+
+#[derive(Serialize)]
+pub struct CommentReadModel {
+    pub _id :String,
+    pub post :String,
+    pub parent :Option<String>,
+    pub content :String,
+    pub author :UserReadBriefModel,
+    pub created :String,
+    pub edited :Option<String>,
+    pub replies :Vec<CommentReadModel>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CommentStoreModel {
+    pub _id :ObjectId,
+    pub post :ObjectId,
+    pub parent :Option<ObjectId>,
+    pub content :String,
+    pub author :ObjectId,
+    pub created :DateTime,
+    pub edited :Option<DateTime>
+}
+
+impl CommentStoreModel {
+    pub fn new(comment :CommentWriteModel, post :ObjectId, parent :Option<ObjectId>, author :ObjectId) -> Self {
+        Self {
+            _id: ObjectId::new(),
+            post,
+            parent,
+            content: comment.content,
+            author,
+            created: DateTime::now(),
+            edited: None
+        }
+    }
+
+    pub async fn to(self, user_ref :&dyn UserRepository) -> Result<CommentReadModel, ApiError> {
+        let author = PostStoreModel::query_author(user_ref, &self.author).await?;
+
+        Ok(CommentReadModel {
+            _id: self._id.to_hex(),
+            post: self.post.to_hex(),
+            parent: self.parent.map(|p| p.to_hex()),
+            content: self.content,
+            author: author.brief(),
+            created: self.created.try_to_rfc3339_string().unwrap_or_default(),
+            edited: self.edited.and_then(|e| e.try_to_rfc3339_string().ok()),
+            replies: vec![]
+        })
+    }
+
+    // Arranges a flat list of comments into reply threads. Comments whose parent is gone are dropped.
+    pub fn thread(comments :Vec<CommentReadModel>) -> Vec<CommentReadModel> {
+        let mut children :HashMap<Option<String>, Vec<CommentReadModel>> = HashMap::new();
+        for comment in comments {
+            children.entry(comment.parent.clone()).or_default().push(comment);
+        }
+
+        fn attach(mut comment :CommentReadModel, children :&mut HashMap<Option<String>, Vec<CommentReadModel>>) -> CommentReadModel {
+            let replies = children.remove(&Some(comment._id.clone())).unwrap_or_default();
+            comment.replies = replies.into_iter().map(|reply| attach(reply, children)).collect();
+            comment
+        }
+
+        let roots = children.remove(&None).unwrap_or_default();
+        roots.into_iter().map(|root| attach(root, &mut children)).collect()
+    }
+
+    // Deletes the comments, together with every reply beneath them.
+    pub async fn delete_threads(comment_ref :&dyn CommentRepository, ids :Vec<ObjectId>) -> Result<(), ApiError> {
+        let mut ids = ids;
+
+        while !ids.is_empty() {
+            comment_ref.delete(&ids).await?;
+            ids = comment_ref.reply_ids(&ids).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::InviteRepository, security::token};
+
+// Invites are valid for a week unless the admin says otherwise.
+pub const DEFAULT_INVITE_LIFETIME :i64 = 7 * 24 * 3600;
+
+#[derive(Deserialize)]
+pub struct InviteWriteModel {
+    // RFC 3339 timestamp, defaults to a week from now.
+    pub expires :Option<String>,
+    // How many accounts can be registered with the code, defaults to one.
+    pub max_uses :Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct InviteReadModel {
+    pub _id :String,
+    // Only ever shown once, right after the invite is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code :Option<String>,
+    // None when the admin's account has since been deleted.
+    pub created_by :Option<String>,
+    pub created :String,
+    pub expires :String,
+    pub max_uses :u32,
+    pub uses :u32
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InviteStoreModel {
+    pub _id :ObjectId,
+    pub code_hash :String,
+    pub created_by :ObjectId,
+    pub created :DateTime,
+    pub expires :DateTime,
+    pub max_uses :u32,
+    pub uses :u32
+}
+
+impl InviteStoreModel {
+    pub fn new(invite :InviteWriteModel, created_by :ObjectId) -> Result<(Self, String), ApiError> {
+        let expires = match invite.expires.as_deref().map(DateTime::parse_rfc3339_str) {
+            Some(Ok(expires)) => expires,
+            Some(Err(e)) => return Err(ApiError::Validation(e.to_string())),
+            None => DateTime::from_millis(DateTime::now().timestamp_millis() + DEFAULT_INVITE_LIFETIME * 1000)
+        };
+
+        if expires <= DateTime::now() {
+            return Err(ApiError::Validation("Invites have to expire in the future.".to_string()))
+        }
+
+        let max_uses = invite.max_uses.unwrap_or(1);
+        if max_uses == 0 {
+            return Err(ApiError::Validation("Invites have to be usable at least once.".to_string()))
+        }
+
+        let code = token::generate();
+
+        Ok((Self {
+            _id: ObjectId::new(),
+            code_hash: token::digest(&code),
+            created_by,
+            created: DateTime::now(),
+            expires,
+            max_uses,
+            uses: 0
+        }, code))
+    }
+
+    // Uses up one registration of the invite.
+    pub async fn redeem(invite_ref :&dyn InviteRepository, code :&str) -> Result<Self, ApiError> {
+        match invite_ref.redeem(&token::digest(code)).await? {
+            Some(invite) => Ok(invite),
+            None => Err(ApiError::Forbidden("Invalid or expired invite code.".to_string()))
+        }
+    }
+
+    // Gives the use back, for when the registration it was taken for fails.
+    pub async fn release(&self, invite_ref :&dyn InviteRepository) -> Result<(), ApiError> {
+        invite_ref.release(&self._id).await
+    }
+
+    pub fn to(self, created_by :Option<String>, code :Option<String>) -> InviteReadModel {
+        InviteReadModel {
+            _id: self._id.to_hex(),
+            code,
+            created_by,
+            created: self.created.try_to_rfc3339_string().unwrap_or_default(),
+            expires: self.expires.try_to_rfc3339_string().unwrap_or_default(),
+            max_uses: self.max_uses,
+            uses: self.uses
+        }
+    }
+}
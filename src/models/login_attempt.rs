@@ -0,0 +1,118 @@
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::LoginAttemptRepository, middlewares::auth::AuthSettings};
+
+// The first lockout lasts this many seconds, and every failure after it doubles the time.
+const LOCKOUT_BASE :i64 = 30;
+const LOCKOUT_MAX :i64 = 3600;
+
+// Failed sign ins are counted both per account and per address, so that neither guessing one
+// account's password nor trying a common password on many accounts goes unnoticed.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum LoginAttemptKind {
+    Account, Address
+}
+
+impl From<LoginAttemptKind> for Bson {
+    fn from(kind :LoginAttemptKind) -> Self {
+        Bson::String(format!("{:?}", kind))
+    }
+}
+
+#[derive(Serialize)]
+pub struct LoginAttemptReadModel {
+    pub _id :String,
+    pub kind :LoginAttemptKind,
+    pub subject :String,
+    pub failures :u32,
+    pub last_failure :String,
+    pub locked_until :Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoginAttemptStoreModel {
+    pub _id :ObjectId,
+    pub kind :LoginAttemptKind,
+    // The account name or the address.
+    pub subject :String,
+    pub failures :u32,
+    // Counters are dropped a day after the last failure.
+    pub last_failure :DateTime,
+    pub locked_until :Option<DateTime>
+}
+
+impl LoginAttemptStoreModel {
+    fn subjects<'a>(name :&'a str, address :Option<&'a str>) -> Vec<(LoginAttemptKind, &'a str)> {
+        let mut subjects = vec![(LoginAttemptKind::Account, name)];
+        if let Some(address) = address {
+            subjects.push((LoginAttemptKind::Address, address));
+        }
+
+        subjects
+    }
+
+    // Refuses the attempt while the account or the address is locked out. This is checked before the
+    // password, so that a lockout can't be used to tell whether a guess was right.
+    pub async fn ensure_unlocked(attempt_ref :&dyn LoginAttemptRepository, name :&str, address :Option<&str>) -> Result<(), ApiError> {
+        for (kind, subject) in Self::subjects(name, address) {
+            let attempt = attempt_ref.find_locked(kind, subject).await?;
+
+            if let Some(locked_until) = attempt.and_then(|attempt| attempt.locked_until) {
+                let seconds = (locked_until.timestamp_millis() - DateTime::now().timestamp_millis()) / 1000 + 1;
+                return Err(ApiError::TooManyRequests(format!("Too many failed sign in attempts, try again in {} seconds.", seconds)))
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn record_failure(attempt_ref :&dyn LoginAttemptRepository, settings :&AuthSettings, name :&str, address :Option<&str>) -> Result<(), ApiError> {
+        for (kind, subject) in Self::subjects(name, address) {
+            let attempt = match attempt_ref.record_failure(kind, subject).await? {
+                Some(attempt) => attempt,
+                None => continue
+            };
+
+            let threshold = match kind {
+                LoginAttemptKind::Account => settings.max_failed_logins,
+                LoginAttemptKind::Address => settings.max_failed_logins_per_address
+            };
+
+            if attempt.failures < threshold {
+                continue
+            }
+
+            let doublings = (attempt.failures - threshold).min(16);
+            let lockout = (LOCKOUT_BASE << doublings).min(LOCKOUT_MAX);
+            let locked_until = DateTime::from_millis(DateTime::now().timestamp_millis() + lockout * 1000);
+
+            attempt_ref.lock(&attempt._id, locked_until).await?;
+
+            warn!("Sign ins locked for {:?} {} for {} seconds after {} failed attempts.", kind, subject, lockout, attempt.failures);
+        }
+
+        Ok(())
+    }
+
+    // A successful sign in wipes the account's slate. The address keeps its count, so that an attacker
+    // can't reset it by signing into an account of their own in between guesses.
+    pub async fn record_success(attempt_ref :&dyn LoginAttemptRepository, name :&str) -> Result<(), ApiError> {
+        attempt_ref.clear(LoginAttemptKind::Account, name).await
+    }
+
+    pub async fn list(attempt_ref :&dyn LoginAttemptRepository, locked_only :bool) -> Result<Vec<LoginAttemptReadModel>, ApiError> {
+        Ok(attempt_ref.list(locked_only).await?.into_iter().map(|attempt| attempt.to()).collect())
+    }
+
+    pub fn to(self) -> LoginAttemptReadModel {
+        LoginAttemptReadModel {
+            _id: self._id.to_hex(),
+            kind: self.kind,
+            subject: self.subject,
+            failures: self.failures,
+            last_failure: self.last_failure.try_to_rfc3339_string().unwrap_or_default(),
+            locked_until: self.locked_until.and_then(|locked_until| locked_until.try_to_rfc3339_string().ok())
+        }
+    }
+}
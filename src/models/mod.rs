@@ -1,2 +1,13 @@
 pub mod post;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod session;
+pub mod comment;
+pub mod page;
+pub mod revision;
+pub mod tag;
+pub mod search;
+pub mod role;
+pub mod invite;
+pub mod totp;
+pub mod api_key;
+pub mod login_attempt;
@@ -0,0 +1,137 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use mongodb::bson::{self, doc, Bson, Document};
+use rocket::serde::Serialize;
+
+use crate::errors::ApiError;
+
+pub const DEFAULT_PAGE_SIZE :u32 = 20;
+pub const MAX_PAGE_SIZE :u32 = 100;
+
+#[derive(FromForm)]
+pub struct PageQuery {
+    pub limit :Option<u32>,
+    pub cursor :Option<String>,
+    pub sort :Option<String>
+}
+
+#[derive(Serialize)]
+pub struct PageModel<T :Serialize> {
+    pub items :Vec<T>,
+    pub next_cursor :Option<String>,
+    pub total :u64
+}
+
+// Where a page starts, the sort field value and `_id` of the item right before it.
+pub struct PagePosition {
+    pub value :Bson,
+    pub id :Bson
+}
+
+// A page as the storage sees it. Items are ordered by the field, then by `_id`, in the same direction.
+pub struct PageRequest {
+    pub field :&'static str,
+    pub descending :bool,
+    pub after :Option<PagePosition>,
+    pub limit :u32
+}
+
+// A sort order accepted by a list endpoint, e.g. `title` or `-created`.
+pub struct Sort {
+    pub name :String,
+    pub field :&'static str,
+    pub descending :bool
+}
+
+impl Sort {
+    // `fields` maps the names clients may sort by to the document fields backing them.
+    pub fn parse(sort :Option<&str>, default :&str, fields :&[(&str, &'static str)]) -> Result<Self, ApiError> {
+        let sort = sort.unwrap_or(default);
+        let (name, descending) = match sort.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (sort, false)
+        };
+
+        match fields.iter().find(|(allowed, _)| *allowed == name) {
+            Some((_, field)) => Ok(Self { name: sort.to_string(), field, descending }),
+            None => Err(ApiError::Validation(format!("Cannot sort by {}.", name)))
+        }
+    }
+
+    fn cursor(&self, last :&Document) -> Result<String, ApiError> {
+        let cursor = doc!{
+            "s": &self.name,
+            "v": last.get(self.field).cloned().unwrap_or(Bson::Null),
+            "_id": last.get("_id").cloned().unwrap_or(Bson::Null)
+        };
+
+        let mut bytes = vec![];
+        match cursor.to_writer(&mut bytes) {
+            Ok(_ok) => Ok(URL_SAFE_NO_PAD.encode(bytes)),
+            Err(e) => Err(ApiError::Internal(e.to_string()))
+        }
+    }
+
+    fn decode(&self, cursor :&str) -> Result<Document, ApiError> {
+        let bytes = match URL_SAFE_NO_PAD.decode(cursor) {
+            Ok(bytes) => bytes,
+            Err(_e) => return Err(Self::invalid_cursor())
+        };
+
+        let cursor = match Document::from_reader(&mut bytes.as_slice()) {
+            Ok(cursor) => cursor,
+            Err(_e) => return Err(Self::invalid_cursor())
+        };
+
+        // A cursor only makes sense for the sort order it was created with.
+        match cursor.get_str("s") {
+            Ok(name) if name == self.name => Ok(cursor),
+            _ => Err(Self::invalid_cursor())
+        }
+    }
+
+    fn invalid_cursor() -> ApiError {
+        ApiError::Validation("Invalid cursor.".to_string())
+    }
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    // What to ask the storage for to get the page the query is after.
+    pub fn request(&self, sort :&Sort) -> Result<PageRequest, ApiError> {
+        let after = match &self.cursor {
+            Some(cursor) => {
+                let cursor = sort.decode(cursor)?;
+                match (cursor.get("v"), cursor.get("_id")) {
+                    (Some(value), Some(id)) => Some(PagePosition { value: value.clone(), id: id.clone() }),
+                    _ => return Err(Sort::invalid_cursor())
+                }
+            },
+            None => None
+        };
+
+        // Ask for one extra item to find out whether there is a next page.
+        Ok(PageRequest { field: sort.field, descending: sort.descending, after, limit: self.limit() + 1 })
+    }
+
+    // Cuts the items fetched for `request` down to the page, along with the cursor for the next page.
+    pub fn finish<T :Serialize>(&self, sort :&Sort, mut items :Vec<T>) -> Result<(Vec<T>, Option<String>), ApiError> {
+        let limit = self.limit();
+
+        let next_cursor = match items.len() > limit as usize {
+            true => {
+                items.truncate(limit as usize);
+                let last = match items.last().map(bson::to_document) {
+                    Some(Ok(last)) => last,
+                    _ => return Err(ApiError::Internal("Failed to build cursor.".to_string()))
+                };
+                Some(sort.cursor(&last)?)
+            },
+            false => None
+        };
+
+        Ok((items, next_cursor))
+    }
+}
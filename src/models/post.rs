@@ -1,90 +1,369 @@
-use mongodb::{bson::{doc, oid::ObjectId, Document}, Collection};
-use rocket::{serde::{Serialize, Deserialize}, http::Status};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+use slug::slugify;
 
-use crate::errors::ApiError;
+use crate::{errors::ApiError, render::markdown::MarkdownRenderer, db::{PostRepository, UserRepository, RevisionRepository}};
 
-use super::user::{UserReadBriefModel, UserStoreModel};
+use super::{revision::RevisionStoreModel, tag};
+use super::{user::{UserReadBriefModel, UserStoreModel, UserAuthClaimsModel}, role::Capability};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum PostStatus {
+    Draft, Scheduled, Published, Archived
+}
+
+impl PostStatus {
+    pub const ALL :[PostStatus; 4] = [PostStatus::Draft, PostStatus::Scheduled, PostStatus::Published, PostStatus::Archived];
+
+    pub fn parse(name :&str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| format!("{:?}", status) == name)
+    }
+}
+
+impl From<PostStatus> for Bson {
+    fn from(status :PostStatus) -> Self {
+        Bson::String(format!("{:?}", status))
+    }
+}
 
 #[derive(Deserialize)]
 pub struct PostWriteModel {
     pub title :String,
     pub content :String,
+    #[serde(default)]
+    pub tags :Vec<String>,
+    pub category :Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct PostStatusWriteModel {
+    pub status :PostStatus,
+    // RFC 3339 timestamp, required when scheduling a post.
+    pub publish_at :Option<String>
 }
 
 #[derive(Serialize)]
 pub struct PostReadBriefModel {
     pub _id :String,
     pub title :String,
-    pub author :String
+    pub slug :String,
+    pub author :String,
+    pub status :PostStatus,
+    pub publish_at :Option<String>,
+    pub tags :Vec<String>,
+    pub category :Option<String>
 }
 
 #[derive(Serialize)]
 pub struct PostReadFullModel {
     pub _id :String,
     pub title :String,
+    pub slug :String,
     pub content :String,
-    pub author :UserReadBriefModel
+    // The content rendered from Markdown into sanitised HTML.
+    pub content_html :String,
+    pub author :UserReadBriefModel,
+    pub status :PostStatus,
+    pub publish_at :Option<String>,
+    pub tags :Vec<String>,
+    pub category :Option<String>
 }
 
-#[derive(Serialize,Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PostStoreModel {
     pub _id :ObjectId,
     pub title :String,
+    pub slug :String,
+    // Slugs the post was previously reachable under, kept so that old links keep working.
+    #[serde(default)]
+    pub slug_history :Vec<String>,
     pub content :String,
-    pub author :ObjectId
+    pub author :ObjectId,
+    pub status :PostStatus,
+    pub publish_at :Option<DateTime>,
+    // Number of the latest revision of the post.
+    pub revision :u32,
+    #[serde(default)]
+    pub tags :Vec<String>,
+    #[serde(default)]
+    pub category :Option<String>,
+    // When the post was last edited or changed its status.
+    #[serde(default)]
+    pub updated :Option<DateTime>
+}
+
+// Which posts a query is restricted to. Only published posts are public, drafts, scheduled and archived
+// posts are visible to their authors and to whoever may edit any post.
+#[derive(PartialEq, Clone, Debug)]
+pub enum PostVisibility {
+    Published,
+    PublishedOrAuthor(ObjectId),
+    All
+}
+
+// The posts a list, search, tag count or feed is made of.
+#[derive(Clone, Debug)]
+pub struct PostFilter {
+    pub visibility :PostVisibility,
+    // Without a status, archived posts are left out. They are only listed when asked for explicitly.
+    pub status :Option<PostStatus>,
+    pub author :Option<ObjectId>,
+    pub tag :Option<String>,
+    pub category :Option<String>
+}
+
+impl PostFilter {
+    pub fn visible_to(claim :Option<&UserAuthClaimsModel>) -> Self {
+        Self {
+            visibility: PostStoreModel::visibility(claim),
+            status: None,
+            author: None,
+            tag: None,
+            category: None
+        }
+    }
+
+    pub fn matches(&self, post :&PostStoreModel) -> bool {
+        let visible = match &self.visibility {
+            PostVisibility::Published => post.status == PostStatus::Published,
+            PostVisibility::PublishedOrAuthor(author) => post.status == PostStatus::Published || &post.author == author,
+            PostVisibility::All => true
+        };
+
+        let status = match self.status {
+            Some(status) => post.status == status,
+            None => post.status != PostStatus::Archived
+        };
+
+        visible && status
+            && self.author.iter().all(|author| &post.author == author)
+            && self.tag.iter().all(|tag| post.tags.contains(tag))
+            && self.category.iter().all(|category| post.category.as_ref() == Some(category))
+    }
 }
 
 impl PostStoreModel {
-    pub async fn query_author(user_ref :&Collection<UserStoreModel>, filter :Document) -> Result<UserStoreModel, ApiError> {
-        match user_ref.find_one(filter, None).await {
-            Ok(maybe_user) => match maybe_user {
-                Some(user) => Ok(user),
-                None => Err(ApiError { status: Status::NotFound, message: format!("User not found.") })
-            }
-            Err(e) => Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    pub async fn query_author(user_ref :&dyn UserRepository, id :&ObjectId) -> Result<UserStoreModel, ApiError> {
+        match user_ref.find(id).await? {
+            Some(user) => Ok(user),
+            None => Err(ApiError::NotFound("User not found.".to_string()))
         }
     }
 
-    pub async fn new(post :PostWriteModel, user_ref :&Collection<UserStoreModel>, author :&str) -> Result<Self, ApiError> {
-        let author = Self::query_author(user_ref, doc!{"name": author}).await?;
+    pub async fn query_author_by_name(user_ref :&dyn UserRepository, name :&str) -> Result<UserStoreModel, ApiError> {
+        match user_ref.find_by_name(name).await? {
+            Some(user) => Ok(user),
+            None => Err(ApiError::NotFound("User not found.".to_string()))
+        }
+    }
+
+    // Finds a post by its `_id`, its current slug, or any slug it was previously known under.
+    pub async fn find(post_ref :&dyn PostRepository, key :&str) -> Result<Self, ApiError> {
+        match post_ref.find(key).await? {
+            Some(post) => Ok(post),
+            None => Err(ApiError::NotFound(format!("Post {} not found.", key)))
+        }
+    }
+
+    // Restricts a post query to the posts the user is allowed to see.
+    pub fn visibility(claim :Option<&UserAuthClaimsModel>) -> PostVisibility {
+        let claim = match claim {
+            Some(claim) => claim,
+            None => return PostVisibility::Published
+        };
+
+        if claim.can(Capability::PostEditAny) {
+            return PostVisibility::All
+        }
+
+        match ObjectId::parse_str(&claim._id) {
+            Ok(id) => PostVisibility::PublishedOrAuthor(id),
+            Err(_e) => PostVisibility::Published
+        }
+    }
+
+    // Archived posts are no longer listed, but stay reachable by a direct link.
+    pub fn is_visible_to(&self, claim :Option<&UserAuthClaimsModel>) -> bool {
+        match (self.status, claim) {
+            (PostStatus::Published | PostStatus::Archived, _) => true,
+            (PostStatus::Draft | PostStatus::Scheduled, Some(claim)) => self.author.to_hex() == claim._id
+                || claim.can(Capability::PostEditAny),
+            (PostStatus::Draft | PostStatus::Scheduled, None) => false
+        }
+    }
+
+    pub fn transition(&mut self, to :PostStatusWriteModel) -> Result<(), ApiError> {
+        let conflict = |message :String| Err(ApiError::Conflict(message));
+
+        match (self.status, to.status) {
+            (PostStatus::Draft | PostStatus::Scheduled, PostStatus::Scheduled) => {
+                let publish_at = match to.publish_at.as_deref().map(DateTime::parse_rfc3339_str) {
+                    Some(Ok(publish_at)) => publish_at,
+                    Some(Err(e)) => return Err(ApiError::Validation(e.to_string())),
+                    None => return Err(ApiError::Validation("Scheduling a post requires publish_at.".to_string()))
+                };
+
+                if publish_at <= DateTime::now() {
+                    return Err(ApiError::Validation("publish_at must be in the future.".to_string()))
+                }
+
+                self.publish_at = Some(publish_at);
+            },
+            (PostStatus::Draft | PostStatus::Scheduled, PostStatus::Published) => self.publish_at = Some(DateTime::now()),
+            // Republishing an archived post keeps its original publication date, if it ever had one that passed.
+            (PostStatus::Archived, PostStatus::Published) => {
+                let now = DateTime::now();
+                match self.publish_at {
+                    Some(publish_at) if publish_at <= now => (),
+                    _ => self.publish_at = Some(now)
+                }
+            },
+            (PostStatus::Published | PostStatus::Archived, PostStatus::Scheduled) =>
+                return conflict("Only drafts can be scheduled, unpublish the post first.".to_string()),
+            (PostStatus::Draft, PostStatus::Draft) | (PostStatus::Published, PostStatus::Published) | (PostStatus::Archived, PostStatus::Archived) =>
+                return conflict(format!("Post is already {:?}.", to.status)),
+            (_, PostStatus::Draft) => self.publish_at = None,
+            (_, PostStatus::Archived) => ()
+        }
+
+        self.status = to.status;
+        self.updated = Some(DateTime::now());
+        Ok(())
+    }
+
+    // Whether the post was looked up by a slug it is no longer published under.
+    pub fn is_moved(&self, key :&str) -> bool {
+        key != self.slug && key != self._id.to_hex()
+    }
+
+    // Fills in fields introduced after a post was created. Posts created before slugs existed get one
+    // generated from their title, posts created before the publishing lifecycle were all public, and
+    // posts created before revisions were tracked start their history with their current content.
+    pub async fn backfill(post_ref :&dyn PostRepository, revision_ref :&dyn RevisionRepository) -> Result<(), ApiError> {
+        post_ref.backfill().await?;
+
+        for mut post in post_ref.unrevisioned().await? {
+            post.revision = 1;
+            let author = post.author;
+            post.commit(post_ref, revision_ref, author, None).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn unique_slug(post_ref :&dyn PostRepository, title :&str, id :&ObjectId) -> Result<String, ApiError> {
+        let mut base = slugify(title);
+        // Slugs must never be mistaken for an `_id`.
+        if base.is_empty() || ObjectId::parse_str(&base).is_ok() {
+            base = format!("post-{}", base).trim_end_matches('-').to_string();
+        }
+
+        let mut slug = base.clone();
+        let mut n = 1;
+        while post_ref.slug_taken(&slug, id).await? {
+            n += 1;
+            slug = format!("{}-{}", base, n);
+        }
+
+        Ok(slug)
+    }
+
+    pub async fn new(post :PostWriteModel, post_ref :&dyn PostRepository, user_ref :&dyn UserRepository, author :&str) -> Result<Self, ApiError> {
+        let author = Self::query_author_by_name(user_ref, author).await?;
+        let id = ObjectId::new();
 
         Ok(Self {
-            _id: ObjectId::new(),
+            _id: id,
+            slug: Self::unique_slug(post_ref, &post.title, &id).await?,
+            slug_history: vec![],
             title: post.title,
             content: post.content,
-            author: author._id
+            author: author._id,
+            status: PostStatus::Draft,
+            publish_at: None,
+            revision: 1,
+            tags: tag::normalize_all(post.tags),
+            category: Self::normalize_category(post.category),
+            updated: Some(DateTime::now())
         })
     }
 
-    pub async fn from(post :PostWriteModel, id :ObjectId, user_ref :&Collection<UserStoreModel>, author :&str) -> Result<Self, ApiError> {
-        let author = Self::query_author(user_ref, doc!{"name": author}).await?;
+    // The next revision of `origin`. The post stays with its author whoever edits it, the editor is
+    // recorded with the revision, see `commit`.
+    pub async fn from(post :PostWriteModel, origin :&PostStoreModel, post_ref :&dyn PostRepository) -> Result<Self, ApiError> {
+        // Renaming a post moves it to a new slug, the old one keeps redirecting to it.
+        let (slug, mut slug_history) = match post.title == origin.title {
+            true => (origin.slug.clone(), origin.slug_history.clone()),
+            false => {
+                let slug = Self::unique_slug(post_ref, &post.title, &origin._id).await?;
+                let mut slug_history = origin.slug_history.clone();
+                slug_history.push(origin.slug.clone());
+                (slug, slug_history)
+            }
+        };
+        slug_history.retain(|old| old != &slug);
 
         Ok(Self {
-            _id: id,
+            _id: origin._id,
             title: post.title,
+            slug,
+            slug_history,
             content: post.content,
-            author: author._id
+            author: origin.author,
+            status: origin.status,
+            publish_at: origin.publish_at,
+            revision: origin.revision + 1,
+            tags: tag::normalize_all(post.tags),
+            category: Self::normalize_category(post.category),
+            updated: Some(DateTime::now())
         })
     }
 
-    pub async fn brief(self, user_ref :&Collection<UserStoreModel>) -> Result<PostReadBriefModel, ApiError> {
-        let author = Self::query_author(user_ref, doc!{"_id": &self.author}).await?;
+    fn normalize_category(category :Option<String>) -> Option<String> {
+        category.map(|category| category.trim().to_string()).filter(|category| !category.is_empty())
+    }
+
+    // Stores this new revision of the post in place of the previous one and appends it to the revision
+    // history. Fails if someone else revised the post in the meantime.
+    pub async fn commit(&self, post_ref :&dyn PostRepository, revision_ref :&dyn RevisionRepository, editor :ObjectId, restored_from :Option<u32>) -> Result<(), ApiError> {
+        if !post_ref.replace(self).await? {
+            return Err(ApiError::Conflict("The post was modified concurrently, please retry.".to_string()))
+        }
+
+        RevisionStoreModel::record(revision_ref, self, editor, restored_from).await?;
+        Ok(())
+    }
+
+    pub async fn brief(self, user_ref :&dyn UserRepository) -> Result<PostReadBriefModel, ApiError> {
+        let author = Self::query_author(user_ref, &self.author).await?;
 
         Ok(PostReadBriefModel {
             _id: self._id.to_hex(),
             title: self.title,
-            author: author.name
+            slug: self.slug,
+            author: author.name,
+            status: self.status,
+            publish_at: self.publish_at.and_then(|p| p.try_to_rfc3339_string().ok()),
+            tags: self.tags,
+            category: self.category
         })
     }
 
-    pub async fn to(self, user_ref :&Collection<UserStoreModel>) -> Result<PostReadFullModel, ApiError> {
-        let author = Self::query_author(user_ref, doc!{"_id": &self.author}).await?;
+    pub async fn to(self, user_ref :&dyn UserRepository, renderer :&MarkdownRenderer) -> Result<PostReadFullModel, ApiError> {
+        let author = Self::query_author(user_ref, &self.author).await?;
+        let content_html = renderer.render(&self._id, self.revision, &self.content).to_string();
 
         Ok(PostReadFullModel {
             _id: self._id.to_hex(),
             title: self.title,
+            slug: self.slug,
             content: self.content,
-            author: author.brief()
+            content_html,
+            author: author.brief(),
+            status: self.status,
+            publish_at: self.publish_at.and_then(|p| p.try_to_rfc3339_string().ok()),
+            tags: self.tags,
+            category: self.category
         })
     }
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+use similar::{ChangeTag, TextDiff};
+
+use crate::{errors::ApiError, db::{RevisionRepository, UserRepository}};
+
+use super::post::PostStoreModel;
+
+#[derive(Serialize)]
+pub struct RevisionReadBriefModel {
+    pub number :u32,
+    pub title :String,
+    // None when the editor's account has since been deleted.
+    pub author :Option<String>,
+    pub created :String,
+    pub restored_from :Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct RevisionReadFullModel {
+    pub number :u32,
+    pub title :String,
+    pub content :String,
+    pub author :Option<String>,
+    pub created :String,
+    pub restored_from :Option<u32>
+}
+
+#[derive(Serialize, PartialEq)]
+pub enum DiffOperation {
+    Equal, Insert, Delete
+}
+
+#[derive(Serialize)]
+pub struct DiffLineModel {
+    pub op :DiffOperation,
+    pub text :String
+}
+
+#[derive(Serialize)]
+pub struct RevisionDiffModel {
+    pub from :u32,
+    pub to :u32,
+    pub title :Vec<DiffLineModel>,
+    pub content :Vec<DiffLineModel>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RevisionStoreModel {
+    pub _id :ObjectId,
+    pub post :ObjectId,
+    pub number :u32,
+    pub title :String,
+    pub content :String,
+    pub author :ObjectId,
+    pub created :DateTime,
+    // The revision this one was restored from, if any.
+    pub restored_from :Option<u32>
+}
+
+impl RevisionStoreModel {
+    // Appends the current state of the post to its revision history.
+    pub async fn record(revision_ref :&dyn RevisionRepository, post :&PostStoreModel, author :ObjectId, restored_from :Option<u32>) -> Result<Self, ApiError> {
+        let revision = Self {
+            _id: ObjectId::new(),
+            post: post._id,
+            number: post.revision,
+            title: post.title.clone(),
+            content: post.content.clone(),
+            author,
+            created: DateTime::now(),
+            restored_from
+        };
+
+        revision_ref.insert(&revision).await?;
+        Ok(revision)
+    }
+
+    pub async fn find(revision_ref :&dyn RevisionRepository, post :&ObjectId, number :u32) -> Result<Self, ApiError> {
+        match revision_ref.find(post, number).await? {
+            Some(revision) => Ok(revision),
+            None => Err(ApiError::NotFound(format!("Revision {} not found.", number)))
+        }
+    }
+
+    async fn query_author(user_ref :&dyn UserRepository, author :&ObjectId) -> Result<Option<String>, ApiError> {
+        Ok(user_ref.find(author).await?.map(|user| user.name))
+    }
+
+    pub async fn brief(self, user_ref :&dyn UserRepository) -> Result<RevisionReadBriefModel, ApiError> {
+        Ok(RevisionReadBriefModel {
+            number: self.number,
+            author: Self::query_author(user_ref, &self.author).await?,
+            title: self.title,
+            created: self.created.try_to_rfc3339_string().unwrap_or_default(),
+            restored_from: self.restored_from
+        })
+    }
+
+    pub async fn to(self, user_ref :&dyn UserRepository) -> Result<RevisionReadFullModel, ApiError> {
+        Ok(RevisionReadFullModel {
+            number: self.number,
+            author: Self::query_author(user_ref, &self.author).await?,
+            title: self.title,
+            content: self.content,
+            created: self.created.try_to_rfc3339_string().unwrap_or_default(),
+            restored_from: self.restored_from
+        })
+    }
+
+    pub fn diff(&self, to :&RevisionStoreModel) -> RevisionDiffModel {
+        RevisionDiffModel {
+            from: self.number,
+            to: to.number,
+            title: Self::diff_lines(&self.title, &to.title),
+            content: Self::diff_lines(&self.content, &to.content)
+        }
+    }
+
+    fn diff_lines(old :&str, new :&str) -> Vec<DiffLineModel> {
+        TextDiff::from_lines(old, new).iter_all_changes().map(|change| DiffLineModel {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOperation::Equal,
+                ChangeTag::Insert => DiffOperation::Insert,
+                ChangeTag::Delete => DiffOperation::Delete
+            },
+            text: change.value().trim_end_matches('\n').to_string()
+        }).collect()
+    }
+}
@@ -0,0 +1,123 @@
+use mongodb::bson::oid::ObjectId;
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::RoleRepository};
+
+use super::user::UserPermissionLevel;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum Capability {
+    #[serde(rename = "post:create")]
+    PostCreate,
+    #[serde(rename = "post:publish")]
+    PostPublish,
+    #[serde(rename = "post:edit_any")]
+    PostEditAny,
+    #[serde(rename = "post:delete_any")]
+    PostDeleteAny,
+    #[serde(rename = "comment:create")]
+    CommentCreate,
+    #[serde(rename = "comment:moderate")]
+    CommentModerate,
+    #[serde(rename = "tag:manage")]
+    TagManage,
+    #[serde(rename = "user:manage")]
+    UserManage,
+    #[serde(rename = "role:manage")]
+    RoleManage
+}
+
+impl Capability {
+    pub const ALL :[Capability; 9] = [
+        Capability::PostCreate, Capability::PostPublish, Capability::PostEditAny, Capability::PostDeleteAny,
+        Capability::CommentCreate, Capability::CommentModerate, Capability::TagManage, Capability::UserManage, Capability::RoleManage
+    ];
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoleReadModel {
+    pub name :UserPermissionLevel,
+    pub capabilities :Vec<Capability>
+}
+
+#[derive(Deserialize)]
+pub struct RoleWriteModel {
+    pub capabilities :Vec<Capability>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleStoreModel {
+    pub _id :ObjectId,
+    pub name :UserPermissionLevel,
+    pub capabilities :Vec<Capability>
+}
+
+impl RoleStoreModel {
+    // What every role is allowed to do out of the box, before an admin changes anything.
+    pub fn defaults(role :UserPermissionLevel) -> Vec<Capability> {
+        match role {
+            UserPermissionLevel::Reader => vec![Capability::CommentCreate],
+            UserPermissionLevel::Author => vec![Capability::PostCreate, Capability::PostPublish, Capability::CommentCreate],
+            UserPermissionLevel::Editor => vec![
+                Capability::PostCreate, Capability::PostPublish, Capability::PostEditAny, Capability::PostDeleteAny,
+                Capability::CommentCreate, Capability::TagManage
+            ],
+            UserPermissionLevel::Moderator => vec![Capability::CommentCreate, Capability::CommentModerate],
+            UserPermissionLevel::Admin => Capability::ALL.to_vec()
+        }
+    }
+
+    // Creates the roles that don't exist yet with their default capabilities. Roles that were already
+    // edited are left alone.
+    pub async fn seed(role_ref :&dyn RoleRepository) -> Result<(), ApiError> {
+        for role in UserPermissionLevel::ALL {
+            role_ref.insert_missing(role, &Self::defaults(role)).await?;
+        }
+
+        Ok(())
+    }
+
+    // Admins can always do everything, so that they can't lock themselves out by editing roles.
+    pub async fn capabilities(role_ref :&dyn RoleRepository, role :UserPermissionLevel) -> Result<Vec<Capability>, ApiError> {
+        if role == UserPermissionLevel::Admin {
+            return Ok(Capability::ALL.to_vec())
+        }
+
+        match role_ref.find(role).await? {
+            Some(role) => Ok(role.capabilities),
+            None => Ok(Self::defaults(role))
+        }
+    }
+
+    pub async fn list(role_ref :&dyn RoleRepository) -> Result<Vec<RoleReadModel>, ApiError> {
+        let mut roles :Vec<RoleReadModel> = role_ref.list().await?.into_iter().map(|role| role.to()).collect();
+
+        // Listed from the least to the most privileged role.
+        roles.sort_by_key(|role| UserPermissionLevel::ALL.iter().position(|level| *level == role.name));
+
+        Ok(roles)
+    }
+
+    pub async fn update(role_ref :&dyn RoleRepository, role :UserPermissionLevel, update :RoleWriteModel) -> Result<RoleReadModel, ApiError> {
+        if role == UserPermissionLevel::Admin {
+            return Err(ApiError::Validation("The Admin role always has every capability.".to_string()))
+        }
+
+        let mut capabilities :Vec<Capability> = vec![];
+        for capability in update.capabilities {
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+
+        role_ref.set_capabilities(role, &capabilities).await?;
+        Ok(RoleReadModel { name: role, capabilities })
+    }
+
+    pub fn to(self) -> RoleReadModel {
+        RoleReadModel {
+            name: self.name,
+            capabilities: self.capabilities
+        }
+    }
+}
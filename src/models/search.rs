@@ -0,0 +1,94 @@
+use rocket::serde::Serialize;
+
+use super::post::{PostReadBriefModel, PostStoreModel};
+
+// Characters of context shown around the first match in a snippet.
+const SNIPPET_LEAD :usize = 60;
+const SNIPPET_LENGTH :usize = 200;
+
+// Backends without a text index of their own score matches like the MongoDB one weighs them, a match in
+// the title counts five times as much as one in the content.
+pub const TITLE_WEIGHT :i64 = 5;
+
+#[derive(Serialize)]
+pub struct PostSearchResultModel {
+    #[serde(flatten)]
+    pub post :PostReadBriefModel,
+    pub score :f64,
+    // Excerpt of the content around the first match, HTML-escaped, with matches wrapped in `<mark>`.
+    pub snippet :String
+}
+
+// A post matching a search, along with how well it matches.
+pub struct PostSearchHit {
+    pub post :PostStoreModel,
+    pub score :f64
+}
+
+// The words to highlight in results. Negated terms (`-word`) are left out, since they can't appear in a match.
+pub fn terms(q :&str) -> Vec<Vec<char>> {
+    q.split_whitespace()
+        .filter(|term| !term.starts_with('-'))
+        .map(|term| term.trim_matches(|c :char| !c.is_alphanumeric()).to_lowercase().chars().collect::<Vec<char>>())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+// The words a match must not contain, written as `-word`.
+pub fn negated_terms(q :&str) -> Vec<String> {
+    q.split_whitespace()
+        .filter_map(|term| term.strip_prefix('-'))
+        .map(|term| term.trim_matches(|c :char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+pub fn snippet(content :&str, terms :&[Vec<char>]) -> String {
+    let chars :Vec<char> = content.chars().collect();
+    let lower :Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+
+    let matches_at = |i :usize| terms.iter().find(|term| lower[i..].starts_with(term)).map(|term| term.len());
+
+    let first = (0..lower.len()).find(|i| matches_at(*i).is_some()).unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_LEAD);
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+
+    let mut i = start;
+    while i < end {
+        match matches_at(i) {
+            Some(len) => {
+                let len = len.min(end - i);
+                snippet.push_str("<mark>");
+                chars[i..i + len].iter().for_each(|c| push_escaped(&mut snippet, *c));
+                snippet.push_str("</mark>");
+                i += len;
+            },
+            None => {
+                push_escaped(&mut snippet, chars[i]);
+                i += 1;
+            }
+        }
+    }
+
+    if end < chars.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+fn push_escaped(out :&mut String, c :char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&#39;"),
+        c => out.push(c)
+    }
+}
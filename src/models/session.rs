@@ -0,0 +1,49 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::security::token;
+
+// Refresh tokens are valid for 30 days after their last rotation.
+pub const REFRESH_TOKEN_LIFETIME :i64 = 30 * 24 * 3600;
+
+#[derive(Deserialize)]
+pub struct SessionRefreshModel {
+    pub refresh_token :String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SessionStoreModel {
+    pub _id :ObjectId,
+    pub user :ObjectId,
+    pub token_hash :String,
+    // Digests of refresh tokens that were already rotated out. Presenting one of them again
+    // means the token was stolen, so the whole session gets revoked.
+    pub used_hashes :Vec<String>,
+    pub revoked :bool,
+    // Whether the user signed in with a second factor. Carried over into every token of the session.
+    #[serde(default)]
+    pub mfa :bool,
+    pub created :DateTime,
+    pub expires :DateTime
+}
+
+impl SessionStoreModel {
+    pub fn new(user :ObjectId, mfa :bool) -> (Self, String) {
+        let refresh_token = token::generate();
+
+        (Self {
+            _id: ObjectId::new(),
+            user,
+            token_hash: token::digest(&refresh_token),
+            used_hashes: vec![],
+            revoked: false,
+            mfa,
+            created: DateTime::now(),
+            expires: Self::expiry()
+        }, refresh_token)
+    }
+
+    pub fn expiry() -> DateTime {
+        DateTime::from_millis(DateTime::now().timestamp_millis() + REFRESH_TOKEN_LIFETIME * 1000)
+    }
+}
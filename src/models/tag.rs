@@ -0,0 +1,40 @@
+use rocket::serde::{Serialize, Deserialize};
+use slug::slugify;
+
+use crate::{errors::ApiError, db::PostRepository};
+
+#[derive(Serialize, Deserialize)]
+pub struct TagReadModel {
+    pub name :String,
+    pub posts :u64
+}
+
+#[derive(Deserialize)]
+pub struct TagRenameModel {
+    pub name :String
+}
+
+#[derive(Deserialize)]
+pub struct TagMergeModel {
+    pub into :String
+}
+
+// Tags are kept as lowercase, URL-safe slugs, so that `Rust` and `rust ` end up being the same tag.
+pub fn normalize(tag :&str) -> String {
+    slugify(tag)
+}
+
+pub fn normalize_all(tags :Vec<String>) -> Vec<String> {
+    let mut normalized :Vec<String> = vec![];
+    for tag in tags.iter().map(|tag| normalize(tag)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    normalized
+}
+
+pub async fn exists(post_ref :&dyn PostRepository, tag :&str) -> Result<bool, ApiError> {
+    Ok(post_ref.count_tag(tag).await? > 0)
+}
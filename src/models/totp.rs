@@ -0,0 +1,88 @@
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, db::UserRepository, security::{totp, token}};
+
+use super::user::UserStoreModel;
+
+const RECOVERY_CODES :usize = 10;
+
+#[derive(Serialize)]
+pub struct TotpEnrolmentModel {
+    pub secret :String,
+    // For authenticator apps, usually shown as a QR code.
+    pub uri :String
+}
+
+#[derive(Deserialize)]
+pub struct TotpCodeModel {
+    // Either a code from the authenticator app, or one of the recovery codes.
+    pub code :String
+}
+
+#[derive(Serialize)]
+pub struct TotpRecoveryCodesModel {
+    pub recovery_codes :Vec<String>
+}
+
+// Second step of signing in, for accounts with two-factor authentication.
+#[derive(Deserialize)]
+pub struct TotpSignInModel {
+    pub mfa_token :String,
+    pub code :String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TotpStoreModel {
+    pub secret :String,
+    // False until the user confirmed the enrolment with a first code.
+    pub enabled :bool,
+    // The last time step a code was accepted for. Codes can't be used twice.
+    pub last_step :i64,
+    // Digests of the recovery codes that are still unused.
+    pub recovery_codes :Vec<String>
+}
+
+impl TotpStoreModel {
+    pub fn enrol() -> Self {
+        Self {
+            secret: totp::generate_secret(),
+            enabled: false,
+            last_step: -1,
+            recovery_codes: vec![]
+        }
+    }
+
+    // Returns the codes in plain text, which are only ever shown this once, and the digests to store.
+    pub fn recovery_codes() -> (Vec<String>, Vec<String>) {
+        let codes :Vec<String> = (0..RECOVERY_CODES).map(|_i| {
+            let code = token::generate();
+            format!("{}-{}-{}-{}", &code[0..4], &code[4..8], &code[8..12], &code[12..16])
+        }).collect();
+
+        let digests = codes.iter().map(|code| token::digest(code)).collect();
+        (codes, digests)
+    }
+
+    // Checks a code against the user's enrolment and uses it up. The update is conditional on the
+    // stored state, so that the same code can't be redeemed twice by concurrent requests.
+    pub async fn redeem(user_ref :&dyn UserRepository, user :&UserStoreModel, code :&str) -> Result<bool, ApiError> {
+        let enrolment = match &user.totp {
+            Some(enrolment) => enrolment,
+            None => return Ok(false)
+        };
+
+        let now = jsonwebtoken::get_current_timestamp();
+        match totp::verify(&enrolment.secret, code, now) {
+            Some(step) if (step as i64) > enrolment.last_step => user_ref.advance_totp_step(&user._id, enrolment.last_step, step as i64).await,
+            Some(_step) => Ok(false),
+            None => {
+                let digest = token::digest(code.trim().to_lowercase().as_str());
+                if !enrolment.enabled || !enrolment.recovery_codes.contains(&digest) {
+                    return Ok(false)
+                }
+
+                user_ref.use_recovery_code(&user._id, &digest).await
+            }
+        }
+    }
+}
@@ -1,10 +1,39 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, Bson};
 use rocket::serde::{Serialize, Deserialize};
-use sha256::digest;
 
-#[derive(Serialize, Deserialize, PartialEq)]
+use crate::{errors::ApiError, db::UserRepository, security::password::{self, PasswordVerification}};
+
+use super::{role::Capability, totp::TotpStoreModel};
+
+// The role of a user. What each role is allowed to do is stored with the roles, see `RoleStoreModel`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
 pub enum UserPermissionLevel {
-    User, Admin
+    Reader,
+    // Users registered before roles were introduced were plain `User`s. Also the role of users
+    // who sign up on their own.
+    #[serde(alias = "User")]
+    #[default]
+    Author,
+    Editor,
+    Moderator,
+    Admin
+}
+
+impl UserPermissionLevel {
+    pub const ALL :[UserPermissionLevel; 5] = [
+        UserPermissionLevel::Reader, UserPermissionLevel::Author, UserPermissionLevel::Editor,
+        UserPermissionLevel::Moderator, UserPermissionLevel::Admin
+    ];
+
+    pub fn parse(name :&str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| format!("{:?}", level).eq_ignore_ascii_case(name))
+    }
+}
+
+impl From<UserPermissionLevel> for Bson {
+    fn from(level :UserPermissionLevel) -> Self {
+        Bson::String(format!("{:?}", level))
+    }
 }
 
 #[derive(Deserialize)]
@@ -12,16 +41,24 @@ pub struct UserWriteModel {
     pub name :String,
     pub password :String,
     pub permissions :UserPermissionLevel,
+    #[serde(default)]
+    pub email :Option<String>,
     
     pub bio :String
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct UserStoreModel {
     pub _id :ObjectId,
     pub name :String,
     pub password_hash :String,
     pub permissions :UserPermissionLevel,
+    #[serde(default)]
+    pub email :Option<String>,
+    #[serde(default)]
+    pub email_verified :bool,
+    #[serde(default)]
+    pub totp :Option<TotpStoreModel>,
 
     pub bio :String
 }
@@ -42,48 +79,163 @@ pub struct UserReadFullModel {
     pub bio :String
 }
 
+// Self-service sign up. There's deliberately no way to pick a role here, new users always get the default one.
+#[derive(Deserialize)]
+pub struct UserRegisterModel {
+    pub name :String,
+    pub password :String,
+    #[serde(default)]
+    pub email :Option<String>,
+    #[serde(default)]
+    pub bio :String,
+    pub invite :String
+}
+
+#[derive(Deserialize)]
+pub struct UserForgotModel {
+    pub email :String
+}
+
+#[derive(Deserialize)]
+pub struct UserResetModel {
+    pub token :String,
+    pub password :String
+}
+
+#[derive(Deserialize)]
+pub struct UserVerifyModel {
+    pub token :String
+}
+
 #[derive(Deserialize)]
 pub struct UserAuthModel {
     pub name :String,
     pub password :String
 }
 
+// Returned instead of the tokens when the account needs a second factor to sign in.
+#[derive(Serialize)]
+pub struct UserMfaChallengeModel {
+    pub mfa_token :String
+}
+
 #[derive(Serialize)]
 pub struct UserAuthResponseModel {
-    pub token :String
+    pub token :String,
+    pub refresh_token :String
 }
 
-#[derive(Serialize,Deserialize)]
+#[derive(Serialize,Deserialize,Clone)]
 pub struct UserAuthClaimsModel {
     pub exp :u64,
+    pub sid :String,
     pub _id :String,
     pub name :String,
+    // The role at sign in. Requests go by the one the user has now, see `validate_token`.
     pub permissions :UserPermissionLevel,
+    // Whether the session was started with a second factor.
+    #[serde(default)]
+    pub mfa :bool,
+    // Resolved from the user's role on every request rather than baked into the token, so that
+    // changes to a role take effect right away.
+    #[serde(skip)]
+    pub capabilities :Vec<Capability>,
+    // The API key the request was made with, if it wasn't made with a token.
+    #[serde(skip)]
+    pub api_key :Option<String>
+}
+
+impl UserAuthClaimsModel {
+    pub fn can(&self, capability :Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    // Account settings and credentials need a signed in user, whatever the scope of a key, so that a
+    // leaked key can't be turned into a password or a second factor.
+    pub fn require_session(&self) -> Result<(), ApiError> {
+        match self.api_key {
+            Some(_) => Err(ApiError::Forbidden("This can't be done with an API key.".to_string())),
+            None => Ok(())
+        }
+    }
+
+    // Admin accounts are only made, changed or removed by admins, or by whoever may edit the roles
+    // anyway. Otherwise handing out user:manage would hand out everything.
+    pub fn require_admin_control(&self) -> Result<(), ApiError> {
+        match self.permissions == UserPermissionLevel::Admin || self.can(Capability::RoleManage) {
+            true => Ok(()),
+            false => Err(ApiError::Forbidden("Only admins can manage admin accounts.".to_string()))
+        }
+    }
 }
 
 impl UserStoreModel {
-    pub fn new(user :UserWriteModel) -> Self {
-        Self {
+    pub fn new(user :UserWriteModel) -> Result<Self, ApiError> {
+        Ok(Self {
             _id: ObjectId::new(),
             name: user.name,
-            password_hash: digest(user.password),
+            password_hash: password::hash(&user.password)?,
             permissions: user.permissions,
+            email: Self::normalize_email(user.email)?,
+            email_verified: false,
+            totp: None,
 
             bio: user.bio
-        }
+        })
     }
 
-    pub fn from(user :UserWriteModel, id :ObjectId) -> Self {
-        Self {
-            _id: id,
+    pub fn from(user :UserWriteModel, origin :&UserStoreModel) -> Result<Self, ApiError> {
+        let email = Self::normalize_email(user.email)?;
+
+        Ok(Self {
+            _id: origin._id,
             name: user.name,
-            password_hash: digest(user.password),
+            password_hash: password::hash(&user.password)?,
             permissions: user.permissions,
+            // A new address has to be confirmed again.
+            email_verified: origin.email_verified && email == origin.email,
+            email,
+            totp: origin.totp.clone(),
 
             bio: user.bio
+        })
+    }
+
+    // Addresses are compared case-insensitively, and only checked for the obvious mistakes. Whether
+    // they actually work is up to the verification email.
+    pub fn normalize_email(email :Option<String>) -> Result<Option<String>, ApiError> {
+        let email = match email {
+            Some(email) => email.trim().to_lowercase(),
+            None => return Ok(None)
+        };
+
+        if email.is_empty() {
+            return Ok(None)
+        }
+
+        match email.split_once('@') {
+            Some((local, domain)) if !local.is_empty() && domain.contains('.') && !email.contains(char::is_whitespace) => Ok(Some(email)),
+            _ => Err(ApiError::Validation(format!("{} is not a valid email address.", email)))
         }
     }
 
+    // Every address can only belong to one account, since it's what passwords are reset by.
+    pub async fn check_email(user_ref :&dyn UserRepository, user :&UserStoreModel) -> Result<(), ApiError> {
+        let email = match &user.email {
+            Some(email) => email,
+            None => return Ok(())
+        };
+
+        match user_ref.find_by_email(email).await? {
+            Some(other) if other._id != user._id => Err(ApiError::Conflict(format!("Email {} is already in use.", email))),
+            _ => Ok(())
+        }
+    }
+
+    pub fn has_totp(&self) -> bool {
+        matches!(&self.totp, Some(totp) if totp.enabled)
+    }
+
     pub fn brief(self) -> UserReadBriefModel {
         UserReadBriefModel {
             _id: self._id.to_hex(),
@@ -102,7 +254,7 @@ impl UserStoreModel {
         }
     }
 
-    pub fn authenticate(&self, password :&str) -> bool {
-        digest(password) == self.password_hash
+    pub fn authenticate(&self, password :&str) -> PasswordVerification {
+        password::verify(password, &self.password_hash)
     }
 }
\ No newline at end of file
@@ -0,0 +1,155 @@
+use chrono::{TimeZone, Utc};
+use mongodb::bson::DateTime;
+use rocket::serde::Deserialize;
+
+// The `feed` section of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FeedSettings {
+    pub title :String,
+    pub description :String,
+    // Public address of the blog, which post links in the feeds are built from.
+    pub link :String,
+    // Number of most recent posts included in a feed.
+    pub size :u32
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        Self {
+            title: "RkBlog".to_string(),
+            description: "Latest posts".to_string(),
+            link: "http://localhost:8000".to_string(),
+            size: 20
+        }
+    }
+}
+
+pub struct FeedEntry {
+    pub id :String,
+    pub title :String,
+    pub link :String,
+    pub author :String,
+    pub published :DateTime,
+    pub updated :DateTime,
+    pub tags :Vec<String>,
+    pub content_html :String
+}
+
+pub struct Feed {
+    pub title :String,
+    pub description :String,
+    pub link :String,
+    // Address the feed itself is served at.
+    pub feed_link :String,
+    pub entries :Vec<FeedEntry>
+}
+
+impl Feed {
+    // When anything in the feed last changed. An empty feed never changes.
+    pub fn updated(&self) -> DateTime {
+        self.entries.iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or(DateTime::from_millis(0))
+    }
+
+    // Fingerprint of the feed contents, changes whenever an entry is added, removed or edited.
+    pub fn etag(&self, format :&str) -> String {
+        let mut fingerprint = format!("{}|{}", format, self.feed_link);
+        for entry in &self.entries {
+            fingerprint.push_str(&format!("|{}@{}", entry.id, entry.updated.timestamp_millis()));
+        }
+
+        format!("\"{}\"", &sha256::digest(fingerprint)[..32])
+    }
+
+    pub fn rss(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel>");
+        xml.push_str(&format!("<title>{}</title>", escape(&self.title)));
+        xml.push_str(&format!("<link>{}</link>", escape(&self.link)));
+        xml.push_str(&format!("<description>{}</description>", escape(&self.description)));
+        xml.push_str(&format!("<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>", escape(&self.feed_link)));
+        xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>", rfc2822(self.updated())));
+
+        for entry in &self.entries {
+            xml.push_str("<item>");
+            xml.push_str(&format!("<title>{}</title>", escape(&entry.title)));
+            xml.push_str(&format!("<link>{}</link>", escape(&entry.link)));
+            xml.push_str(&format!("<guid isPermaLink=\"false\">{}</guid>", escape(&entry.id)));
+            xml.push_str(&format!("<dc:creator>{}</dc:creator>", escape(&entry.author)));
+            xml.push_str(&format!("<pubDate>{}</pubDate>", rfc2822(entry.published)));
+            for tag in &entry.tags {
+                xml.push_str(&format!("<category>{}</category>", escape(tag)));
+            }
+            xml.push_str(&format!("<description>{}</description>", escape(&entry.content_html)));
+            xml.push_str("</item>");
+        }
+
+        xml.push_str("</channel></rss>");
+        xml
+    }
+
+    pub fn atom(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">");
+        xml.push_str(&format!("<id>{}</id>", escape(&self.feed_link)));
+        xml.push_str(&format!("<title>{}</title>", escape(&self.title)));
+        xml.push_str(&format!("<subtitle>{}</subtitle>", escape(&self.description)));
+        xml.push_str(&format!("<link href=\"{}\"/>", escape(&self.link)));
+        xml.push_str(&format!("<link href=\"{}\" rel=\"self\"/>", escape(&self.feed_link)));
+        xml.push_str(&format!("<updated>{}</updated>", rfc3339(self.updated())));
+
+        for entry in &self.entries {
+            xml.push_str("<entry>");
+            xml.push_str(&format!("<id>urn:rkblog:post:{}</id>", escape(&entry.id)));
+            xml.push_str(&format!("<title>{}</title>", escape(&entry.title)));
+            xml.push_str(&format!("<link href=\"{}\"/>", escape(&entry.link)));
+            xml.push_str(&format!("<author><name>{}</name></author>", escape(&entry.author)));
+            xml.push_str(&format!("<published>{}</published>", rfc3339(entry.published)));
+            xml.push_str(&format!("<updated>{}</updated>", rfc3339(entry.updated)));
+            for tag in &entry.tags {
+                xml.push_str(&format!("<category term=\"{}\"/>", escape(tag)));
+            }
+            xml.push_str(&format!("<content type=\"html\">{}</content>", escape(&entry.content_html)));
+            xml.push_str("</entry>");
+        }
+
+        xml.push_str("</feed>");
+        xml
+    }
+}
+
+pub fn escape(text :&str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c)
+        }
+    }
+
+    escaped
+}
+
+fn to_chrono(date :DateTime) -> chrono::DateTime<Utc> {
+    Utc.timestamp_millis_opt(date.timestamp_millis()).single().unwrap_or_default()
+}
+
+pub fn rfc2822(date :DateTime) -> String {
+    to_chrono(date).to_rfc2822()
+}
+
+pub fn rfc3339(date :DateTime) -> String {
+    to_chrono(date).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+// Dates in HTTP headers, e.g. `Last-Modified`.
+pub fn http_date(date :DateTime) -> String {
+    to_chrono(date).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
@@ -0,0 +1,93 @@
+use std::{borrow::Cow, collections::HashMap, sync::{Arc, Mutex}};
+
+use ammonia::Builder;
+use mongodb::bson::oid::ObjectId;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+
+// Number of rendered revisions kept in memory.
+const CACHE_CAPACITY :usize = 1024;
+
+// Renders post content from Markdown into sanitised HTML, and remembers the result for each
+// revision of a post, since a revision never changes once written.
+pub struct MarkdownRenderer {
+    sanitizer :Builder<'static>,
+    cache :Mutex<HashMap<(ObjectId, u32), Arc<String>>>
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        let mut sanitizer = Builder::default();
+        sanitizer
+            .add_tags(&["input"])
+            .add_tag_attributes("code", &["class"])
+            .add_tag_attributes("div", &["class", "id"])
+            .add_tag_attributes("sup", &["class"])
+            .add_tag_attributes("th", &["style"])
+            .add_tag_attributes("td", &["style"])
+            .add_tag_attributes("input", &["type", "checked", "disabled"])
+            .attribute_filter(Self::filter_attribute);
+
+        Self { sanitizer, cache: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl MarkdownRenderer {
+    pub fn render(&self, post :&ObjectId, revision :u32, content :&str) -> Arc<String> {
+        if let Some(html) = self.cache.lock().unwrap().get(&(*post, revision)) {
+            return html.clone()
+        }
+
+        let html = Arc::new(self.render_uncached(content));
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_CAPACITY {
+            // Older revisions of the same post are never asked for again, so a full cache is
+            // simply started over instead of tracking usage.
+            cache.clear();
+        }
+        cache.insert((*post, revision), html.clone());
+
+        html
+    }
+
+    pub fn render_uncached(&self, content :&str) -> String {
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS;
+
+        let mut unsafe_html = String::new();
+        // Footnote anchors get prefixed, so they can't clash with the ids of the surrounding page. Done here
+        // rather than in the sanitizer, which can't tell a footnote link from any other in-page link.
+        let events = Parser::new_ext(content, options).map(|event| match event {
+            Event::FootnoteReference(name) => Event::FootnoteReference(format!("fn-{}", name).into()),
+            Event::Start(Tag::FootnoteDefinition(name)) => Event::Start(Tag::FootnoteDefinition(format!("fn-{}", name).into())),
+            Event::End(Tag::FootnoteDefinition(name)) => Event::End(Tag::FootnoteDefinition(format!("fn-{}", name).into())),
+            event => event
+        });
+        html::push_html(&mut unsafe_html, events);
+
+        self.sanitizer.clean(&unsafe_html).to_string()
+    }
+
+    // Only lets through the attributes the Markdown renderer itself produces: syntax highlighting
+    // classes on code fences, footnotes, table column alignment and task list checkboxes.
+    fn filter_attribute<'u>(element :&str, attribute :&str, value :&'u str) -> Option<Cow<'u, str>> {
+        match (element, attribute) {
+            ("code", "class") => {
+                let language = value.strip_prefix("language-")?;
+                match language.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '+' || c == '#') {
+                    true => Some(value.into()),
+                    false => None
+                }
+            },
+            ("div", "class") => (value == "footnote-definition").then(|| value.into()),
+            ("sup", "class") => matches!(value, "footnote-reference" | "footnote-definition-label").then(|| value.into()),
+            // Only the footnote definitions, ids from raw HTML could clash with the surrounding page.
+            ("div", "id") => value.starts_with("fn-").then(|| value.into()),
+            ("th" | "td", "style") => matches!(value, "text-align: left" | "text-align: center" | "text-align: right").then(|| value.into()),
+            ("input", "type") => (value == "checkbox").then(|| value.into()),
+            _ => Some(value.into())
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, serde::json::Json, response::status::Created};
+use crate::{models::{api_key::{ApiKeyStoreModel, ApiKeyReadModel, ApiKeyWriteModel}, user::UserAuthClaimsModel}, db::ApiKeyRepository,
+    middlewares::auth::{AuthorizeToken, UserAuthorization}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type ApiKeysResponse = Result<Json<Vec<ApiKeyReadModel>>, ApiError>;
+type ApiKeyResponse = Result<Json<ApiKeyReadModel>, ApiError>;
+type ApiKeyResponseCreated = Result<Created<Json<ApiKeyReadModel>>, ApiError>;
+
+// Keys are managed by signing in, so that a leaked key can't be used to mint more of them.
+fn query_owner(claim :&UserAuthClaimsModel) -> Result<ObjectId, ApiError> {
+    if claim.api_key.is_some() {
+        return Err(ApiError::Forbidden("API keys can't be managed with an API key.".to_string()))
+    }
+
+    match ObjectId::parse_str(&claim._id) {
+        Ok(id) => Ok(id),
+        Err(e) => Err(ApiError::Validation(e.to_string()))
+    }
+}
+
+#[get("/")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn ApiKeyRepository>>,
+    auth :AuthorizeToken<UserAuthorization>
+) -> ApiKeysResponse {
+    let owner = query_owner(&auth.claim)?;
+
+    let keys :Vec<ApiKeyReadModel> = db.list(&owner).await?.into_iter().map(|key| key.to(None)).collect();
+
+    Ok(Json(keys))
+}
+
+#[post("/", data="<key>")]
+pub async fn create(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn ApiKeyRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    key :Json<ApiKeyWriteModel>
+) -> ApiKeyResponseCreated {
+    let owner = query_owner(&auth.claim)?;
+
+    if let Some(capability) = key.0.capabilities.iter().find(|capability| !auth.claim.can(**capability)) {
+        return Err(ApiError::Forbidden(format!("You can't grant a key the {:?} capability, since you don't have it.", capability)))
+    }
+
+    let (new_key, secret) = ApiKeyStoreModel::new(key.0, owner, auth.claim.mfa)?;
+
+    db.insert(&new_key).await?;
+
+    Ok(Created::new(new_key._id.to_hex()).body(Json(new_key.to(Some(secret)))))
+}
+
+#[delete("/<id>")]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn ApiKeyRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str
+) -> ApiKeyResponse {
+    let owner = query_owner(&auth.claim)?;
+
+    let key_id = match ObjectId::parse_str(id) {
+        Ok(key_id) => key_id,
+        Err(_e) => return Err(ApiError::Validation(format!("{} is not a valid id.", id)))
+    };
+
+    match db.delete(&key_id, &owner).await? {
+        Some(key) => Ok(Json(key.to(None))),
+        None => Err(ApiError::NotFound(format!("Key {} not found.", id)))
+    }
+}
@@ -1,44 +1,313 @@
-use mongodb::{Collection, bson::doc};
-use rocket::{State, serde::json::{Json}, http::Status};
+use std::sync::Arc;
 
-use crate::{models::user::{UserStoreModel, UserAuthModel, UserAuthResponseModel, UserAuthClaimsModel}, errors::ApiError, 
-middlewares::auth::SecretKeyWrapper};
+use mongodb::bson::oid::ObjectId;
+use jsonwebtoken::jwk::JwkSet;
+use rocket::{State, serde::json::{Json}, http::Status, response::status::Created, Either};
 
-type AuthResponse = Result<Json<UserAuthResponseModel>, ApiError>;
+use crate::{models::{user::{UserStoreModel, UserAuthModel, UserAuthResponseModel, UserAuthClaimsModel, UserRegisterModel,
+    UserWriteModel, UserReadFullModel, UserPermissionLevel, UserForgotModel, UserResetModel, UserVerifyModel, UserMfaChallengeModel},
+    session::{SessionStoreModel, SessionRefreshModel}, invite::InviteStoreModel},
+    db::{UserRepository, SessionRepository, InviteRepository}, errors::ApiError, mail::{self, Mailer, MailSettings},
+middlewares::auth::{SecretKeyWrapper, AuthorizeToken, UserAuthorization}, security::{password::{self, PasswordVerification}, token, action::{self, ActionToken, ActionPurpose}, jwt::JwtKeys}};
+use crate::middlewares::{rate_limit::{RateLimit, AuthLimit, ReadLimit}, lockout::SignInAttempts};
+
+pub type AuthResponse = Result<Json<UserAuthResponseModel>, ApiError>;
+type SignInResponse = Result<Either<Json<UserAuthResponseModel>, Json<UserMfaChallengeModel>>, ApiError>;
+
+fn issue_token(user :UserStoreModel, session :&SessionStoreModel, refresh_token :String, keys :&JwtKeys) -> AuthResponse {
+    let claims = UserAuthClaimsModel {
+        exp: jsonwebtoken::get_current_timestamp() + keys.lifetime,
+        sid: session._id.to_hex(),
+        _id: user._id.to_hex(),
+        name: user.name,
+        permissions: user.permissions,
+        mfa: session.mfa,
+        capabilities: vec![],
+        api_key: None
+    };
+
+    let token = keys.encode(&claims)?;
+
+    Ok(Json(UserAuthResponseModel {token, refresh_token}))
+}
+
+pub async fn start_session(sessions :&dyn SessionRepository, user :UserStoreModel, mfa :bool, keys :&JwtKeys) -> AuthResponse {
+    let (session, refresh_token) = SessionStoreModel::new(user._id, mfa);
+
+    sessions.insert(&session).await?;
+
+    issue_token(user, &session, refresh_token, keys)
+}
+
+// The same for unknown users and wrong passwords, so that it doesn't give away which accounts exist.
+fn invalid_credentials() -> ApiError {
+    ApiError::Unauthorized("Invalid name or password.".to_string())
+}
 
 #[post("/", data="<auth>")]
 pub async fn get_token(
-    db :&State<Collection<UserStoreModel>>, 
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    sessions :&State<Arc<dyn SessionRepository>>,
+    attempts :SignInAttempts<'_>,
     secret :&State<SecretKeyWrapper>,
+    keys :&State<JwtKeys>,
     auth :Json<UserAuthModel>
-) -> AuthResponse {
-    let user = match db.find_one(doc!{"name": &auth.0.name}, None).await {
-        Ok(maybe_user) => match maybe_user {
-            Some(user) => user,
-            None => return Err(ApiError { status: Status::NotFound, message: format!("User {} not found.", &auth.0.name) })
+) -> SignInResponse {
+    attempts.ensure_unlocked(&auth.0.name).await?;
+
+    let mut user = match db.find_by_name(&auth.0.name).await? {
+        Some(user) => user,
+        None => {
+            // Hash anyway, so that unknown names don't stand out by answering faster.
+            password::hash(&auth.password)?;
+            attempts.record_failure(&auth.0.name).await?;
+            return Err(invalid_credentials())
         }
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
     };
 
-    if !user.authenticate(&auth.password) {
-        return Err(ApiError { status: Status::Forbidden, message: "Invalid password.".to_string() })
+    match user.authenticate(&auth.password) {
+        PasswordVerification::Invalid => {
+            attempts.record_failure(&auth.0.name).await?;
+            return Err(invalid_credentials())
+        },
+        PasswordVerification::Valid => (),
+        PasswordVerification::ValidNeedsRehash => {
+            // Upgrade legacy or outdated hashes now that we know the plaintext password.
+            let password_hash = password::hash(&auth.password)?;
+            match db.set_password_hash(&user._id, &password_hash).await {
+                Ok(_ok) => user.password_hash = password_hash,
+                Err(e) => warn!("Failed to rehash password of user {}: {}", &user.name, e.message())
+            }
+        }
+    }
+
+    // The password alone isn't enough, the client has to come back with a code. See `totp::sign_in`.
+    // Until then the failures keep counting.
+    if user.has_totp() {
+        let mfa_token = action::sign(secret, ActionPurpose::SignIn, &user)?;
+        return Ok(Either::Right(Json(UserMfaChallengeModel { mfa_token })))
     }
 
-    let claims = UserAuthClaimsModel {
-        exp: jsonwebtoken::get_current_timestamp() + 3600,
-        _id: user._id.to_hex(),
-        name: user.name,
-        permissions: user.permissions
+    attempts.record_success(&user.name).await?;
+
+    Ok(Either::Left(start_session(sessions.as_ref(), user, false, keys).await?))
+}
+
+#[post("/register", data="<user>")]
+pub async fn register(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    invites :&State<Arc<dyn InviteRepository>>,
+    mailer :&State<Arc<dyn Mailer>>,
+    mail_settings :&State<MailSettings>,
+    secret :&State<SecretKeyWrapper>,
+    user :Json<UserRegisterModel>
+) -> Result<Created<Json<UserReadFullModel>>, ApiError> {
+    if let Some(_thing) = db.find_by_name(&user.0.name).await? {
+        return Err(ApiError::Conflict(format!("User {} already exists.", &user.0.name)))
+    }
+
+    let new_user = UserStoreModel::new(UserWriteModel {
+        name: user.0.name,
+        password: user.0.password,
+        permissions: UserPermissionLevel::default(),
+        email: user.0.email,
+        bio: user.0.bio
+    })?;
+    UserStoreModel::check_email(db.as_ref(), &new_user).await?;
+
+    let invite = InviteStoreModel::redeem(invites.as_ref(), &user.0.invite).await?;
+
+    if let Err(e) = db.insert(&new_user).await {
+        invite.release(invites.as_ref()).await?;
+        return Err(e)
+    }
+
+    if let Err(e) = mail::send_verification(mailer.as_ref(), mail_settings, secret, &new_user).await {
+        warn!("Failed to send the verification email to user {}: {}", &new_user.name, e.message());
+    }
+
+    Ok(Created::new(new_user.name.clone()).body(Json(new_user.to())))
+}
+
+async fn query_user(db :&dyn UserRepository, id :&ObjectId) -> Result<UserStoreModel, ApiError> {
+    match db.find(id).await? {
+        Some(user) => Ok(user),
+        None => Err(ApiError::Validation("Invalid or expired token.".to_string()))
+    }
+}
+
+// Always accepted, so that it can't be used to find out which addresses have an account.
+#[post("/forgot", data="<forgot>")]
+pub async fn forgot(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    mailer :&State<Arc<dyn Mailer>>,
+    mail_settings :&State<MailSettings>,
+    secret :&State<SecretKeyWrapper>,
+    forgot :Json<UserForgotModel>
+) -> Result<Status, ApiError> {
+    let email = match UserStoreModel::normalize_email(Some(forgot.0.email))? {
+        Some(email) => email,
+        None => return Err(ApiError::Validation("Email must not be empty.".to_string()))
+    };
+
+    // Resetting through an address nobody confirmed would let whoever typed it in take the account over.
+    let user = db.find_by_email(&email).await?;
+
+    if let Some(user) = user.filter(|user| user.email_verified) {
+        if let Some(email) = mail::password_reset(mail_settings, secret, &user)? {
+            // Sent in the background, waiting for the mail server would tell apart the addresses that have an account.
+            let mailer = Arc::clone(mailer.inner());
+            rocket::tokio::spawn(async move {
+                if let Err(e) = mailer.send(email).await {
+                    warn!("Failed to send the password reset email to user {}: {}", &user.name, e.message());
+                }
+            });
+        }
+    }
+
+    Ok(Status::Accepted)
+}
+
+#[post("/reset", data="<reset>")]
+pub async fn reset(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    sessions :&State<Arc<dyn SessionRepository>>,
+    secret :&State<SecretKeyWrapper>,
+    reset :Json<UserResetModel>
+) -> Result<Status, ApiError> {
+    let action = ActionToken::decode(secret, ActionPurpose::ResetPassword, &reset.0.token)?;
+    let user = query_user(db.as_ref(), &action.user).await?;
+
+    if !action.matches(&user) {
+        return Err(ApiError::Validation("Invalid or expired token.".to_string()))
+    }
+
+    let password_hash = password::hash(&reset.0.password)?;
+
+    // Only goes through if the password wasn't changed in the meantime, which keeps the token single-use.
+    if !db.change_password_hash(&user._id, &user.password_hash, &password_hash).await? {
+        return Err(ApiError::Validation("Invalid or expired token.".to_string()))
+    }
+
+    // Whoever knew the old password is signed out everywhere.
+    sessions.revoke_by_user(&user._id).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[post("/verify", data="<verify>")]
+pub async fn verify(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    secret :&State<SecretKeyWrapper>,
+    verify :Json<UserVerifyModel>
+) -> Result<Status, ApiError> {
+    let action = ActionToken::decode(secret, ActionPurpose::VerifyEmail, &verify.0.token)?;
+    let user = query_user(db.as_ref(), &action.user).await?;
+
+    if !action.matches(&user) {
+        return Err(ApiError::Validation("Invalid or expired token.".to_string()))
+    }
+
+    db.verify_email(&user._id, user.email.as_deref()).await?;
+
+    Ok(Status::NoContent)
+}
+
+#[post("/verify/resend")]
+pub async fn resend_verification(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    mailer :&State<Arc<dyn Mailer>>,
+    mail_settings :&State<MailSettings>,
+    secret :&State<SecretKeyWrapper>,
+    auth :AuthorizeToken<UserAuthorization>
+) -> Result<Status, ApiError> {
+    let id = match ObjectId::parse_str(&auth.claim._id) {
+        Ok(id) => id,
+        Err(e) => return Err(ApiError::Validation(e.to_string()))
     };
 
-    let token = match jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(), 
-        &claims, 
-        &jsonwebtoken::EncodingKey::from_secret(&secret.key.to_string().as_bytes())
-    ) {
-        Ok(token) => token,
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    let user = match db.find(&id).await? {
+        Some(user) => user,
+        None => return Err(ApiError::NotFound(format!("User {} not found.", &auth.claim.name)))
     };
 
-    Ok(Json(UserAuthResponseModel {token}))
-}
\ No newline at end of file
+    if user.email.is_none() {
+        return Err(ApiError::Validation("There's no email address to verify.".to_string()))
+    }
+
+    if user.email_verified {
+        return Err(ApiError::Conflict("The email address is already verified.".to_string()))
+    }
+
+    mail::send_verification(mailer.as_ref(), mail_settings, secret, &user).await?;
+
+    Ok(Status::Accepted)
+}
+
+#[post("/refresh", data="<refresh>")]
+pub async fn refresh(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    sessions :&State<Arc<dyn SessionRepository>>,
+    keys :&State<JwtKeys>,
+    refresh :Json<SessionRefreshModel>
+) -> AuthResponse {
+    let presented_hash = token::digest(&refresh.0.refresh_token);
+    let refresh_token = token::generate();
+
+    // Rotate atomically, so a refresh token can only ever be exchanged once.
+    let session = match sessions.rotate(&presented_hash, &token::digest(&refresh_token), SessionStoreModel::expiry()).await? {
+        Some(session) => session,
+        None => {
+            // A rotated-out token was presented again, so either the client or an attacker holds a
+            // stolen copy. Revoke the whole session to cut both of them off.
+            if sessions.revoke_reused(&presented_hash).await? {
+                warn!("Refresh token reuse detected, session revoked.");
+            }
+
+            return Err(ApiError::Unauthorized("Invalid refresh token.".to_string()))
+        }
+    };
+
+    let user = match db.find(&session.user).await? {
+        Some(user) => user,
+        None => return Err(ApiError::Unauthorized("Invalid refresh token.".to_string()))
+    };
+
+    issue_token(user, &session, refresh_token, keys)
+}
+
+#[post("/logout")]
+pub async fn logout(
+    _limit :RateLimit<AuthLimit>,
+    sessions :&State<Arc<dyn SessionRepository>>,
+    auth :AuthorizeToken<UserAuthorization>
+) -> Result<Status, ApiError> {
+    // API keys have no session to end, they are revoked through `/keys`.
+    auth.claim.require_session()?;
+
+    let sid = match ObjectId::parse_str(&auth.claim.sid) {
+        Ok(sid) => sid,
+        Err(e) => return Err(ApiError::Validation(e.to_string()))
+    };
+
+    sessions.revoke(&sid).await?;
+
+    Ok(Status::NoContent)
+}
+
+// The public keys access tokens can be verified with. Empty while tokens are signed with the secret key,
+// which must never be handed out.
+#[get("/jwks.json")]
+pub async fn jwks(
+    _limit :RateLimit<ReadLimit>,
+    keys :&State<JwtKeys>
+) -> Json<JwkSet> {
+    Json(keys.jwks.clone())
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::{State, serde::json::Json, response::status::Created};
+use crate::{models::{comment::{CommentStoreModel, CommentReadModel, CommentWriteModel, CommentEditModel}, post::PostStoreModel,
+    user::UserAuthClaimsModel, role::Capability}, db::{CommentRepository, PostRepository, UserRepository},
+    middlewares::auth::{AuthorizeToken, MaybeAuthorizeToken, UserAuthorization, CapabilityAuthorization, CommentCreateCapability}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type CommentsResponse = Result<Json<Vec<CommentReadModel>>, ApiError>;
+type CommentResponse = Result<Json<CommentReadModel>, ApiError>;
+type CommentResponseCreated = Result<Created<Json<CommentReadModel>>, ApiError>;
+
+// Comments are only accessible on posts the user is allowed to see.
+async fn query_post(post_ref :&dyn PostRepository, claim :Option<&UserAuthClaimsModel>, id :&str) -> Result<PostStoreModel, ApiError> {
+    let post = PostStoreModel::find(post_ref, id).await?;
+
+    match post.is_visible_to(claim) {
+        true => Ok(post),
+        false => Err(ApiError::NotFound(format!("Post {} not found.", id)))
+    }
+}
+
+fn parse_id(id :&str) -> Result<ObjectId, ApiError> {
+    match ObjectId::parse_str(id) {
+        Ok(id) => Ok(id),
+        Err(_e) => Err(ApiError::Validation(format!("{} is not a valid id.", id)))
+    }
+}
+
+async fn query_comment(db :&dyn CommentRepository, post :&ObjectId, id :&str) -> Result<CommentStoreModel, ApiError> {
+    match db.find(&parse_id(id)?, post).await? {
+        Some(comment) => Ok(comment),
+        None => Err(ApiError::NotFound(format!("Comment {} not found.", id)))
+    }
+}
+
+#[get("/<id>/comments")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn CommentRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :MaybeAuthorizeToken,
+    id :&str
+) -> CommentsResponse {
+    let post = query_post(ref_posts.as_ref(), auth.claim.as_ref(), id).await?;
+
+    let results = db.list(&post._id).await?;
+
+    let mut comments :Vec<CommentReadModel> = vec![];
+    for i in results {
+        comments.push(i.to(ref_users.as_ref()).await?);
+    }
+
+    Ok(Json(CommentStoreModel::thread(comments)))
+}
+
+#[post("/<id>/comments", data="<comment>")]
+pub async fn create(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn CommentRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<CapabilityAuthorization<CommentCreateCapability>>,
+    id :&str,
+    comment :Json<CommentWriteModel>
+) -> CommentResponseCreated {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+
+    // Replies have to stay within the thread of the same post.
+    let parent = match &comment.0.parent {
+        Some(parent) => Some(query_comment(db.as_ref(), &post._id, parent).await?._id),
+        None => None
+    };
+
+    let author = PostStoreModel::query_author_by_name(ref_users.as_ref(), &auth.claim.name).await?;
+    let new_comment = CommentStoreModel::new(comment.0, post._id, parent, author._id);
+
+    db.insert(&new_comment).await?;
+
+    Ok(Created::new(new_comment._id.to_hex()).body(Json(new_comment.to(ref_users.as_ref()).await?)))
+}
+
+#[put("/<id>/comments/<comment_id>", data="<comment>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn CommentRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str,
+    comment_id :&str,
+    comment :Json<CommentEditModel>
+) -> CommentResponse {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+    let mut origin_comment = query_comment(db.as_ref(), &post._id, comment_id).await?;
+
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &origin_comment.author).await?;
+
+    if author.name != auth.claim.name && !auth.claim.can(Capability::CommentModerate) {
+        return Err(ApiError::Forbidden("You don't have permission to modify this resource.".to_string()))
+    }
+
+    origin_comment.content = comment.0.content;
+    origin_comment.edited = Some(DateTime::now());
+
+    db.replace(&origin_comment).await?;
+
+    Ok(Json(origin_comment.to(ref_users.as_ref()).await?))
+}
+
+#[delete("/<id>/comments/<comment_id>")]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn CommentRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str,
+    comment_id :&str
+) -> CommentResponse {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+    let comment = query_comment(db.as_ref(), &post._id, comment_id).await?;
+
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &comment.author).await?;
+
+    if author.name != auth.claim.name && !auth.claim.can(Capability::CommentModerate) {
+        return Err(ApiError::Forbidden("You don't have permission to delete this resource.".to_string()))
+    }
+
+    // Replies go away together with the comment they respond to.
+    CommentStoreModel::delete_threads(db.as_ref(), vec![comment._id]).await?;
+
+    Ok(Json(comment.to(ref_users.as_ref()).await?))
+}
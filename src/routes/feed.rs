@@ -0,0 +1,215 @@
+use std::{collections::HashMap, io::Cursor, sync::Arc};
+
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, http::{Status, ContentType, Header}, Response, request::{Outcome, FromRequest}, outcome::Outcome::Success};
+use crate::{models::{post::{PostStoreModel, PostStatus, PostFilter, PostVisibility}, tag}, db::{PostRepository, UserRepository},
+    middlewares::conditional::ConditionalRequest, render::{markdown::MarkdownRenderer, feed::{Feed, FeedEntry, FeedSettings, http_date}}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, ReadLimit};
+
+pub enum FeedFormat {
+    Rss, Atom
+}
+
+impl FeedFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom"
+        }
+    }
+
+    fn content_type(&self) -> ContentType {
+        match self {
+            FeedFormat::Rss => ContentType::new("application", "rss+xml"),
+            FeedFormat::Atom => ContentType::new("application", "atom+xml")
+        }
+    }
+}
+
+pub struct FeedResponse {
+    body :Option<String>,
+    content_type :ContentType,
+    etag :String,
+    last_modified :String
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for FeedResponse {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let mut response = Response::build();
+        response
+            .header(Header::new("ETag", self.etag))
+            .header(Header::new("Last-Modified", self.last_modified))
+            .header(Header::new("Cache-Control", "public, max-age=300"));
+
+        match self.body {
+            Some(body) => response
+                .sized_body(body.len(), Cursor::new(body))
+                .header(self.content_type)
+                .ok(),
+            None => response.status(Status::NotModified).ok()
+        }
+    }
+}
+
+// Feeds only ever contain published posts, no matter who asks.
+fn published() -> PostFilter {
+    PostFilter {
+        visibility: PostVisibility::Published,
+        status: Some(PostStatus::Published),
+        author: None,
+        tag: None,
+        category: None
+    }
+}
+
+// Everything a feed is built from, gathered up front so the routes only differ in what they ask for.
+pub struct FeedSource<'r> {
+    db :&'r dyn PostRepository,
+    ref_users :&'r dyn UserRepository,
+    renderer :&'r MarkdownRenderer,
+    settings :&'r FeedSettings,
+    conditions :ConditionalRequest
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FeedSource<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let db = match request.guard::<&State<Arc<dyn PostRepository>>>().await {
+            Success(db) => db,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+        let ref_users = match request.guard::<&State<Arc<dyn UserRepository>>>().await {
+            Success(ref_users) => ref_users,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+        let renderer = match request.guard::<&State<MarkdownRenderer>>().await {
+            Success(renderer) => renderer,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+        let settings = match request.guard::<&State<FeedSettings>>().await {
+            Success(settings) => settings,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+        let conditions = match request.guard::<ConditionalRequest>().await {
+            Success(conditions) => conditions,
+            _ => return Outcome::Failure((Status::InternalServerError, ()))
+        };
+
+        Outcome::Success(FeedSource {
+            db: db.inner().as_ref(),
+            ref_users: ref_users.inner().as_ref(),
+            renderer: renderer.inner(),
+            settings: settings.inner(),
+            conditions
+        })
+    }
+}
+
+// Builds a feed out of the latest published posts matching the filter. Rendering is skipped
+// entirely when the client's cached copy is still current.
+async fn feed(source :FeedSource<'_>, format :FeedFormat, path :String, title :String, filter :PostFilter) -> Result<FeedResponse, ApiError> {
+    let posts = source.db.latest(&filter, source.settings.size).await?;
+
+    let link = source.settings.link.trim_end_matches('/');
+    let mut feed = Feed {
+        title,
+        description: source.settings.description.clone(),
+        link: link.to_string(),
+        feed_link: format!("{}{}.{}", link, path, format.extension()),
+        entries: vec![]
+    };
+
+    let mut authors :HashMap<ObjectId, String> = HashMap::new();
+    for post in &posts {
+        let published = post.publish_at.unwrap_or(post._id.timestamp());
+        feed.entries.push(FeedEntry {
+            id: post._id.to_hex(),
+            title: post.title.clone(),
+            link: format!("{}/posts/{}", link, post.slug),
+            author: String::new(),
+            published,
+            updated: post.updated.unwrap_or(published).max(published),
+            tags: post.tags.clone(),
+            content_html: String::new()
+        });
+    }
+
+    let etag = feed.etag(format.extension());
+    let last_modified = http_date(feed.updated());
+
+    if source.conditions.is_fresh(&etag) {
+        return Ok(FeedResponse { body: None, content_type: format.content_type(), etag, last_modified })
+    }
+
+    for (entry, post) in feed.entries.iter_mut().zip(posts) {
+        let author = match authors.get(&post.author) {
+            Some(author) => author.clone(),
+            None => {
+                let author = PostStoreModel::query_author(source.ref_users, &post.author).await?;
+                authors.insert(post.author, author.name.clone());
+                author.name
+            }
+        };
+
+        entry.author = author;
+        entry.content_html = source.renderer.render(&post._id, post.revision, &post.content).to_string();
+    }
+
+    let body = match format {
+        FeedFormat::Rss => feed.rss(),
+        FeedFormat::Atom => feed.atom()
+    };
+
+    Ok(FeedResponse { body: Some(body), content_type: format.content_type(), etag, last_modified })
+}
+
+async fn author_feed(source :FeedSource<'_>, format :FeedFormat, name :&str) -> Result<FeedResponse, ApiError> {
+    let author = match source.ref_users.find_by_name(name).await? {
+        Some(user) => user,
+        None => return Err(ApiError::NotFound(format!("User {} not found.", name)))
+    };
+
+    let title = format!("{} - {}", source.settings.title, author.name);
+    feed(source, format, format!("/users/{}/feed", author.name), title, PostFilter { author: Some(author._id), ..published() }).await
+}
+
+async fn tag_feed(source :FeedSource<'_>, format :FeedFormat, name :&str) -> Result<FeedResponse, ApiError> {
+    let name = tag::normalize(name);
+    let title = format!("{} - #{}", source.settings.title, name);
+    feed(source, format, format!("/tags/{}/feed", name), title, PostFilter { tag: Some(name), ..published() }).await
+}
+
+#[get("/feed.rss")]
+pub async fn rss(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>) -> Result<FeedResponse, ApiError> {
+    let title = source.settings.title.clone();
+    feed(source, FeedFormat::Rss, "/feed".to_string(), title, published()).await
+}
+
+#[get("/feed.atom")]
+pub async fn atom(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>) -> Result<FeedResponse, ApiError> {
+    let title = source.settings.title.clone();
+    feed(source, FeedFormat::Atom, "/feed".to_string(), title, published()).await
+}
+
+#[get("/users/<name>/feed.rss")]
+pub async fn author_rss(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>, name :&str) -> Result<FeedResponse, ApiError> {
+    author_feed(source, FeedFormat::Rss, name).await
+}
+
+#[get("/users/<name>/feed.atom")]
+pub async fn author_atom(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>, name :&str) -> Result<FeedResponse, ApiError> {
+    author_feed(source, FeedFormat::Atom, name).await
+}
+
+#[get("/tags/<name>/feed.rss")]
+pub async fn tag_rss(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>, name :&str) -> Result<FeedResponse, ApiError> {
+    tag_feed(source, FeedFormat::Rss, name).await
+}
+
+#[get("/tags/<name>/feed.atom")]
+pub async fn tag_atom(_limit :RateLimit<ReadLimit>, source :FeedSource<'_>, name :&str) -> Result<FeedResponse, ApiError> {
+    tag_feed(source, FeedFormat::Atom, name).await
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, serde::json::Json, response::status::Created};
+use crate::{models::{invite::{InviteStoreModel, InviteReadModel, InviteWriteModel}, post::PostStoreModel}, db::{InviteRepository, UserRepository},
+    middlewares::auth::{AuthorizeToken, CapabilityAuthorization, UserManageCapability}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type InvitesResponse = Result<Json<Vec<InviteReadModel>>, ApiError>;
+type InviteResponse = Result<Json<InviteReadModel>, ApiError>;
+type InviteResponseCreated = Result<Created<Json<InviteReadModel>>, ApiError>;
+
+async fn query_creator(user_ref :&dyn UserRepository, id :&ObjectId) -> Result<Option<String>, ApiError> {
+    Ok(user_ref.find(id).await?.map(|user| user.name))
+}
+
+#[get("/")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn InviteRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>
+) -> InvitesResponse {
+    let results = db.list().await?;
+
+    let mut invites :Vec<InviteReadModel> = vec![];
+    for i in results {
+        let created_by = query_creator(ref_users.as_ref(), &i.created_by).await?;
+        invites.push(i.to(created_by, None));
+    }
+
+    Ok(Json(invites))
+}
+
+#[post("/", data="<invite>")]
+pub async fn create(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn InviteRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
+    invite :Json<InviteWriteModel>
+) -> InviteResponseCreated {
+    let creator = PostStoreModel::query_author_by_name(ref_users.as_ref(), &auth.claim.name).await?;
+    let (new_invite, code) = InviteStoreModel::new(invite.0, creator._id)?;
+
+    db.insert(&new_invite).await?;
+
+    Ok(Created::new(new_invite._id.to_hex()).body(Json(new_invite.to(Some(creator.name), Some(code)))))
+}
+
+#[delete("/<id>")]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn InviteRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
+    id :&str
+) -> InviteResponse {
+    let id = match ObjectId::parse_str(id) {
+        Ok(id) => id,
+        Err(_e) => return Err(ApiError::Validation(format!("{} is not a valid id.", id)))
+    };
+
+    let invite = match db.delete(&id).await? {
+        Some(invite) => invite,
+        None => return Err(ApiError::NotFound(format!("Invite {} not found.", id)))
+    };
+
+    let created_by = query_creator(ref_users.as_ref(), &invite.created_by).await?;
+    Ok(Json(invite.to(created_by, None)))
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, serde::json::Json};
+use crate::{models::login_attempt::{LoginAttemptStoreModel, LoginAttemptReadModel}, db::LoginAttemptRepository,
+    middlewares::auth::{AuthorizeToken, CapabilityAuthorization, UserManageCapability}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type LockoutsResponse = Result<Json<Vec<LoginAttemptReadModel>>, ApiError>;
+type LockoutResponse = Result<Json<LoginAttemptReadModel>, ApiError>;
+
+// Lists the failed sign in counters, only the ones currently locked out unless `all` is set.
+#[get("/?<all>")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn LoginAttemptRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
+    all :Option<bool>
+) -> LockoutsResponse {
+    Ok(Json(LoginAttemptStoreModel::list(db.as_ref(), !all.unwrap_or(false)).await?))
+}
+
+// Lifts a lockout and resets its counter, e.g. after a user locked themselves out.
+#[delete("/<id>")]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn LoginAttemptRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
+    id :&str
+) -> LockoutResponse {
+    let attempt_id = match ObjectId::parse_str(id) {
+        Ok(attempt_id) => attempt_id,
+        Err(_e) => return Err(ApiError::Validation(format!("{} is not a valid id.", id)))
+    };
+
+    match db.delete(&attempt_id).await? {
+        Some(attempt) => Ok(Json(attempt.to())),
+        None => Err(ApiError::NotFound(format!("Lockout {} not found.", id)))
+    }
+}
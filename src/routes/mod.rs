@@ -1,3 +1,12 @@
 pub mod post;
 pub mod user;
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod comment;
+pub mod revision;
+pub mod tag;
+pub mod feed;
+pub mod role;
+pub mod invite;
+pub mod totp;
+pub mod api_key;
+pub mod lockout;
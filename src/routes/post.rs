@@ -1,129 +1,269 @@
-use mongodb::{bson::{doc}, Collection};
-use rocket::{State, serde::json::Json, futures::TryStreamExt, http::Status, response::status::Created};
-use crate::{models::{post::{ PostStoreModel, PostReadBriefModel, PostReadFullModel, PostWriteModel}, 
-    user::{UserStoreModel, UserPermissionLevel}}, middlewares::auth::{AuthorizeToken, UserAuthorization}};
+use std::sync::Arc;
+
+use rocket::{State, serde::json::Json, response::{status::Created, Redirect}, Either};
+use crate::{models::{post::{ PostStoreModel, PostReadBriefModel, PostReadFullModel, PostWriteModel, PostStatus, PostStatusWriteModel, PostFilter}, 
+    role::Capability, revision::RevisionStoreModel,
+    page::{PageModel, PageQuery, Sort}, tag, search::{PostSearchResultModel, terms, snippet}},
+    db::{PostRepository, UserRepository, CommentRepository, RevisionRepository},
+    middlewares::auth::{AuthorizeToken, MaybeAuthorizeToken, UserAuthorization, CapabilityAuthorization, PostCreateCapability}, tasks::scheduler::PostScheduler, render::markdown::MarkdownRenderer};
 use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
 
-type PostsResponse = Result<Json<Vec<PostReadBriefModel>>, ApiError>;
+type SearchResponse = Result<Json<PageModel<PostSearchResultModel>>, ApiError>;
+pub type PostsResponse = Result<Json<PageModel<PostReadBriefModel>>, ApiError>;
 type PostResponse = Result<Json<PostReadFullModel>, ApiError>;
+type PostResponseMoved = Result<Either<Json<PostReadFullModel>, Redirect>, ApiError>;
 type PostResponseCreated = Result<Created<Json<PostReadFullModel>>, ApiError>;
 
-#[get("/")]
-pub async fn list(
-    db :&State<Collection<PostStoreModel>>, 
-    ref_users :&State<Collection<UserStoreModel>>,
-    _auth :AuthorizeToken<UserAuthorization>
+// Lists a page of the posts matching the filter, out of the posts the user is allowed to see.
+pub async fn list_page(
+    db :&dyn PostRepository,
+    ref_users :&dyn UserRepository,
+    filter :PostFilter,
+    status :Option<&str>,
+    page :PageQuery
 ) -> PostsResponse {
-    let mut results = match db.find(None, None).await {
-        Ok(posts) => posts,
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()} )
+    let sort = Sort::parse(page.sort.as_deref(), "-created", &[("created", "_id"), ("title", "title"), ("published", "publish_at")])?;
+
+    // Archived posts are only listed when asked for explicitly.
+    let filter = match status.map(PostStatus::parse) {
+        Some(Some(status)) => PostFilter { status: Some(status), ..filter },
+        Some(None) => return Ok(Json(PageModel { items: vec![], next_cursor: None, total: 0 })),
+        None => filter
     };
 
+    let request = page.request(&sort)?;
+    let (results, total) = db.page(&filter, &request).await?;
+    let (results, next_cursor) = page.finish(&sort, results)?;
+
     let mut posts :Vec<PostReadBriefModel> = vec![];
-    while let Ok(Some(i)) = results.try_next().await {
-        posts.push(i.brief(&ref_users).await?);
-    } 
-        
-    Ok(Json(posts))
+    for i in results {
+        posts.push(i.brief(ref_users).await?);
+    }
+
+    Ok(Json(PageModel { items: posts, next_cursor, total }))
 }
 
-#[get("/<title>")]
-pub async fn get<'a>(
-    db :&State<Collection<PostStoreModel>>, 
-    ref_users :&State<Collection<UserStoreModel>>,
-    _auth :AuthorizeToken<UserAuthorization>,
-    title :&'a str
-) -> PostResponse {
-    let post = match db.find_one(doc!{"title": title}, None).await {
-        Ok(maybe_post) => match maybe_post {
-            Some(post) => post,
-            None => return Err(ApiError{status: Status::NotFound, message: format!("Post {} not found.", title)})
-        },
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
+#[get("/?<author>&<status>&<tag>&<category>&<page..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn PostRepository>>, 
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :MaybeAuthorizeToken,
+    author :Option<&str>,
+    status :Option<&str>,
+    tag :Option<&str>,
+    category :Option<&str>,
+    page :PageQuery
+) -> PostsResponse {
+    let mut filter = PostFilter::visible_to(auth.claim.as_ref());
+
+    if let Some(author) = author {
+        match ref_users.find_by_name(author).await? {
+            Some(author) => filter.author = Some(author._id),
+            None => return Ok(Json(PageModel { items: vec![], next_cursor: None, total: 0 }))
+        }
+    }
+
+    filter.tag = tag.map(tag::normalize);
+    filter.category = category.map(str::to_string);
+
+    list_page(db.as_ref(), ref_users.as_ref(), filter, status, page).await
+}
+
+// Full-text search over titles and content. Results are ordered by relevance, so the cursor is a plain offset.
+#[get("/search?<q>&<author>&<tag>&<page..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn search(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :MaybeAuthorizeToken,
+    q :&str,
+    author :Option<&str>,
+    tag :Option<&str>,
+    page :PageQuery
+) -> SearchResponse {
+    let terms = terms(q);
+    if terms.is_empty() {
+        return Err(ApiError::Validation("Search query must not be empty.".to_string()))
+    }
+
+    let mut filter = PostFilter::visible_to(auth.claim.as_ref());
+
+    if let Some(author) = author {
+        match ref_users.find_by_name(author).await? {
+            Some(author) => filter.author = Some(author._id),
+            None => return Ok(Json(PageModel { items: vec![], next_cursor: None, total: 0 }))
+        }
+    }
+
+    filter.tag = tag.map(tag::normalize);
+
+    let skip = match page.cursor.as_deref().map(str::parse::<u64>) {
+        Some(Ok(skip)) => skip,
+        Some(Err(_e)) => return Err(ApiError::Validation("Invalid cursor.".to_string())),
+        None => 0
+    };
+    let limit = page.limit();
+
+    let (hits, total) = db.search(q, &filter, skip, limit).await?;
+
+    let next_cursor = match skip + (hits.len() as u64) < total {
+        true => Some((skip + hits.len() as u64).to_string()),
+        false => None
     };
 
-    Ok(Json(post.to(&ref_users).await?))
+    let mut results :Vec<PostSearchResultModel> = vec![];
+    for hit in hits {
+        let snippet = snippet(&hit.post.content, &terms);
+        results.push(PostSearchResultModel { post: hit.post.brief(ref_users.as_ref()).await?, score: hit.score, snippet });
+    }
+
+    Ok(Json(PageModel { items: results, next_cursor, total }))
+}
+
+#[get("/<id>")]
+pub async fn get(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn PostRepository>>, 
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    auth :MaybeAuthorizeToken,
+    id :&str
+) -> PostResponseMoved {
+    let post = PostStoreModel::find(db.as_ref(), id).await?;
+
+    if !post.is_visible_to(auth.claim.as_ref()) {
+        return Err(ApiError::NotFound(format!("Post {} not found.", id)))
+    }
+
+    if post.is_moved(id) {
+        return Ok(Either::Right(Redirect::moved(format!("/posts/{}", post.slug))))
+    }
+
+    Ok(Either::Left(Json(post.to(ref_users.as_ref(), renderer).await?)))
 }
 
 #[post("/", data="<post>")]
 pub async fn create(
-    db :&State<Collection<PostStoreModel>>, 
-    ref_users :&State<Collection<UserStoreModel>>,
-    auth :AuthorizeToken<UserAuthorization>,
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn PostRepository>>, 
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    ref_revisions :&State<Arc<dyn RevisionRepository>>,
+    auth :AuthorizeToken<CapabilityAuthorization<PostCreateCapability>>,
     post :Json<PostWriteModel>
 ) -> PostResponseCreated {
-    match db.find_one(doc!{"title": &post.0.title}, None).await {
-        Ok(maybe_post) => if let Some(_thing) = maybe_post {
-            return Err(ApiError{ status: Status::NotFound, message: format!("Post {} already exists.", &post.0.title)})
-        }
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
-    };
+    if let Some(_thing) = db.find_by_title(&post.0.title).await? {
+        return Err(ApiError::Conflict(format!("Post {} already exists.", &post.0.title)))
+    }
 
-    let new_post = PostStoreModel::new(post.0, &ref_users, &auth.claim.name).await?;
+    let new_post = PostStoreModel::new(post.0, db.as_ref(), ref_users.as_ref(), &auth.claim.name).await?;
 
-    match db.insert_one(&new_post, None).await {
-        Ok(_ok) => return Ok(Created::new(format!("{}", &new_post.title)).body(Json(new_post.to(&ref_users).await?))),
-        Err(e) => return Err(ApiError{status: Status::InternalServerError, message: e.to_string()})
-    };
+    db.insert(&new_post).await?;
+
+    RevisionStoreModel::record(ref_revisions.as_ref(), &new_post, new_post.author, None).await?;
+
+    Ok(Created::new(new_post.slug.clone()).body(Json(new_post.to(ref_users.as_ref(), renderer).await?)))
 }
 
-#[put("/<title>", data="<post>")]
-pub async fn update<'a>(
-    db :&State<Collection<PostStoreModel>>, 
-    ref_users :&State<Collection<UserStoreModel>>,
+#[put("/<id>", data="<post>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn PostRepository>>, 
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    ref_revisions :&State<Arc<dyn RevisionRepository>>,
     auth :AuthorizeToken<UserAuthorization>,
-    title :&'a str, 
+    id :&str, 
     post :Json<PostWriteModel>
 ) -> PostResponse {
-    let origin_post = match db.find_one(doc!{"title": title}, None).await {
-        Ok(maybe_post) => match maybe_post {
-            Some(post) => post,
-            None => return Err(ApiError{ status: Status::NotFound, message: format!("Post {} not found.", title)})
-        }
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
-    };
+    let origin_post = PostStoreModel::find(db.as_ref(), id).await?;
 
-    let author = PostStoreModel::query_author(&ref_users, doc!{"_id": &origin_post.author}).await?;
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &origin_post.author).await?;
 
-    if &author.name != &auth.claim.name {
-        if auth.claim.permissions != UserPermissionLevel::Admin {
-            return Err(ApiError { status: Status::Forbidden, message: "You don't have permission to modify this resource.".to_string() })
-        }
+    if author.name != auth.claim.name && !auth.claim.can(Capability::PostEditAny) {
+        return Err(ApiError::Forbidden("You don't have permission to modify this resource.".to_string()))
     }
 
-    let replace_post = PostStoreModel::from(post.0, origin_post._id, &ref_users, &auth.claim.name).await?;
+    // Answered like a new post with a taken title, rather than left to the unique index.
+    if post.0.title != origin_post.title && db.find_by_title(&post.0.title).await?.is_some() {
+        return Err(ApiError::Conflict(format!("Post {} already exists.", &post.0.title)))
+    }
+
+    let editor = PostStoreModel::query_author_by_name(ref_users.as_ref(), &auth.claim.name).await?;
+    let replace_post = PostStoreModel::from(post.0, &origin_post, db.as_ref()).await?;
+    replace_post.commit(db.as_ref(), ref_revisions.as_ref(), editor._id, None).await?;
+
+    Ok(Json(replace_post.to(ref_users.as_ref(), renderer).await?))
+}
+
+#[delete("/<id>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    ref_comments :&State<Arc<dyn CommentRepository>>,
+    ref_revisions :&State<Arc<dyn RevisionRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str
+) -> PostResponse {
+    let post = PostStoreModel::find(db.as_ref(), id).await?;
+
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &post.author).await?;
 
-    match db.replace_one(doc!{"_id": &origin_post._id}, &replace_post, None).await {
-        Ok(_ok) => Ok(Json(replace_post.to(&ref_users).await?)),
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
+    if author.name != auth.claim.name && !auth.claim.can(Capability::PostDeleteAny) {
+        return Err(ApiError::Forbidden("You don't have permission to delete this resource.".to_string()))
     }
+
+    db.delete(&post._id).await?;
+
+    // Delete all comments under the deleted post
+    ref_comments.delete_by_post(&post._id).await?;
+
+    // Delete the revision history of the deleted post
+    ref_revisions.delete_by_posts(&[post._id]).await?;
+
+    Ok(Json(post.to(ref_users.as_ref(), renderer).await?))
 }
 
-#[delete("/<title>")]
-pub async fn delete<'a>(
-    db :&State<Collection<PostStoreModel>>,
-    ref_users :&State<Collection<UserStoreModel>>,
+#[put("/<id>/status", data="<status>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn transition(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    scheduler :&State<PostScheduler>,
     auth :AuthorizeToken<UserAuthorization>,
-    title :&'a str
+    id :&str,
+    status :Json<PostStatusWriteModel>
 ) -> PostResponse {
-    let post = match db.find_one(doc!{"title": title}, None).await {
-        Ok(maybe_post) => match maybe_post {
-            Some(post) => post,
-            None => return Err(ApiError{ status: Status::NotFound, message: format!("Post {} not found.", title)})
-        },
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
-    };
+    let mut post = PostStoreModel::find(db.as_ref(), id).await?;
 
-    let author = PostStoreModel::query_author(&ref_users, doc!{"_id": &post.author}).await?;
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &post.author).await?;
 
-    if &author.name != &auth.claim.name {
-        if auth.claim.permissions != UserPermissionLevel::Admin {
-            return Err(ApiError { status: Status::Forbidden, message: "You don't have permission to delete this resource.".to_string() })
-        }
+    if author.name != auth.claim.name && !auth.claim.can(Capability::PostEditAny) {
+        return Err(ApiError::Forbidden("You don't have permission to modify this resource.".to_string()))
+    }
+
+    // Taking a post public, now or later, is a separate permission from writing it.
+    if matches!(status.0.status, PostStatus::Published | PostStatus::Scheduled) && !auth.claim.can(Capability::PostPublish) {
+        return Err(ApiError::Forbidden("You don't have permission to publish posts.".to_string()))
     }
 
-    match db.delete_one(doc!{"title": title}, None).await {
-        Ok(_ok) => Ok(Json(post.to(&ref_users).await?)),
-        Err(e) => return Err(ApiError{ status: Status::InternalServerError, message: e.to_string()})
+    post.transition(status.0)?;
+
+    db.set_status(&post).await?;
+
+    if post.status == PostStatus::Scheduled {
+        scheduler.reschedule();
     }
-}
\ No newline at end of file
+
+    Ok(Json(post.to(ref_users.as_ref(), renderer).await?))
+}
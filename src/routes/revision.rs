@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use rocket::{State, serde::json::Json};
+use crate::{models::{post::{PostStoreModel, PostReadFullModel, PostWriteModel}, revision::{RevisionStoreModel, RevisionReadBriefModel,
+    RevisionReadFullModel, RevisionDiffModel}, user::UserAuthClaimsModel, role::Capability}, db::{RevisionRepository, PostRepository, UserRepository},
+    middlewares::auth::{AuthorizeToken, UserAuthorization}, render::markdown::MarkdownRenderer};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type RevisionsResponse = Result<Json<Vec<RevisionReadBriefModel>>, ApiError>;
+type RevisionResponse = Result<Json<RevisionReadFullModel>, ApiError>;
+type RevisionDiffResponse = Result<Json<RevisionDiffModel>, ApiError>;
+
+// Revision history is only accessible on posts the user is allowed to see.
+async fn query_post(post_ref :&dyn PostRepository, claim :Option<&UserAuthClaimsModel>, id :&str) -> Result<PostStoreModel, ApiError> {
+    let post = PostStoreModel::find(post_ref, id).await?;
+
+    match post.is_visible_to(claim) {
+        true => Ok(post),
+        false => Err(ApiError::NotFound(format!("Post {} not found.", id)))
+    }
+}
+
+#[get("/<id>/revisions")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn RevisionRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str
+) -> RevisionsResponse {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+
+    let results = db.list(&post._id).await?;
+
+    let mut revisions :Vec<RevisionReadBriefModel> = vec![];
+    for i in results {
+        revisions.push(i.brief(ref_users.as_ref()).await?);
+    }
+
+    Ok(Json(revisions))
+}
+
+#[get("/<id>/revisions/<number>")]
+pub async fn get(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn RevisionRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str,
+    number :u32
+) -> RevisionResponse {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+    let revision = RevisionStoreModel::find(db.as_ref(), &post._id, number).await?;
+
+    Ok(Json(revision.to(ref_users.as_ref()).await?))
+}
+
+// Line-based diff of a revision against an older one, by default the revision right before it.
+#[get("/<id>/revisions/<number>/diff?<against>")]
+pub async fn diff(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn RevisionRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str,
+    number :u32,
+    against :Option<u32>
+) -> RevisionDiffResponse {
+    let post = query_post(ref_posts.as_ref(), Some(&auth.claim), id).await?;
+    let revision = RevisionStoreModel::find(db.as_ref(), &post._id, number).await?;
+
+    let against = match against {
+        Some(against) => against,
+        None if number > 1 => number - 1,
+        None => return Err(ApiError::Validation("The first revision has nothing to compare against.".to_string()))
+    };
+    let base = RevisionStoreModel::find(db.as_ref(), &post._id, against).await?;
+
+    Ok(Json(base.diff(&revision)))
+}
+
+#[post("/<id>/revisions/<number>/restore")]
+#[allow(clippy::too_many_arguments)]
+pub async fn restore(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn RevisionRepository>>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    renderer :&State<MarkdownRenderer>,
+    auth :AuthorizeToken<UserAuthorization>,
+    id :&str,
+    number :u32
+) -> Result<Json<PostReadFullModel>, ApiError> {
+    let origin_post = PostStoreModel::find(ref_posts.as_ref(), id).await?;
+
+    let author = PostStoreModel::query_author(ref_users.as_ref(), &origin_post.author).await?;
+
+    if author.name != auth.claim.name && !auth.claim.can(Capability::PostEditAny) {
+        return Err(ApiError::Forbidden("You don't have permission to modify this resource.".to_string()))
+    }
+
+    let revision = RevisionStoreModel::find(db.as_ref(), &origin_post._id, number).await?;
+
+    // Restoring never rewrites history, the old content comes back as the newest revision.
+    let restored = PostWriteModel {
+        title: revision.title,
+        content: revision.content,
+        tags: origin_post.tags.clone(),
+        category: origin_post.category.clone()
+    };
+    let editor = PostStoreModel::query_author_by_name(ref_users.as_ref(), &auth.claim.name).await?;
+    let replace_post = PostStoreModel::from(restored, &origin_post, ref_posts.as_ref()).await?;
+    replace_post.commit(ref_posts.as_ref(), db.as_ref(), editor._id, Some(number)).await?;
+
+    Ok(Json(replace_post.to(ref_users.as_ref(), renderer).await?))
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use rocket::{State, serde::json::Json};
+use crate::{models::{role::{RoleStoreModel, RoleReadModel, RoleWriteModel}, user::UserPermissionLevel}, db::RoleRepository,
+    middlewares::auth::{AuthorizeToken, CapabilityAuthorization, RoleManageCapability}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type RolesResponse = Result<Json<Vec<RoleReadModel>>, ApiError>;
+type RoleResponse = Result<Json<RoleReadModel>, ApiError>;
+
+#[get("/")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn RoleRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<RoleManageCapability>>
+) -> RolesResponse {
+    Ok(Json(RoleStoreModel::list(db.as_ref()).await?))
+}
+
+#[put("/<name>", data="<role>")]
+pub async fn update(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn RoleRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<RoleManageCapability>>,
+    name :&str,
+    role :Json<RoleWriteModel>
+) -> RoleResponse {
+    let level = match UserPermissionLevel::parse(name) {
+        Some(level) => level,
+        None => return Err(ApiError::NotFound(format!("Role {} not found.", name)))
+    };
+
+    Ok(Json(RoleStoreModel::update(db.as_ref(), level, role.0).await?))
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use rocket::{State, serde::json::Json};
+use crate::{models::{post::PostFilter, page::PageQuery, tag::{self, TagReadModel, TagRenameModel, TagMergeModel}},
+    db::{PostRepository, UserRepository}, middlewares::auth::{AuthorizeToken, MaybeAuthorizeToken, CapabilityAuthorization, TagManageCapability}};
+use crate::errors::ApiError;
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+use super::post::{list_page, PostsResponse};
+
+type TagsResponse = Result<Json<Vec<TagReadModel>>, ApiError>;
+type TagResponse = Result<Json<TagReadModel>, ApiError>;
+
+#[get("/")]
+pub async fn list(
+    _limit :RateLimit<ReadLimit>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    auth :MaybeAuthorizeToken
+) -> TagsResponse {
+    let filter = PostFilter::visible_to(auth.claim.as_ref());
+
+    Ok(Json(ref_posts.tag_counts(&filter).await?))
+}
+
+#[get("/<name>/posts?<page..>")]
+pub async fn posts(
+    _limit :RateLimit<ReadLimit>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    ref_users :&State<Arc<dyn UserRepository>>,
+    auth :MaybeAuthorizeToken,
+    name :&str,
+    page :PageQuery
+) -> PostsResponse {
+    let filter = PostFilter { tag: Some(tag::normalize(name)), ..PostFilter::visible_to(auth.claim.as_ref()) };
+
+    list_page(ref_posts.as_ref(), ref_users.as_ref(), filter, None, page).await
+}
+
+#[put("/<name>", data="<rename>")]
+pub async fn rename(
+    _limit :RateLimit<WriteLimit>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<TagManageCapability>>,
+    name :&str,
+    rename :Json<TagRenameModel>
+) -> TagResponse {
+    let from = tag::normalize(name);
+    let to = tag::normalize(&rename.0.name);
+
+    if to.is_empty() {
+        return Err(ApiError::Validation("Tag name must not be empty.".to_string()))
+    }
+
+    if !tag::exists(ref_posts.as_ref(), &from).await? {
+        return Err(ApiError::NotFound(format!("Tag {} not found.", from)))
+    }
+
+    if tag::exists(ref_posts.as_ref(), &to).await? {
+        return Err(ApiError::Conflict(format!("Tag {} already exists, merge the tags instead.", to)))
+    }
+
+    let posts = ref_posts.retag(&from, &to).await?;
+
+    Ok(Json(TagReadModel { name: to, posts }))
+}
+
+#[post("/<name>/merge", data="<merge>")]
+pub async fn merge(
+    _limit :RateLimit<WriteLimit>,
+    ref_posts :&State<Arc<dyn PostRepository>>,
+    _auth :AuthorizeToken<CapabilityAuthorization<TagManageCapability>>,
+    name :&str,
+    merge :Json<TagMergeModel>
+) -> TagResponse {
+    let from = tag::normalize(name);
+    let into = tag::normalize(&merge.0.into);
+
+    if from == into {
+        return Err(ApiError::Validation("Cannot merge a tag into itself.".to_string()))
+    }
+
+    for tag in [&from, &into] {
+        if !tag::exists(ref_posts.as_ref(), tag).await? {
+            return Err(ApiError::NotFound(format!("Tag {} not found.", tag)))
+        }
+    }
+
+    ref_posts.retag(&from, &into).await?;
+
+    let posts = ref_posts.count_tag(&into).await?;
+
+    Ok(Json(TagReadModel { name: into, posts }))
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, serde::json::Json, http::Status};
+use crate::{models::{user::{UserStoreModel, UserAuthClaimsModel},
+    totp::{TotpStoreModel, TotpEnrolmentModel, TotpCodeModel, TotpRecoveryCodesModel, TotpSignInModel}},
+    db::{UserRepository, SessionRepository},
+    middlewares::auth::{AuthorizeToken, UserAuthorization, AuthSettings, SecretKeyWrapper},
+    security::{totp, action::{ActionToken, ActionPurpose}, jwt::JwtKeys}};
+use crate::errors::ApiError;
+use crate::middlewares::{rate_limit::{RateLimit, AuthLimit}, lockout::SignInAttempts};
+
+use super::auth::{start_session, AuthResponse};
+
+async fn query_user(db :&dyn UserRepository, claim :&UserAuthClaimsModel) -> Result<UserStoreModel, ApiError> {
+    claim.require_session()?;
+
+    let id = match ObjectId::parse_str(&claim._id) {
+        Ok(id) => id,
+        Err(e) => return Err(ApiError::Validation(e.to_string()))
+    };
+
+    match db.find(&id).await? {
+        Some(user) => Ok(user),
+        None => Err(ApiError::NotFound(format!("User {} not found.", &claim.name)))
+    }
+}
+
+// Starts an enrolment. It only takes effect once confirmed with a code, so that a user can't lock
+// themselves out with a secret that never made it into their app.
+#[post("/totp")]
+pub async fn enrol(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    settings :&State<AuthSettings>,
+    auth :AuthorizeToken<UserAuthorization>
+) -> Result<Json<TotpEnrolmentModel>, ApiError> {
+    let user = query_user(db.as_ref(), &auth.claim).await?;
+
+    if user.has_totp() {
+        return Err(ApiError::Conflict("Two-factor authentication is already enabled.".to_string()))
+    }
+
+    let enrolment = TotpStoreModel::enrol();
+    db.set_totp(&user._id, Some(&enrolment)).await?;
+
+    Ok(Json(TotpEnrolmentModel {
+        uri: totp::uri(&settings.totp_issuer, &user.name, &enrolment.secret),
+        secret: enrolment.secret
+    }))
+}
+
+#[post("/totp/confirm", data="<code>")]
+pub async fn confirm(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    code :Json<TotpCodeModel>
+) -> Result<Json<TotpRecoveryCodesModel>, ApiError> {
+    let user = query_user(db.as_ref(), &auth.claim).await?;
+
+    let mut enrolment = match &user.totp {
+        Some(enrolment) if !enrolment.enabled => enrolment.clone(),
+        Some(_enrolment) => return Err(ApiError::Conflict("Two-factor authentication is already enabled.".to_string())),
+        None => return Err(ApiError::Validation("There's no enrolment to confirm.".to_string()))
+    };
+
+    enrolment.last_step = match totp::verify(&enrolment.secret, &code.0.code, jsonwebtoken::get_current_timestamp()) {
+        Some(step) => step as i64,
+        None => return Err(ApiError::Forbidden("Invalid code.".to_string()))
+    };
+
+    let (recovery_codes, digests) = TotpStoreModel::recovery_codes();
+    enrolment.enabled = true;
+    enrolment.recovery_codes = digests;
+    db.set_totp(&user._id, Some(&enrolment)).await?;
+
+    Ok(Json(TotpRecoveryCodesModel { recovery_codes }))
+}
+
+#[post("/totp/disable", data="<code>")]
+pub async fn disable(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    code :Json<TotpCodeModel>
+) -> Result<Status, ApiError> {
+    let user = query_user(db.as_ref(), &auth.claim).await?;
+
+    if !user.has_totp() {
+        return Err(ApiError::Validation("Two-factor authentication is not enabled.".to_string()))
+    }
+
+    if !TotpStoreModel::redeem(db.as_ref(), &user, &code.0.code).await? {
+        return Err(ApiError::Forbidden("Invalid code.".to_string()))
+    }
+
+    db.set_totp(&user._id, None).await?;
+
+    Ok(Status::NoContent)
+}
+
+// Replaces all recovery codes, e.g. after running out of them.
+#[post("/totp/recovery", data="<code>")]
+pub async fn recovery_codes(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    auth :AuthorizeToken<UserAuthorization>,
+    code :Json<TotpCodeModel>
+) -> Result<Json<TotpRecoveryCodesModel>, ApiError> {
+    let user = query_user(db.as_ref(), &auth.claim).await?;
+
+    if !user.has_totp() {
+        return Err(ApiError::Validation("Two-factor authentication is not enabled.".to_string()))
+    }
+
+    if !TotpStoreModel::redeem(db.as_ref(), &user, &code.0.code).await? {
+        return Err(ApiError::Forbidden("Invalid code.".to_string()))
+    }
+
+    let (recovery_codes, digests) = TotpStoreModel::recovery_codes();
+
+    db.set_recovery_codes(&user._id, &digests).await?;
+
+    Ok(Json(TotpRecoveryCodesModel { recovery_codes }))
+}
+
+// Second step of signing in, trades the token from the first step and a code for a session.
+#[post("/totp/sign-in", data="<sign_in>")]
+pub async fn sign_in(
+    _limit :RateLimit<AuthLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    sessions :&State<Arc<dyn SessionRepository>>,
+    attempts :SignInAttempts<'_>,
+    secret :&State<SecretKeyWrapper>,
+    keys :&State<JwtKeys>,
+    sign_in :Json<TotpSignInModel>
+) -> AuthResponse {
+    let action = ActionToken::decode(secret, ActionPurpose::SignIn, &sign_in.0.mfa_token)?;
+
+    let user = match db.find(&action.user).await? {
+        Some(user) => user,
+        None => return Err(ApiError::Unauthorized("Invalid or expired token.".to_string()))
+    };
+
+    if !action.matches(&user) {
+        return Err(ApiError::Unauthorized("Invalid or expired token.".to_string()))
+    }
+
+    // Codes are only six digits, so guessing them is throttled just like passwords.
+    attempts.ensure_unlocked(&user.name).await?;
+
+    if !TotpStoreModel::redeem(db.as_ref(), &user, &sign_in.0.code).await? {
+        attempts.record_failure(&user.name).await?;
+        return Err(ApiError::Forbidden("Invalid code.".to_string()))
+    }
+
+    attempts.record_success(&user.name).await?;
+
+    start_session(sessions.as_ref(), user, true, keys).await
+}
@@ -1,43 +1,45 @@
-use mongodb::{Collection, bson::doc};
-use rocket::{State, serde::json::Json, http::Status, futures::TryStreamExt, response::status::{Created}};
-use crate::{models::{user::{UserStoreModel, UserReadFullModel, UserWriteModel, UserPermissionLevel, UserReadBriefModel}, post::PostStoreModel}, 
-    errors::ApiError, 
-    middlewares::auth::{AuthorizeToken, UserAuthorization, AdminPermissionAuthorization}};
+use std::sync::Arc;
 
-type UsersResponse = Result<Json<Vec<UserReadBriefModel>>, ApiError>;
+use mongodb::bson::oid::ObjectId;
+use rocket::{State, serde::json::Json, response::status::{Created}};
+use crate::{models::{user::{UserStoreModel, UserReadFullModel, UserWriteModel, UserReadBriefModel, UserPermissionLevel}, role::Capability,
+    comment::CommentStoreModel, page::{PageModel, PageQuery, Sort}}, 
+    db::{UserRepository, PostRepository, SessionRepository, ApiKeyRepository, CommentRepository, RevisionRepository}, errors::ApiError, mail::{self, Mailer, MailSettings},
+    middlewares::auth::{AuthorizeToken, MaybeAuthorizeToken, UserAuthorization, CapabilityAuthorization, UserManageCapability, SecretKeyWrapper},
+    security::password::PasswordVerification};
+use crate::middlewares::rate_limit::{RateLimit, WriteLimit, ReadLimit};
+
+type UsersResponse = Result<Json<PageModel<UserReadBriefModel>>, ApiError>;
 type UserResponse = Result<Json<UserReadFullModel>, ApiError>;
 type UserResponseCreated = Result<Created<Json<UserReadFullModel>>, ApiError>;
 
-#[get("/")]
+#[get("/?<page..>")]
 pub async fn list(
-    db :&State<Collection<UserStoreModel>>,
-    _auth :AuthorizeToken<UserAuthorization>
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn UserRepository>>,
+    _auth :MaybeAuthorizeToken,
+    page :PageQuery
 ) -> UsersResponse {
-    let mut results = match db.find(None, None).await {
-        Ok(users) => users,
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
-    };
+    let sort = Sort::parse(page.sort.as_deref(), "name", &[("name", "name"), ("created", "_id")])?;
 
-    let mut users = vec![];
-    while let Ok(Some(user)) = results.try_next().await {
-        users.push(user.brief())
-    };
+    let request = page.request(&sort)?;
+    let (results, total) = db.page(&request).await?;
+    let (results, next_cursor) = page.finish(&sort, results)?;
+    let users = results.into_iter().map(|user| user.brief()).collect();
 
-    Ok(Json(users))
+    Ok(Json(PageModel { items: users, next_cursor, total }))
 }
 
 #[get("/<name>")]
-pub async fn get<'a>(
-    db :&State<Collection<UserStoreModel>>, 
-    _auth: AuthorizeToken<UserAuthorization>,
-    name :&'a str
+pub async fn get(
+    _limit :RateLimit<ReadLimit>,
+    db :&State<Arc<dyn UserRepository>>, 
+    _auth :MaybeAuthorizeToken,
+    name :&str
 ) -> UserResponse {
-    let user = match db.find_one(doc! {"name": name}, None).await {
-        Ok(maybe_user) => match maybe_user {
-            Some(user) => user,
-            None => return Err(ApiError { status: Status::NotFound, message: format!("User {} not found.", name) })
-        }
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    let user = match db.find_by_name(name).await? {
+        Some(user) => user,
+        None => return Err(ApiError::NotFound(format!("User {} not found.", name)))
     };
 
     Ok(Json(user.to()))
@@ -45,77 +47,133 @@ pub async fn get<'a>(
 
 #[post("/", data="<user>")]
 pub async fn create(
-    db :&State<Collection<UserStoreModel>>, 
-    _auth: AuthorizeToken<AdminPermissionAuthorization>,
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn UserRepository>>, 
+    mailer :&State<Arc<dyn Mailer>>,
+    mail_settings :&State<MailSettings>,
+    secret :&State<SecretKeyWrapper>,
+    auth: AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
     user :Json<UserWriteModel>
 ) -> UserResponseCreated {
-    match db.find_one(doc! {"name": &user.0.name}, None).await {
-        Ok(maybe_user) => if let Some(_thing) = maybe_user {
-            return Err(ApiError { status: Status::Conflict, message: format!("User {} already exists.", &user.0.name) })
-        }
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    if user.0.permissions == UserPermissionLevel::Admin {
+        auth.claim.require_admin_control()?;
+    }
+
+    if let Some(_thing) = db.find_by_name(&user.0.name).await? {
+        return Err(ApiError::Conflict(format!("User {} already exists.", &user.0.name)))
     }
     
-    let new_user = UserStoreModel::new(user.0);
+    let new_user = UserStoreModel::new(user.0)?;
+    UserStoreModel::check_email(db.as_ref(), &new_user).await?;
 
-    match db.insert_one(&new_user, None).await {
-        Ok(_ok) => return Ok(Created::new(format!("{}", &new_user.name)).body(Json(new_user.to()))),
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    db.insert(&new_user).await?;
+
+    if let Err(e) = mail::send_verification(mailer.as_ref(), mail_settings, secret, &new_user).await {
+        warn!("Failed to send the verification email to user {}: {}", &new_user.name, e.message());
     }
+
+    Ok(Created::new(new_user.name.clone()).body(Json(new_user.to())))
 }
 
 #[put("/<name>", data="<user>")]
-pub async fn update<'a>(
-    db :&State<Collection<UserStoreModel>>, 
+#[allow(clippy::too_many_arguments)]
+pub async fn update(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn UserRepository>>, 
+    sessions :&State<Arc<dyn SessionRepository>>,
+    mailer :&State<Arc<dyn Mailer>>,
+    mail_settings :&State<MailSettings>,
+    secret :&State<SecretKeyWrapper>,
     auth :AuthorizeToken<UserAuthorization>,
-    name :&'a str, 
+    name :&str, 
     user :Json<UserWriteModel>
 ) -> UserResponse {
-    let origin_user = match db.find_one(doc! {"name": name}, None).await {
-        Ok(maybe_user) => match maybe_user {
-            Some(user) => user,
-            None => return Err(ApiError { status: Status::NotFound, message: format!("User {} not found.", name) })
-        }
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    auth.claim.require_session()?;
+
+    let origin_user = match db.find_by_name(name).await? {
+        Some(user) => user,
+        None => return Err(ApiError::NotFound(format!("User {} not found.", name)))
     };
 
-    if &origin_user.name != &auth.claim.name {
-        if auth.claim.permissions != UserPermissionLevel::Admin {
-            return Err(ApiError { status: Status::Forbidden, message: "You don't have permission to modify this resource.".to_string() })
+    if origin_user.name != auth.claim.name && !auth.claim.can(Capability::UserManage) {
+        return Err(ApiError::Forbidden("You don't have permission to modify this resource.".to_string()))
+    }
+
+    // Users can't hand themselves a different role.
+    if user.0.permissions != origin_user.permissions && !auth.claim.can(Capability::UserManage) {
+        return Err(ApiError::Forbidden("You don't have permission to change roles.".to_string()))
+    }
+
+    if user.0.permissions == UserPermissionLevel::Admin || origin_user.permissions == UserPermissionLevel::Admin {
+        auth.claim.require_admin_control()?;
+    }
+
+    // The password is always sent, and hashed with a fresh salt, so the hashes can't be compared.
+    let password_changed = matches!(origin_user.authenticate(&user.0.password), PasswordVerification::Invalid);
+
+    let replace_user = UserStoreModel::from(user.0, &origin_user)?;
+    UserStoreModel::check_email(db.as_ref(), &replace_user).await?;
+
+    db.replace(&replace_user).await?;
+
+    // Whoever knew the old password is signed out, except the session that changed it.
+    if password_changed {
+        match ObjectId::parse_str(&auth.claim.sid) {
+            Ok(sid) if auth.claim._id == replace_user._id.to_hex() => sessions.revoke_others(&replace_user._id, &sid).await?,
+            _ => sessions.revoke_by_user(&replace_user._id).await?
         }
     }
-    
-    let replace_user = UserStoreModel::from(user.0, origin_user._id);
 
-    match db.replace_one(doc! {"_id": &origin_user._id}, &replace_user, None).await {
-        Ok(_ok) => return Ok(Json(replace_user.to())),
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    if replace_user.email != origin_user.email {
+        if let Err(e) = mail::send_verification(mailer.as_ref(), mail_settings, secret, &replace_user).await {
+            warn!("Failed to send the verification email to user {}: {}", &replace_user.name, e.message());
+        }
     }
+
+    Ok(Json(replace_user.to()))
 }
 
 #[delete("/<name>")]
-pub async fn delete<'a>(
-    db :&State<Collection<UserStoreModel>>, 
-    post_ref :&State<Collection<PostStoreModel>>,
-    _auth :AuthorizeToken<AdminPermissionAuthorization>,
-    name :&'a str
+#[allow(clippy::too_many_arguments)]
+pub async fn delete(
+    _limit :RateLimit<WriteLimit>,
+    db :&State<Arc<dyn UserRepository>>, 
+    post_ref :&State<Arc<dyn PostRepository>>,
+    session_ref :&State<Arc<dyn SessionRepository>>,
+    api_key_ref :&State<Arc<dyn ApiKeyRepository>>,
+    comment_ref :&State<Arc<dyn CommentRepository>>,
+    revision_ref :&State<Arc<dyn RevisionRepository>>,
+    auth :AuthorizeToken<CapabilityAuthorization<UserManageCapability>>,
+    name :&str
 ) -> UserResponse {
-    let user = match db.find_one(doc! {"name": name}, None).await {
-        Ok(maybe_user) => match maybe_user {
-            Some(user) => user,
-            None => return Err(ApiError { status: Status::NotFound, message: format!("User {} not found.", name) })
-        }
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    let user = match db.find_by_name(name).await? {
+        Some(user) => user,
+        None => return Err(ApiError::NotFound(format!("User {} not found.", name)))
     };
 
-    match db.delete_one(doc! {"name": name}, None).await {
-        Ok(_ok) => {
-            // Delete all posts of the deleted user
-            match post_ref.delete_many(doc!{"author": user._id}, None).await {
-                Ok(_ok) => return Ok(Json(user.to())),
-                Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
-            }
-        },
-        Err(e) => return Err(ApiError { status: Status::InternalServerError, message: e.to_string() })
+    if user.permissions == UserPermissionLevel::Admin {
+        auth.claim.require_admin_control()?;
     }
-}
\ No newline at end of file
+
+    db.delete(&user._id).await?;
+
+    // Delete all comments of the deleted user, as well as all comments under their posts
+    let post_ids = post_ref.ids_by_author(&user._id).await?;
+
+    let comment_ids = comment_ref.ids_by_author_or_posts(&user._id, &post_ids).await?;
+    CommentStoreModel::delete_threads(comment_ref.as_ref(), comment_ids).await?;
+
+    // Delete the revision history of their posts
+    revision_ref.delete_by_posts(&post_ids).await?;
+
+    // Delete all posts of the deleted user
+    post_ref.delete_by_author(&user._id).await?;
+
+    // Revoke their API keys
+    api_key_ref.delete_by_user(&user._id).await?;
+
+    // Sign the deleted user out everywhere
+    session_ref.delete_by_user(&user._id).await?;
+
+    Ok(Json(user.to()))
+}
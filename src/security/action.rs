@@ -0,0 +1,98 @@
+use mongodb::bson::oid::ObjectId;
+use rocket::serde::{Serialize, Deserialize};
+
+use crate::{errors::ApiError, middlewares::auth::SecretKeyWrapper, models::user::UserStoreModel};
+
+// Short-lived tokens that allow a single action, e.g. a password reset.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ActionPurpose {
+    VerifyEmail, ResetPassword,
+    // Finishing a sign in with a second factor, after the password was checked.
+    SignIn
+}
+
+impl ActionPurpose {
+    // How long a token stays valid, in seconds.
+    pub fn lifetime(&self) -> u64 {
+        match self {
+            ActionPurpose::VerifyEmail => 48 * 3600,
+            ActionPurpose::ResetPassword => 30 * 60,
+            ActionPurpose::SignIn => 5 * 60
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ActionClaimsModel {
+    exp :u64,
+    sub :String,
+    purpose :ActionPurpose,
+    fingerprint :String
+}
+
+// Digest of the part of the user the action changes. Once the action went through, the fingerprint
+// no longer matches, which makes the tokens single-use without having to keep track of them.
+fn fingerprint(purpose :ActionPurpose, user :&UserStoreModel) -> String {
+    match purpose {
+        ActionPurpose::VerifyEmail => sha256::digest(format!("verify|{}|{}", user.email.as_deref().unwrap_or_default(), user.email_verified)),
+        ActionPurpose::ResetPassword => sha256::digest(format!("reset|{}", user.password_hash)),
+        ActionPurpose::SignIn => match &user.totp {
+            Some(totp) => sha256::digest(format!("sign-in|{}|{}|{}", user.password_hash, totp.last_step, totp.recovery_codes.len())),
+            None => sha256::digest(format!("sign-in|{}", user.password_hash))
+        }
+    }
+}
+
+pub fn sign(secret :&SecretKeyWrapper, purpose :ActionPurpose, user :&UserStoreModel) -> Result<String, ApiError> {
+    let claims = ActionClaimsModel {
+        exp: jsonwebtoken::get_current_timestamp() + purpose.lifetime(),
+        sub: user._id.to_hex(),
+        purpose,
+        fingerprint: fingerprint(purpose, user)
+    };
+
+    match jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(&secret.key)
+    ) {
+        Ok(token) => Ok(token),
+        Err(e) => Err(ApiError::from(e))
+    }
+}
+
+// A token that was signed by us and is meant for the expected purpose. It's only valid for as long as
+// `matches` agrees though.
+pub struct ActionToken {
+    pub user :ObjectId,
+    purpose :ActionPurpose,
+    fingerprint :String
+}
+
+impl ActionToken {
+    pub fn decode(secret :&SecretKeyWrapper, purpose :ActionPurpose, token :&str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::Validation("Invalid or expired token.".to_string());
+
+        let claims = match jsonwebtoken::decode::<ActionClaimsModel>(
+            token,
+            &jsonwebtoken::DecodingKey::from_secret(&secret.key),
+            &jsonwebtoken::Validation::default()
+        ) {
+            Ok(claims) => claims.claims,
+            Err(_e) => return Err(invalid())
+        };
+
+        if claims.purpose != purpose {
+            return Err(invalid())
+        }
+
+        match ObjectId::parse_str(&claims.sub) {
+            Ok(user) => Ok(Self { user, purpose, fingerprint: claims.fingerprint }),
+            Err(_e) => Err(invalid())
+        }
+    }
+
+    pub fn matches(&self, user :&UserStoreModel) -> bool {
+        user._id == self.user && self.fingerprint == fingerprint(self.purpose, user)
+    }
+}
@@ -0,0 +1,252 @@
+use std::{collections::HashMap, fs};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, EncodingKey, DecodingKey, Header, Validation, errors::ErrorKind,
+    jwk::{Jwk, JwkSet, CommonParameters, PublicKeyUse, AlgorithmParameters, RSAKeyParameters, RSAKeyType,
+    OctetKeyPairParameters, OctetKeyPairType, EllipticCurve}};
+use rocket::serde::{Serialize, Deserialize, de::DeserializeOwned};
+use simple_asn1::ASN1Block;
+
+use crate::{errors::ApiError, middlewares::auth::SecretKeyWrapper};
+
+// An Ed25519 public key in SubjectPublicKeyInfo form is a fixed prefix followed by the 32 bytes of the key.
+const ED25519_SPKI_LEN :usize = 44;
+
+// The `jwt` section of the Rocket configuration.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct JwtSettings {
+    // How long access tokens stay valid, in seconds.
+    pub lifetime :u64,
+    pub issuer :Option<String>,
+    pub audience :Option<String>,
+    // The key new tokens are signed with. Without one, tokens are signed with HS256 and the secret key.
+    pub signing_key :Option<JwtKeySettings>,
+    // Retired signing keys. Tokens signed with them are still accepted, so that rotating the signing key
+    // doesn't sign everyone out. They can be dropped once the longest lifetime has passed.
+    pub verification_keys :Vec<JwtKeySettings>
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        Self {
+            lifetime: 3600,
+            issuer: None,
+            audience: None,
+            signing_key: None,
+            verification_keys: vec![]
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JwtKeySettings {
+    // Sent along in the `kid` header, to pick the key to verify a token with.
+    pub kid :String,
+    // RS256, RS384, RS512, PS256, PS384, PS512 or EdDSA.
+    pub algorithm :Algorithm,
+    // Paths to PEM files. The private key is only needed for the signing key.
+    pub private_key :Option<String>,
+    pub public_key :String
+}
+
+struct VerificationKey {
+    algorithm :Algorithm,
+    key :DecodingKey
+}
+
+#[derive(Serialize)]
+struct RegisteredClaims<'a, T :Serialize> {
+    #[serde(flatten)]
+    claims :&'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iss :Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud :Option<&'a str>
+}
+
+// Signs and verifies access tokens.
+pub struct JwtKeys {
+    pub lifetime :u64,
+    issuer :Option<String>,
+    audience :Option<String>,
+    header :Header,
+    encoding :EncodingKey,
+    // By `kid`. Tokens without one are only accepted while no signing key is configured.
+    verification :HashMap<Option<String>, VerificationKey>,
+    // The public halves of the keys above, for other services to verify tokens with.
+    pub jwks :JwkSet
+}
+
+fn is_rsa(algorithm :Algorithm) -> bool {
+    matches!(algorithm, Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512)
+}
+
+fn read_pem(path :&str) -> Result<pem::Pem, String> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Failed to read key {}: {}", path, e))
+    };
+
+    match pem::parse(contents) {
+        Ok(pem) => Ok(pem),
+        Err(e) => Err(format!("Failed to parse key {}: {}", path, e))
+    }
+}
+
+// Takes the modulus and exponent out of a PKCS#1 or SubjectPublicKeyInfo encoded RSA public key.
+fn rsa_components(pem :&pem::Pem) -> Option<(Vec<u8>, Vec<u8>)> {
+    let der = match pem.tag.as_str() {
+        "RSA PUBLIC KEY" => pem.contents.clone(),
+        "PUBLIC KEY" => match simple_asn1::from_der(&pem.contents).ok()?.first()? {
+            ASN1Block::Sequence(_, blocks) => match blocks.get(1)? {
+                ASN1Block::BitString(_, _, bits) => bits.clone(),
+                _ => return None
+            },
+            _ => return None
+        },
+        _ => return None
+    };
+
+    match simple_asn1::from_der(&der).ok()?.first()? {
+        ASN1Block::Sequence(_, blocks) => match (blocks.first()?, blocks.get(1)?) {
+            (ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)) => Some((n.to_bytes_be().1, e.to_bytes_be().1)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn public_key(settings :&JwtKeySettings) -> Result<(VerificationKey, Jwk), String> {
+    let pem = read_pem(&settings.public_key)?;
+
+    let (key, parameters) = if is_rsa(settings.algorithm) {
+        let (n, e) = match rsa_components(&pem) {
+            Some(components) => components,
+            None => return Err(format!("{} is not an RSA public key.", settings.public_key))
+        };
+
+        (DecodingKey::from_rsa_raw_components(&n, &e), AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(n),
+            e: URL_SAFE_NO_PAD.encode(e)
+        }))
+    } else if settings.algorithm == Algorithm::EdDSA {
+        if pem.tag != "PUBLIC KEY" || pem.contents.len() != ED25519_SPKI_LEN {
+            return Err(format!("{} is not an Ed25519 public key.", settings.public_key))
+        }
+
+        let x = &pem.contents[ED25519_SPKI_LEN - 32..];
+        (DecodingKey::from_ed_der(x), AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(x)
+        }))
+    } else {
+        return Err(format!("Key {} uses {:?}, only RSA and EdDSA keys are supported.", settings.kid, settings.algorithm))
+    };
+
+    Ok((VerificationKey { algorithm: settings.algorithm, key }, Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(settings.algorithm),
+            key_id: Some(settings.kid.clone()),
+            ..Default::default()
+        },
+        algorithm: parameters
+    }))
+}
+
+impl JwtKeys {
+    pub fn load(settings :JwtSettings, secret :&SecretKeyWrapper) -> Result<Self, String> {
+        let mut verification = HashMap::new();
+        let mut jwks = vec![];
+
+        let (header, encoding) = match &settings.signing_key {
+            Some(key) => {
+                let path = match &key.private_key {
+                    Some(path) => path,
+                    None => return Err(format!("Signing key {} has no private key.", key.kid))
+                };
+
+                let pem = match fs::read(path) {
+                    Ok(pem) => pem,
+                    Err(e) => return Err(format!("Failed to read key {}: {}", path, e))
+                };
+
+                let encoding = match key.algorithm {
+                    Algorithm::EdDSA => EncodingKey::from_ed_pem(&pem),
+                    algorithm if is_rsa(algorithm) => EncodingKey::from_rsa_pem(&pem),
+                    algorithm => return Err(format!("Key {} uses {:?}, only RSA and EdDSA keys are supported.", key.kid, algorithm))
+                };
+
+                let mut header = Header::new(key.algorithm);
+                header.kid = Some(key.kid.clone());
+
+                match encoding {
+                    Ok(encoding) => (header, encoding),
+                    Err(e) => return Err(format!("Failed to parse key {}: {}", path, e))
+                }
+            },
+            None => {
+                verification.insert(None, VerificationKey { algorithm: Algorithm::HS256, key: DecodingKey::from_secret(&secret.key) });
+                (Header::default(), EncodingKey::from_secret(&secret.key))
+            }
+        };
+
+        for key in settings.signing_key.iter().chain(settings.verification_keys.iter()) {
+            let (verification_key, jwk) = public_key(key)?;
+
+            if verification.insert(Some(key.kid.clone()), verification_key).is_some() {
+                return Err(format!("Key id {} is used more than once.", key.kid))
+            }
+
+            jwks.push(jwk);
+        }
+
+        Ok(Self {
+            lifetime: settings.lifetime,
+            issuer: settings.issuer,
+            audience: settings.audience,
+            header,
+            encoding,
+            verification,
+            jwks: JwkSet { keys: jwks }
+        })
+    }
+
+    pub fn encode<T :Serialize>(&self, claims :&T) -> Result<String, ApiError> {
+        let claims = RegisteredClaims {
+            claims,
+            iss: self.issuer.as_deref(),
+            aud: self.audience.as_deref()
+        };
+
+        match jsonwebtoken::encode(&self.header, &claims, &self.encoding) {
+            Ok(token) => Ok(token),
+            Err(e) => Err(ApiError::from(e))
+        }
+    }
+
+    // The key is picked by the token's `kid`, and only accepted for the algorithm it was configured with.
+    pub fn decode<T :DeserializeOwned>(&self, token :&str) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let key = match self.verification.get(&header.kid) {
+            Some(key) => key,
+            None => return Err(ErrorKind::InvalidToken.into())
+        };
+
+        let mut validation = Validation::new(key.algorithm);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            validation.required_spec_claims.insert("iss".to_string());
+        }
+        if let Some(audience) = &self.audience {
+            validation.set_audience(&[audience]);
+            validation.required_spec_claims.insert("aud".to_string());
+        }
+
+        jsonwebtoken::decode::<T>(token, &key.key, &validation).map(|data| data.claims)
+    }
+}
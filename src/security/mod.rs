@@ -0,0 +1,5 @@
+pub mod password;
+pub mod token;
+pub mod action;
+pub mod totp;
+pub mod jwt;
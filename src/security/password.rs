@@ -0,0 +1,67 @@
+use argon2::{Argon2, Algorithm, PasswordHasher, PasswordVerifier, password_hash::{SaltString, PasswordHash, rand_core::OsRng}};
+use subtle::ConstantTimeEq;
+
+use crate::errors::ApiError;
+
+#[derive(PartialEq)]
+pub enum PasswordVerification {
+    Invalid,
+    Valid,
+    // The password is correct, but the stored hash uses a legacy scheme or outdated
+    // parameters and should be replaced with a fresh hash.
+    ValidNeedsRehash
+}
+
+pub fn hash(password :&str) -> Result<String, ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(e) => Err(ApiError::Internal(e.to_string()))
+    }
+}
+
+pub fn verify(password :&str, stored :&str) -> PasswordVerification {
+    if is_legacy(stored) {
+        // Hashes created before the switch to Argon2id are plain hex SHA-256 digests.
+        let digest = sha256::digest(password);
+        return match bool::from(digest.as_bytes().ct_eq(stored.as_bytes())) {
+            true => PasswordVerification::ValidNeedsRehash,
+            false => PasswordVerification::Invalid
+        }
+    }
+
+    let parsed = match PasswordHash::new(stored) {
+        Ok(parsed) => parsed,
+        Err(_e) => return PasswordVerification::Invalid
+    };
+
+    // Argon2::verify_password compares the computed hash in constant time.
+    if Argon2::default().verify_password(password.as_bytes(), &parsed).is_err() {
+        return PasswordVerification::Invalid
+    }
+
+    match is_current(&parsed) {
+        true => PasswordVerification::Valid,
+        false => PasswordVerification::ValidNeedsRehash
+    }
+}
+
+fn is_legacy(stored :&str) -> bool {
+    stored.len() == 64 && stored.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_current(parsed :&PasswordHash) -> bool {
+    let current = Argon2::default();
+
+    if parsed.algorithm != Algorithm::Argon2id.ident() {
+        return false
+    }
+
+    match argon2::Params::try_from(parsed) {
+        Ok(params) => params.m_cost() == current.params().m_cost()
+            && params.t_cost() == current.params().t_cost()
+            && params.p_cost() == current.params().p_cost(),
+        Err(_e) => false
+    }
+}
@@ -0,0 +1,14 @@
+use rand::RngCore;
+
+// Generates an opaque, URL-safe random token with 256 bits of entropy.
+pub fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Opaque tokens are only ever stored as digests, so a leaked database does not leak usable tokens.
+pub fn digest(token :&str) -> String {
+    sha256::digest(token)
+}
@@ -0,0 +1,62 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+// RFC 6238 defaults, which is what authenticator apps expect.
+const STEP :u64 = 30;
+const DIGITS :u32 = 6;
+// Codes from one step before and after are accepted too, to make up for clock drift.
+const SKEW :i64 = 1;
+
+const ALPHABET :base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+// 160 random bits, base32 encoded the way authenticator apps take them.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    base32::encode(ALPHABET, &bytes)
+}
+
+pub fn uri(issuer :&str, account :&str, secret :&str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encode(issuer), encode(account), secret, encode(issuer), DIGITS, STEP
+    )
+}
+
+fn encode(text :&str) -> String {
+    text.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        b => format!("%{:02X}", b)
+    }).collect()
+}
+
+pub fn code(secret :&[u8], step :u64) -> String {
+    let mut mac = match Hmac::<Sha1>::new_from_slice(secret) {
+        Ok(mac) => mac,
+        Err(_e) => return String::new()
+    };
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, RFC 4226 section 5.3.
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+// Returns the time step the code belongs to, so that the caller can refuse it once it was used.
+pub fn verify(secret :&str, code :&str, now :u64) -> Option<u64> {
+    let secret = base32::decode(ALPHABET, secret)?;
+    let code = code.trim();
+    let current = (now / STEP) as i64;
+
+    (-SKEW..=SKEW)
+        .map(|offset| current + offset)
+        .filter(|step| *step >= 0)
+        .map(|step| step as u64)
+        .find(|step| bool::from(self::code(&secret, *step).as_bytes().ct_eq(code.as_bytes())))
+}
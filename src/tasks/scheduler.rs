@@ -0,0 +1,57 @@
+use std::{sync::Arc, time::Duration};
+
+use mongodb::bson::DateTime;
+use rocket::{fairing::AdHoc, tokio::{self, sync::Notify}};
+
+use crate::db::PostRepository;
+
+// Upper bound on how long the scheduler sleeps, in case a wake-up gets lost.
+const MAX_SLEEP :Duration = Duration::from_secs(60);
+
+// Flips scheduled posts to published once their `publish_at` has passed.
+#[derive(Clone, Default)]
+pub struct PostScheduler {
+    wake :Arc<Notify>
+}
+
+impl PostScheduler {
+    // Makes the scheduler re-check when the next post is due, e.g. after a post got (re)scheduled.
+    pub fn reschedule(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_liftoff("Post scheduler", |rocket| Box::pin(async move {
+            let posts = rocket.state::<Arc<dyn PostRepository>>().cloned();
+            let scheduler = rocket.state::<PostScheduler>().cloned();
+
+            match (posts, scheduler) {
+                (Some(posts), Some(scheduler)) => {
+                    tokio::spawn(async move { scheduler.run(posts).await });
+                },
+                _ => error!("Post scheduler is missing its managed state, scheduled posts won't be published.")
+            }
+        }))
+    }
+
+    async fn run(self, posts :Arc<dyn PostRepository>) {
+        loop {
+            let sleep = match posts.publish_scheduled().await {
+                Ok(Some(next)) => {
+                    let due_in = next.timestamp_millis() - DateTime::now().timestamp_millis();
+                    Duration::from_millis(due_in.max(0) as u64).min(MAX_SLEEP)
+                },
+                Ok(None) => MAX_SLEEP,
+                Err(e) => {
+                    warn!("Failed to publish scheduled posts: {}", e.message());
+                    MAX_SLEEP
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => (),
+                _ = self.wake.notified() => ()
+            }
+        }
+    }
+}
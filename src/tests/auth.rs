@@ -0,0 +1,245 @@
+use rocket::{http::{Header, Status}, serde::json::serde_json::json};
+
+use crate::{middlewares::auth::SecretKeyWrapper, models::user::{UserStoreModel, UserPermissionLevel},
+    security::action::{self, ActionPurpose}};
+
+use super::{client, client_with, config, seed_user, sign_in, users, bearer, body, PASSWORD};
+
+#[rocket::async_test]
+async fn sign_in_issues_tokens() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let tokens = body(response).await;
+    assert!(tokens["token"].is_string());
+    assert!(tokens["refresh_token"].is_string());
+}
+
+#[rocket::async_test]
+async fn sign_in_does_not_tell_unknown_users_from_wrong_passwords() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let wrong_password = client.post("/auth").json(&json!({"name": "alice", "password": "guess"})).dispatch().await;
+    assert_eq!(wrong_password.status(), Status::Unauthorized);
+    let wrong_password = body(wrong_password).await;
+
+    let unknown_user = client.post("/auth").json(&json!({"name": "mallory", "password": "guess"})).dispatch().await;
+    assert_eq!(unknown_user.status(), Status::Unauthorized);
+
+    assert_eq!(body(unknown_user).await, wrong_password);
+}
+
+#[rocket::async_test]
+async fn repeated_failures_lock_the_account() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    for _ in 0..5 {
+        let response = client.post("/auth").json(&json!({"name": "alice", "password": "guess"})).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    // Even the right password is turned away while locked out.
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+}
+
+#[rocket::async_test]
+async fn spraying_locks_the_address_whatever_it_claims_to_be() {
+    let client = client_with(config().merge(("auth.max_failed_logins_per_address", 3))).await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let remote = "192.0.2.1:40000".parse().unwrap();
+
+    for (name, claimed) in [("bob", "10.0.0.1"), ("carol", "10.0.0.2"), ("dave", "10.0.0.3")] {
+        let response = client.post("/auth")
+            .remote(remote)
+            .header(Header::new("X-Real-IP", claimed))
+            .json(&json!({"name": name, "password": "guess"}))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    let response = client.post("/auth")
+        .remote(remote)
+        .header(Header::new("X-Real-IP", "10.0.0.4"))
+        .json(&json!({"name": "alice", "password": PASSWORD}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+}
+
+#[rocket::async_test]
+async fn register_needs_a_valid_invite() {
+    let client = client().await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let token = sign_in(&client, "admin").await;
+
+    let response = client.post("/auth/register")
+        .json(&json!({"name": "bob", "password": PASSWORD, "invite": "made-up"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.post("/invites").header(bearer(&token)).json(&json!({})).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let code = body(response).await["code"].as_str().unwrap().to_string();
+
+    let response = client.post("/auth/register")
+        .json(&json!({"name": "bob", "password": PASSWORD, "invite": &code}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(body(response).await["permissions"], "Author");
+
+    // Invites are good for one account by default.
+    let response = client.post("/auth/register")
+        .json(&json!({"name": "carol", "password": PASSWORD, "invite": &code}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    sign_in(&client, "bob").await;
+}
+
+#[rocket::async_test]
+async fn register_refuses_taken_names() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let response = client.post("/auth/register")
+        .json(&json!({"name": "alice", "password": PASSWORD, "invite": "made-up"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn refresh_rotates_the_token_and_detects_reuse() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    let first = body(response).await["refresh_token"].as_str().unwrap().to_string();
+
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &first})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let tokens = body(response).await;
+    let second = tokens["refresh_token"].as_str().unwrap().to_string();
+    let token = tokens["token"].as_str().unwrap().to_string();
+    assert_ne!(first, second);
+
+    // Presenting the rotated out token again revokes the whole session.
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &first})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &second})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth/logout").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn logout_ends_the_session() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let token = sign_in(&client, "alice").await;
+
+    let response = client.post("/auth/logout").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client.post("/auth/logout").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn forgot_is_accepted_for_any_address() {
+    let client = client().await;
+
+    let response = client.post("/auth/forgot").json(&json!({"email": "nobody@example.com"})).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = client.post("/auth/forgot").json(&json!({"email": " "})).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn reset_changes_the_password_once() {
+    let client = client().await;
+    let user = seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let token = sign_in(&client, "alice").await;
+
+    let secret = client.rocket().state::<SecretKeyWrapper>().unwrap();
+    let reset = action::sign(secret, ActionPurpose::ResetPassword, &user).unwrap();
+
+    let response = client.post("/auth/reset").json(&json!({"token": &reset, "password": "a new password"})).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": "a new password"})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Sessions started with the old password are gone.
+    let response = client.post("/auth/logout").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth/reset").json(&json!({"token": &reset, "password": "yet another one"})).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn verify_confirms_the_address() {
+    let client = client().await;
+    let user = seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let user = UserStoreModel { email: Some("alice@example.com".to_string()), ..user };
+    users(&client).replace(&user).await.unwrap();
+    let token = sign_in(&client, "alice").await;
+
+    let response = client.post("/auth/verify/resend").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let secret = client.rocket().state::<SecretKeyWrapper>().unwrap();
+    let verify = action::sign(secret, ActionPurpose::VerifyEmail, &user).unwrap();
+
+    let response = client.post("/auth/verify").json(&json!({"token": verify})).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(users(&client).find(&user._id).await.unwrap().unwrap().email_verified);
+
+    let response = client.post("/auth/verify/resend").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn verify_rejects_tokens_for_other_purposes() {
+    let client = client().await;
+    let user = seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let secret = client.rocket().state::<SecretKeyWrapper>().unwrap();
+    let reset = action::sign(secret, ActionPurpose::ResetPassword, &user).unwrap();
+
+    let response = client.post("/auth/verify").json(&json!({"token": reset})).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn resend_verification_needs_an_address() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let token = sign_in(&client, "alice").await;
+
+    let response = client.post("/auth/verify/resend").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.post("/auth/verify/resend").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn jwks_hands_out_no_shared_secret() {
+    let client = client().await;
+
+    let response = client.get("/.well-known/jwks.json").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await, json!({"keys": []}));
+}
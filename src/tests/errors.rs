@@ -0,0 +1,134 @@
+use rocket::{http::{ContentType, Header, Status}, response::Responder, serde::json::{Value, serde_json::{self, json}}};
+
+use crate::{errors::ApiError, models::user::UserPermissionLevel};
+
+use super::{assert_denied, client, client_with, config, seed_user, sign_in, bearer, body};
+
+#[rocket::async_test]
+async fn missing_credentials_are_caught_as_unauthorized() {
+    let client = client().await;
+
+    let response = client.post("/posts").json(&json!({"title": "Hello", "content": "World"})).dispatch().await;
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(body(response).await["detail"], "You need to authenticate to access this resource.");
+}
+
+#[rocket::async_test]
+async fn invalid_tokens_are_caught_as_forbidden() {
+    let client = client().await;
+
+    let response = client.get("/posts").header(bearer("not-a-token")).dispatch().await;
+
+    assert_denied(response).await;
+}
+
+#[rocket::async_test]
+async fn missing_capabilities_are_caught_as_forbidden() {
+    let client = client().await;
+    seed_user(&client, "reader", UserPermissionLevel::Reader).await;
+    let token = sign_in(&client, "reader").await;
+
+    let response = client.post("/posts").header(bearer(&token)).json(&json!({"title": "Hello", "content": "World"})).dispatch().await;
+
+    assert_denied(response).await;
+}
+
+#[rocket::async_test]
+async fn rate_limits_are_caught_as_too_many_requests() {
+    let client = client_with(config()
+        .merge(("rate_limit.enabled", true))
+        .merge(("rate_limit.auth", json!({"burst": 1, "per_minute": 1})))
+    ).await;
+
+    let forgot = json!({"email": "nobody@example.com"});
+    let response = client.post("/auth/forgot").json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = client.post("/auth/forgot").json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert!(response.headers().get_one("Retry-After").is_some());
+
+    let message = body(response).await["detail"].as_str().unwrap().to_string();
+    assert!(message.starts_with("Too many requests, try again in"));
+}
+
+#[rocket::async_test]
+async fn rate_limits_ignore_client_supplied_addresses() {
+    let limited = config()
+        .merge(("rate_limit.enabled", true))
+        .merge(("rate_limit.auth", json!({"burst": 1, "per_minute": 1})));
+    let forgot = json!({"email": "nobody@example.com"});
+
+    let client = client_with(limited.clone()).await;
+    let response = client.post("/auth/forgot").header(Header::new("X-Real-IP", "10.0.0.1")).json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+    let response = client.post("/auth/forgot").header(Header::new("X-Real-IP", "10.0.0.2")).json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+
+    // Behind a trusted proxy the header is the client's address.
+    let client = client_with(limited.merge(("proxy.trusted", true))).await;
+    let response = client.post("/auth/forgot").header(Header::new("X-Real-IP", "10.0.0.1")).json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+    let response = client.post("/auth/forgot").header(Header::new("X-Real-IP", "10.0.0.2")).json(&forgot).dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+}
+
+#[rocket::async_test]
+async fn malformed_authorization_headers_are_rejected() {
+    let client = client().await;
+
+    let response = client.get("/posts").header(Header::new("Authorization", "Basic")).dispatch().await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn errors_are_answered_as_problems() {
+    let client = client().await;
+    seed_user(&client, "reader", UserPermissionLevel::Reader).await;
+    let token = sign_in(&client, "reader").await;
+
+    let response = client.post("/posts").header(bearer(&token)).json(&json!({"title": "Hello", "content": "World"})).dispatch().await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(response.content_type(), Some(ContentType::new("application", "problem+json")));
+    let problem = body(response).await;
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Forbidden");
+    assert_eq!(problem["status"], 403);
+    assert_eq!(problem["code"], "forbidden");
+}
+
+#[rocket::async_test]
+async fn unknown_routes_are_caught_as_not_found() {
+    let client = client().await;
+
+    let response = client.get("/nowhere").dispatch().await;
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(body(response).await["code"], "not_found");
+}
+
+#[rocket::async_test]
+async fn unreadable_bodies_are_caught_as_unprocessable() {
+    let client = client().await;
+
+    let response = client.post("/auth").header(ContentType::JSON).body("{\"name\": 42}").dispatch().await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(body(response).await["code"], "unprocessable");
+}
+
+#[rocket::async_test]
+async fn internal_errors_keep_their_cause_to_the_logs() {
+    let client = client().await;
+    let request = client.get("/");
+
+    let mut response = ApiError::Internal("connection refused by 10.0.0.7".to_string()).respond_to(&request).unwrap();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    let problem :Value = serde_json::from_str(&response.body_mut().to_string().await.unwrap()).unwrap();
+    assert_eq!(problem["code"], "internal");
+    assert!(!problem["detail"].as_str().unwrap().contains("10.0.0.7"));
+}
@@ -0,0 +1,95 @@
+// End-to-end tests. Every test runs the whole app on storage of its own in memory, so they need no
+// database and don't see each other's data. `RKBLOG_TEST_URI=sqlite::memory:` runs them on SQLite instead.
+mod auth;
+mod errors;
+mod post;
+mod storage;
+mod user;
+
+use std::sync::Arc;
+
+use rocket::{Config, figment::Figment, local::asynchronous::{Client, LocalResponse}, http::{Header, Status},
+    serde::json::{Value, serde_json::json}};
+
+use crate::{db::{self, UserRepository}, models::user::{UserStoreModel, UserWriteModel, UserPermissionLevel}};
+
+pub const PASSWORD :&str = "correct horse battery staple";
+
+// Rate limits are off, the tests sign in far more often than any client would.
+pub fn config() -> Figment {
+    Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("rate_limit.enabled", false))
+}
+
+pub async fn client_with(config :Figment) -> Client {
+    let uri = std::env::var("RKBLOG_TEST_URI").unwrap_or_else(|_e| "memory:".to_string());
+    client_on(&uri, config).await
+}
+
+pub async fn client_on(uri :&str, config :Figment) -> Client {
+    let db = db::connect(uri, "").await.unwrap();
+    Client::tracked(super::app(rocket::custom(config), db).await).await.unwrap()
+}
+
+pub async fn client() -> Client {
+    client_with(config()).await
+}
+
+// Puts a user straight into the storage. Through the routes, only admins could hand out roles.
+pub async fn seed_user(client :&Client, name :&str, permissions :UserPermissionLevel) -> UserStoreModel {
+    let user = UserStoreModel::new(UserWriteModel {
+        name: name.to_string(),
+        password: PASSWORD.to_string(),
+        permissions,
+        email: None,
+        bio: format!("Hi, I'm {}.", name)
+    }).unwrap();
+
+    users(client).insert(&user).await.unwrap();
+    user
+}
+
+pub fn users(client :&Client) -> &Arc<dyn UserRepository> {
+    client.rocket().state::<Arc<dyn UserRepository>>().unwrap()
+}
+
+pub async fn sign_in(client :&Client, name :&str) -> String {
+    let response = client.post("/auth").json(&json!({"name": name, "password": PASSWORD})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    body(response).await["token"].as_str().unwrap().to_string()
+}
+
+pub fn bearer(token :&str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+pub async fn body(response :LocalResponse<'_>) -> Value {
+    response.into_json::<Value>().await.unwrap()
+}
+
+// Turned away by one of the authorization guards, and answered by the forbidden catcher.
+pub async fn assert_denied(response :LocalResponse<'_>) {
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(body(response).await["detail"], "You don't have permission to access this resource.");
+}
+
+// Creates a post as the signed in user and returns its slug.
+pub async fn create_post(client :&Client, token :&str, title :&str, tags :&[&str]) -> String {
+    let response = client.post("/posts")
+        .header(bearer(token))
+        .json(&json!({"title": title, "content": format!("All about {}.", title), "tags": tags}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    body(response).await["slug"].as_str().unwrap().to_string()
+}
+
+pub async fn publish(client :&Client, token :&str, slug :&str) {
+    let response = client.put(format!("/posts/{}/status", slug))
+        .header(bearer(token))
+        .json(&json!({"status": "Published"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
@@ -0,0 +1,510 @@
+use std::sync::Arc;
+
+use rocket::{http::{Header, Status}, serde::json::serde_json::json};
+
+use crate::{db::{CommentRepository, RevisionRepository, PostRepository}, models::user::UserPermissionLevel};
+
+use super::{assert_denied, client, seed_user, sign_in, bearer, body, create_post, publish};
+
+#[rocket::async_test]
+async fn create_needs_a_signed_in_author() {
+    let client = client().await;
+    seed_user(&client, "reader", UserPermissionLevel::Reader).await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let post = json!({"title": "Hello", "content": "World"});
+
+    let response = client.post("/posts").json(&post).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let token = sign_in(&client, "reader").await;
+    let response = client.post("/posts").header(bearer(&token)).json(&post).dispatch().await;
+    assert_denied(response).await;
+
+    let token = sign_in(&client, "alice").await;
+    let response = client.post("/posts").header(bearer(&token)).json(&post).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.headers().get_one("Location"), Some("hello"));
+
+    let post = body(response).await;
+    assert_eq!(post["status"], "Draft");
+    assert_eq!(post["author"]["name"], "alice");
+}
+
+#[rocket::async_test]
+async fn create_refuses_duplicate_titles() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let token = sign_in(&client, "alice").await;
+    create_post(&client, &token, "Hello", &[]).await;
+
+    let response = client.post("/posts").header(bearer(&token)).json(&json!({"title": "Hello", "content": "Again"})).dispatch().await;
+
+    assert_eq!(response.status(), Status::Conflict);
+    let problem = body(response).await;
+    assert_eq!(problem["code"], "conflict");
+    assert_eq!(problem["detail"], "Post Hello already exists.");
+}
+
+#[rocket::async_test]
+async fn renaming_onto_a_taken_title_conflicts() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let token = sign_in(&client, "alice").await;
+    create_post(&client, &token, "Hello", &[]).await;
+    let slug = create_post(&client, &token, "World", &[]).await;
+    let path = format!("/posts/{}", slug);
+
+    let response = client.put(&path).header(bearer(&token)).json(&json!({"title": "Hello", "content": "Renamed."})).dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+    let problem = body(response).await;
+    assert_eq!(problem["code"], "conflict");
+    assert_eq!(problem["detail"], "Post Hello already exists.");
+
+    // Keeping the title isn't a clash with itself.
+    let response = client.put(&path).header(bearer(&token)).json(&json!({"title": "World", "content": "Edited."})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn drafts_are_only_visible_to_their_author_and_editors() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    seed_user(&client, "editor", UserPermissionLevel::Editor).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Work in progress", &[]).await;
+    let path = format!("/posts/{}", slug);
+
+    let response = client.get(&path).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let bob = sign_in(&client, "bob").await;
+    let response = client.get(&path).header(bearer(&bob)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.get(&path).header(bearer(&alice)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let editor = sign_in(&client, "editor").await;
+    let response = client.get(&path).header(bearer(&editor)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    publish(&client, &alice, &slug).await;
+
+    let response = client.get(&path).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(body(response).await["publish_at"].is_string());
+}
+
+#[rocket::async_test]
+async fn drafts_stay_with_their_author_after_an_editor_edits_them() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "editor", UserPermissionLevel::Editor).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Work in progress", &[]).await;
+    let path = format!("/posts/{}", slug);
+
+    let editor = sign_in(&client, "editor").await;
+    let response = client.put(&path).header(bearer(&editor)).json(&json!({"title": "Work in progress", "content": "Tidied up."})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get(&path).header(bearer(&alice)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["author"]["name"], "alice");
+
+    publish(&client, &alice, &slug).await;
+}
+
+#[rocket::async_test]
+async fn publishing_needs_the_capability() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Hello", &[]).await;
+
+    // Take the capability away from the author role after the fact.
+    let author_role = json!({"capabilities": ["post:create", "comment:create"]});
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let admin = sign_in(&client, "admin").await;
+    let response = client.put("/roles/Author").header(bearer(&admin)).json(&author_role).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.put(format!("/posts/{}/status", slug))
+        .header(bearer(&alice))
+        .json(&json!({"status": "Published"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.put(format!("/posts/{}/status", slug))
+        .header(bearer(&alice))
+        .json(&json!({"status": "Archived"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn transitions_are_checked() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Hello", &[]).await;
+    let path = format!("/posts/{}/status", slug);
+
+    let response = client.put(&path).header(bearer(&alice)).json(&json!({"status": "Draft"})).dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client.put(&path).header(bearer(&alice)).json(&json!({"status": "Scheduled"})).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.put(&path)
+        .header(bearer(&alice))
+        .json(&json!({"status": "Scheduled", "publish_at": "2000-01-01T00:00:00Z"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.put(&path)
+        .header(bearer(&alice))
+        .json(&json!({"status": "Scheduled", "publish_at": "2999-01-01T00:00:00Z"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["status"], "Scheduled");
+
+    publish(&client, &alice, &slug).await;
+
+    let response = client.put(&path)
+        .header(bearer(&alice))
+        .json(&json!({"status": "Scheduled", "publish_at": "2999-01-01T00:00:00Z"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn republishing_an_archived_post_dates_it_if_it_never_went_out() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let draft = create_post(&client, &alice, "Draft", &[]).await;
+    let scheduled = create_post(&client, &alice, "Scheduled", &[]).await;
+
+    let response = client.put(format!("/posts/{}/status", scheduled))
+        .header(bearer(&alice))
+        .json(&json!({"status": "Scheduled", "publish_at": "2999-01-01T00:00:00Z"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    for slug in [draft, scheduled] {
+        let path = format!("/posts/{}/status", slug);
+        let response = client.put(&path).header(bearer(&alice)).json(&json!({"status": "Archived"})).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.put(&path).header(bearer(&alice)).json(&json!({"status": "Published"})).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let publish_at = body(response).await["publish_at"].as_str().unwrap().to_string();
+        assert!(publish_at.as_str() < "2999", "{} is in the future", publish_at);
+    }
+}
+
+#[rocket::async_test]
+async fn only_the_author_and_editors_may_update() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    seed_user(&client, "editor", UserPermissionLevel::Editor).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Hello", &[]).await;
+    publish(&client, &alice, &slug).await;
+    let path = format!("/posts/{}", slug);
+    let update = json!({"title": "Hello", "content": "Edited."});
+
+    let response = client.put(&path).json(&update).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let bob = sign_in(&client, "bob").await;
+    let response = client.put(&path).header(bearer(&bob)).json(&update).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.put(&path).header(bearer(&alice)).json(&update).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["content"], "Edited.");
+
+    let editor = sign_in(&client, "editor").await;
+    let response = client.put(&path).header(bearer(&editor)).json(&update).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    // The post stays Alice's, the editor only shows up in the history.
+    assert_eq!(body(response).await["author"]["name"], "alice");
+
+    let response = client.get(format!("{}/revisions", path)).header(bearer(&alice)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let revisions = body(response).await;
+    let editors :Vec<&str> = revisions.as_array().unwrap().iter().map(|revision| revision["author"].as_str().unwrap()).collect();
+    assert_eq!(editors.len(), 3);
+    assert!(editors.contains(&"editor"), "{:?}", editors);
+    assert_eq!(editors.iter().filter(|editor| **editor == "alice").count(), 2);
+
+    let response = client.put("/posts/missing").header(bearer(&alice)).json(&update).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn renaming_redirects_the_old_slug() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Hello", &[]).await;
+    publish(&client, &alice, &slug).await;
+
+    let response = client.put(format!("/posts/{}", slug))
+        .header(bearer(&alice))
+        .json(&json!({"title": "Hello again", "content": "Renamed."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["slug"], "hello-again");
+
+    let response = client.get("/posts/hello").dispatch().await;
+    assert_eq!(response.status(), Status::MovedPermanently);
+    assert_eq!(response.headers().get_one("Location"), Some("/posts/hello-again"));
+
+    let response = client.get("/posts/hello-again").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn only_footnote_anchors_are_prefixed() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    let response = client.post("/posts")
+        .header(bearer(&alice))
+        .json(&json!({"title": "Notes", "content": "See [the comments](#comments).[^1]\n\n[^1]: A footnote.\n"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    let post = body(response).await;
+    let html = post["content_html"].as_str().unwrap();
+    assert!(html.contains("href=\"#comments\""), "{}", html);
+    assert!(html.contains("href=\"#fn-1\""), "{}", html);
+    assert!(html.contains("id=\"fn-1\""), "{}", html);
+}
+
+#[rocket::async_test]
+async fn delete_takes_comments_and_revisions_along() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let slug = create_post(&client, &alice, "Hello", &[]).await;
+    publish(&client, &alice, &slug).await;
+
+    let response = client.post(format!("/posts/{}/comments", slug))
+        .header(bearer(&alice))
+        .json(&json!({"content": "First!"}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    let bob = sign_in(&client, "bob").await;
+    let response = client.delete(format!("/posts/{}", slug)).header(bearer(&bob)).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.delete(format!("/posts/{}", slug)).header(bearer(&alice)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let id = body(response).await["_id"].as_str().unwrap().parse().unwrap();
+
+    let response = client.get(format!("/posts/{}", slug)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let posts = client.rocket().state::<Arc<dyn PostRepository>>().unwrap();
+    assert!(posts.find(&slug).await.unwrap().is_none());
+
+    let comments = client.rocket().state::<Arc<dyn CommentRepository>>().unwrap();
+    assert!(comments.list(&id).await.unwrap().is_empty());
+
+    let revisions = client.rocket().state::<Arc<dyn RevisionRepository>>().unwrap();
+    assert!(revisions.list(&id).await.unwrap().is_empty());
+}
+
+#[rocket::async_test]
+async fn list_filters_by_author_tag_and_status() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    let bob = sign_in(&client, "bob").await;
+
+    let first = create_post(&client, &alice, "First", &["Rust"]).await;
+    publish(&client, &alice, &first).await;
+    let second = create_post(&client, &bob, "Second", &["rust", "web"]).await;
+    publish(&client, &bob, &second).await;
+    create_post(&client, &alice, "Third", &["rust"]).await;
+
+    let response = client.get("/posts").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["total"], 2);
+
+    // Alice sees her own draft as well.
+    let response = client.get("/posts").header(bearer(&alice)).dispatch().await;
+    assert_eq!(body(response).await["total"], 3);
+
+    let response = client.get("/posts?author=bob").dispatch().await;
+    let posts = body(response).await;
+    assert_eq!(posts["total"], 1);
+    assert_eq!(posts["items"][0]["slug"], "second");
+
+    let response = client.get("/posts?author=nobody").dispatch().await;
+    assert_eq!(body(response).await["total"], 0);
+
+    let response = client.get("/posts?tag=RUST").dispatch().await;
+    assert_eq!(body(response).await["total"], 2);
+
+    let response = client.get("/posts?tag=web").dispatch().await;
+    assert_eq!(body(response).await["total"], 1);
+
+    let response = client.get("/posts?status=Draft").header(bearer(&alice)).dispatch().await;
+    let posts = body(response).await;
+    assert_eq!(posts["total"], 1);
+    assert_eq!(posts["items"][0]["slug"], "third");
+
+    let response = client.get("/posts?status=Nonsense").dispatch().await;
+    assert_eq!(body(response).await["total"], 0);
+}
+
+#[rocket::async_test]
+async fn list_pages_through_all_posts() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    for title in ["Alpha", "Bravo", "Charlie", "Delta", "Echo"] {
+        let slug = create_post(&client, &alice, title, &[]).await;
+        publish(&client, &alice, &slug).await;
+    }
+
+    let mut titles :Vec<String> = vec![];
+    let mut path = "/posts?sort=title&limit=2".to_string();
+    loop {
+        let response = client.get(&path).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let page = body(response).await;
+        assert_eq!(page["total"], 5);
+
+        for post in page["items"].as_array().unwrap() {
+            titles.push(post["title"].as_str().unwrap().to_string());
+        }
+
+        match page["next_cursor"].as_str() {
+            Some(cursor) => path = format!("/posts?sort=title&limit=2&cursor={}", cursor),
+            None => break
+        }
+    }
+    assert_eq!(titles, ["Alpha", "Bravo", "Charlie", "Delta", "Echo"]);
+
+    let response = client.get("/posts?sort=-title&limit=1").dispatch().await;
+    assert_eq!(body(response).await["items"][0]["title"], "Echo");
+
+    let response = client.get("/posts?sort=author").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.get("/posts?cursor=garbage").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn paging_by_publication_steps_over_drafts() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    for (title, published) in [("Alpha", false), ("Bravo", true), ("Charlie", false), ("Delta", true)] {
+        let slug = create_post(&client, &alice, title, &[]).await;
+        if published {
+            publish(&client, &alice, &slug).await;
+        }
+    }
+
+    // Drafts have no publication date, they come before every published post.
+    for (sort, expected) in [("published", ["Alpha", "Charlie", "Bravo", "Delta"]), ("-published", ["Delta", "Bravo", "Charlie", "Alpha"])] {
+        let mut titles :Vec<String> = vec![];
+        let mut path = format!("/posts?sort={}&limit=1", sort);
+        loop {
+            let response = client.get(&path).header(bearer(&alice)).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let page = body(response).await;
+
+            for post in page["items"].as_array().unwrap() {
+                titles.push(post["title"].as_str().unwrap().to_string());
+            }
+
+            match page["next_cursor"].as_str() {
+                Some(cursor) => path = format!("/posts?sort={}&limit=1&cursor={}", sort, cursor),
+                None => break
+            }
+        }
+        assert_eq!(titles, expected);
+    }
+}
+
+#[rocket::async_test]
+async fn search_ranks_title_matches_first() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    let response = client.post("/posts")
+        .header(bearer(&alice))
+        .json(&json!({"title": "Cooking", "content": "Pasta, and a word about rockets."}))
+        .dispatch().await;
+    let cooking = body(response).await["slug"].as_str().unwrap().to_string();
+    publish(&client, &alice, &cooking).await;
+
+    let rockets = create_post(&client, &alice, "Rockets", &[]).await;
+    publish(&client, &alice, &rockets).await;
+
+    let unrelated = create_post(&client, &alice, "Gardening", &[]).await;
+    publish(&client, &alice, &unrelated).await;
+
+    // Drafts never show up for anyone else.
+    create_post(&client, &alice, "More rockets", &[]).await;
+
+    let response = client.get("/posts/search?q=rockets").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let results = body(response).await;
+    assert_eq!(results["total"], 2);
+    assert_eq!(results["items"][0]["slug"], "rockets");
+    assert_eq!(results["items"][1]["slug"], "cooking");
+
+    let response = client.get("/posts/search?q=rockets%20-pasta").dispatch().await;
+    assert_eq!(body(response).await["total"], 1);
+
+    let response = client.get("/posts/search?q=%20").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn feeds_go_stale_when_a_post_is_archived() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    for title in ["First", "Second"] {
+        let slug = create_post(&client, &alice, title, &[]).await;
+        publish(&client, &alice, &slug).await;
+    }
+
+    let response = client.get("/feed.rss").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    let last_modified = response.headers().get_one("Last-Modified").unwrap().to_string();
+    assert!(response.into_string().await.unwrap().contains("<dc:creator>alice</dc:creator>"));
+
+    let response = client.get("/feed.rss").header(Header::new("If-None-Match", etag.clone())).dispatch().await;
+    assert_eq!(response.status(), Status::NotModified);
+
+    let response = client.put("/posts/second/status").header(bearer(&alice)).json(&json!({"status": "Archived"})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Nothing left in the feed is newer than before, only the tag gives it away.
+    let response = client.get("/feed.rss").header(Header::new("If-None-Match", etag)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/feed.rss").header(Header::new("If-Modified-Since", last_modified)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
@@ -0,0 +1,174 @@
+// The SQL backend, exercised on SQLite whatever `RKBLOG_TEST_URI` says, in the places where it
+// does the most work of its own: paging, search, tags, token rotation, counters and timestamps.
+use std::sync::Arc;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rocket::{http::Status, local::asynchronous::Client, serde::json::serde_json::json};
+
+use crate::{db::{self, PostRepository}, models::{user::{UserStoreModel, UserPermissionLevel}, post::PostStoreModel}};
+
+use super::{config, client_on, seed_user, sign_in, users, bearer, body, create_post, publish, PASSWORD};
+
+async fn client() -> Client {
+    client_on("sqlite::memory:", config()).await
+}
+
+#[rocket::async_test]
+async fn posts_page_and_search() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    for title in ["Alpha", "Bravo", "Charlie", "Delta rockets"] {
+        let slug = create_post(&client, &alice, title, &["letters"]).await;
+        publish(&client, &alice, &slug).await;
+    }
+    let response = client.post("/posts")
+        .header(bearer(&alice))
+        .json(&json!({"title": "Echo", "content": "Rockets, rockets and more rockets.", "tags": ["letters", "space"]}))
+        .dispatch().await;
+    let echo = body(response).await["slug"].as_str().unwrap().to_string();
+    publish(&client, &alice, &echo).await;
+
+    let mut titles :Vec<String> = vec![];
+    let mut path = "/posts?sort=-title&limit=2".to_string();
+    loop {
+        let page = body(client.get(&path).dispatch().await).await;
+        assert_eq!(page["total"], 5);
+
+        for post in page["items"].as_array().unwrap() {
+            titles.push(post["title"].as_str().unwrap().to_string());
+        }
+
+        match page["next_cursor"].as_str() {
+            Some(cursor) => path = format!("/posts?sort=-title&limit=2&cursor={}", cursor),
+            None => break
+        }
+    }
+    assert_eq!(titles, ["Echo", "Delta rockets", "Charlie", "Bravo", "Alpha"]);
+
+    let response = client.get("/posts?tag=space").dispatch().await;
+    assert_eq!(body(response).await["items"][0]["tags"], json!(["letters", "space"]));
+
+    // One title match outweighs three in the content.
+    let results = body(client.get("/posts/search?q=rockets").dispatch().await).await;
+    assert_eq!(results["total"], 2);
+    assert_eq!(results["items"][0]["slug"], "delta-rockets");
+    assert_eq!(results["items"][1]["slug"], "echo");
+
+    let results = body(client.get("/posts/search?q=rockets%20-more").dispatch().await).await;
+    assert_eq!(results["total"], 1);
+}
+
+#[rocket::async_test]
+async fn tags_are_counted_and_merged() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Editor).await;
+    let alice = sign_in(&client, "alice").await;
+
+    for (title, tags) in [("One", vec!["rust", "web"]), ("Two", vec!["rustlang", "rust"]), ("Three", vec!["rustlang"])] {
+        let slug = create_post(&client, &alice, title, &tags).await;
+        publish(&client, &alice, &slug).await;
+    }
+
+    let response = client.post("/tags/rustlang/merge").header(bearer(&alice)).json(&json!({"into": "rust"})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["posts"], 3);
+
+    let response = client.get("/tags").dispatch().await;
+    assert_eq!(body(response).await, json!([{"name": "rust", "posts": 3}, {"name": "web", "posts": 1}]));
+
+    let response = client.get("/posts/two").dispatch().await;
+    assert_eq!(body(response).await["tags"], json!(["rust"]));
+}
+
+#[rocket::async_test]
+async fn refresh_tokens_rotate_once() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    let first = body(response).await["refresh_token"].as_str().unwrap().to_string();
+
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &first})).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let second = body(response).await["refresh_token"].as_str().unwrap().to_string();
+
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &first})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth/refresh").json(&json!({"refresh_token": &second})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn timestamps_keep_every_millisecond() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+    create_post(&client, &alice, "Hello", &[]).await;
+
+    let posts = client.rocket().state::<Arc<dyn PostRepository>>().unwrap();
+    let at = DateTime::from_millis(1_790_000_000_123);
+    let hello = PostStoreModel { publish_at: Some(at), ..posts.find("hello").await.unwrap().unwrap() };
+    posts.set_status(&hello).await.unwrap();
+
+    assert_eq!(posts.find("hello").await.unwrap().unwrap().publish_at, Some(at));
+}
+
+#[rocket::async_test]
+async fn failures_are_counted_per_account() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    for _ in 0..5 {
+        let response = client.post("/auth").json(&json!({"name": "alice", "password": "guess"})).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    let response = client.post("/auth").json(&json!({"name": "alice", "password": PASSWORD})).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+}
+
+#[rocket::async_test]
+async fn migrations_run_once() {
+    let path = std::env::temp_dir().join(format!("rkblog-{}.db", ObjectId::new()));
+    let uri = format!("sqlite:{}", path.display());
+
+    let client = client_on(&uri, config()).await;
+    let alice = seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    drop(client);
+
+    // Opening the database again finds the schema in place, and the data in it.
+    let db = db::connect(&uri, "").await.unwrap();
+    assert_eq!(db.users.find(&alice._id).await.unwrap().unwrap().name, "alice");
+
+    let client = client_on(&uri, config()).await;
+    assert!(users(&client).find_by_name("alice").await.unwrap().is_some());
+
+    std::fs::remove_file(path).ok();
+}
+
+#[rocket::async_test]
+async fn names_and_titles_are_unique_in_storage() {
+    for uri in ["memory:", "sqlite::memory:"] {
+        let client = client_on(uri, config()).await;
+        let alice = seed_user(&client, "alice", UserPermissionLevel::Author).await;
+        let token = sign_in(&client, "alice").await;
+        create_post(&client, &token, "Hello", &[]).await;
+
+        // What the routes check for beforehand, written anyway, as two requests at once could.
+        let twin = UserStoreModel { _id: ObjectId::new(), ..alice };
+        assert_eq!(users(&client).insert(&twin).await.unwrap_err().status(), Status::Conflict);
+
+        let posts = client.rocket().state::<Arc<dyn PostRepository>>().unwrap();
+        let hello = posts.find("hello").await.unwrap().unwrap();
+        let twin = PostStoreModel { _id: ObjectId::new(), slug: "hello-2".to_string(), ..hello.clone() };
+        assert_eq!(posts.insert(&twin).await.unwrap_err().status(), Status::Conflict);
+
+        create_post(&client, &token, "World", &[]).await;
+        let world = posts.find("world").await.unwrap().unwrap();
+        let renamed = PostStoreModel { title: hello.title, revision: world.revision + 1, ..world };
+        assert_eq!(posts.replace(&renamed).await.unwrap_err().status(), Status::Conflict);
+    }
+}
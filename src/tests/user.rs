@@ -0,0 +1,291 @@
+use std::sync::Arc;
+
+use rocket::{http::{Header, Status}, serde::json::serde_json::json};
+
+use crate::{db::{CommentRepository, RevisionRepository, PostRepository}, models::user::UserPermissionLevel};
+
+use super::{assert_denied, client, seed_user, sign_in, users, bearer, body, create_post, publish, PASSWORD};
+
+#[rocket::async_test]
+async fn list_and_get_are_public() {
+    let client = client().await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+
+    let response = client.get("/users").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let page = body(response).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["items"][0]["name"], "alice");
+    assert_eq!(page["items"][1]["name"], "bob");
+
+    let response = client.get("/users/alice").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let user = body(response).await;
+    assert_eq!(user["bio"], "Hi, I'm alice.");
+    assert!(user.get("password_hash").is_none());
+
+    let response = client.get("/users/nobody").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn create_needs_the_capability() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let user = json!({"name": "bob", "password": PASSWORD, "permissions": "Editor", "bio": ""});
+
+    let alice = sign_in(&client, "alice").await;
+    let response = client.post("/users").header(bearer(&alice)).json(&user).dispatch().await;
+    assert_denied(response).await;
+
+    let admin = sign_in(&client, "admin").await;
+    let response = client.post("/users").header(bearer(&admin)).json(&user).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(body(response).await["permissions"], "Editor");
+
+    let response = client.post("/users").header(bearer(&admin)).json(&user).dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    sign_in(&client, "bob").await;
+}
+
+#[rocket::async_test]
+async fn users_may_update_themselves_but_not_their_role() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    let response = client.put("/users/alice")
+        .header(bearer(&alice))
+        .json(&json!({"name": "alice", "password": PASSWORD, "permissions": "Author", "bio": "Updated."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["bio"], "Updated.");
+
+    let response = client.put("/users/alice")
+        .header(bearer(&alice))
+        .json(&json!({"name": "alice", "password": PASSWORD, "permissions": "Admin", "bio": "Updated."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.put("/users/bob")
+        .header(bearer(&alice))
+        .json(&json!({"name": "bob", "password": PASSWORD, "permissions": "Author", "bio": "Hacked."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.put("/users/nobody")
+        .header(bearer(&alice))
+        .json(&json!({"name": "nobody", "password": PASSWORD, "permissions": "Author", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn changing_the_password_signs_out_the_other_sessions() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let here = sign_in(&client, "alice").await;
+    let elsewhere = sign_in(&client, "alice").await;
+    let post = json!({"title": "Hello", "content": "World"});
+
+    let response = client.put("/users/alice")
+        .header(bearer(&here))
+        .json(&json!({"name": "alice", "password": PASSWORD, "permissions": "Author", "bio": "Same password."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.post("/posts").header(bearer(&elsewhere)).json(&post).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client.put("/users/alice")
+        .header(bearer(&here))
+        .json(&json!({"name": "alice", "password": "a whole new password", "permissions": "Author", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.post("/posts").header(bearer(&elsewhere)).json(&json!({"title": "Again", "content": "World"})).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/posts").header(bearer(&here)).json(&json!({"title": "Still here", "content": "World"})).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[rocket::async_test]
+async fn api_keys_cant_change_the_account() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    let alice = sign_in(&client, "alice").await;
+
+    let response = client.post("/keys")
+        .header(bearer(&alice))
+        .json(&json!({"name": "Read only", "capabilities": []}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let key = body(response).await["key"].as_str().unwrap().to_string();
+
+    let response = client.put("/users/alice")
+        .header(Header::new("X-Api-Key", key.clone()))
+        .json(&json!({"name": "alice", "password": "taken over for good", "permissions": "Author", "bio": "Mine now."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.post("/auth/totp").header(Header::new("X-Api-Key", key.clone())).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    // There's no session behind a key to sign out of.
+    let response = client.post("/auth/logout").header(Header::new("X-Api-Key", key)).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(body(response).await["code"], "forbidden");
+}
+
+#[rocket::async_test]
+async fn admins_may_change_roles() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let admin = sign_in(&client, "admin").await;
+
+    let response = client.put("/users/alice")
+        .header(bearer(&admin))
+        .json(&json!({"name": "alice", "password": PASSWORD, "permissions": "Editor", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(body(response).await["permissions"], "Editor");
+}
+
+#[rocket::async_test]
+async fn only_admins_manage_admins() {
+    let client = client().await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    seed_user(&client, "manager", UserPermissionLevel::Moderator).await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let admin = sign_in(&client, "admin").await;
+
+    let moderator_role = json!({"capabilities": ["comment:create", "comment:moderate", "user:manage"]});
+    let response = client.put("/roles/Moderator").header(bearer(&admin)).json(&moderator_role).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let manager = sign_in(&client, "manager").await;
+    for (name, permissions) in [("manager", "Admin"), ("bob", "Admin"), ("admin", "Admin"), ("admin", "Reader")] {
+        let response = client.put(format!("/users/{}", name))
+            .header(bearer(&manager))
+            .json(&json!({"name": name, "password": PASSWORD, "permissions": permissions, "bio": ""}))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Forbidden, "{} to {}", name, permissions);
+    }
+
+    let response = client.post("/users")
+        .header(bearer(&manager))
+        .json(&json!({"name": "root", "password": PASSWORD, "permissions": "Admin", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.delete("/users/admin").header(bearer(&manager)).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    // Everyone else is still theirs to manage.
+    let response = client.put("/users/bob")
+        .header(bearer(&manager))
+        .json(&json!({"name": "bob", "password": PASSWORD, "permissions": "Editor", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn demotions_take_effect_right_away() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let alice = sign_in(&client, "alice").await;
+    let admin = sign_in(&client, "admin").await;
+
+    let response = client.put("/users/alice")
+        .header(bearer(&admin))
+        .json(&json!({"name": "alice", "password": PASSWORD, "permissions": "Reader", "bio": ""}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // The token still says Author.
+    let response = client.post("/posts").header(bearer(&alice)).json(&json!({"title": "Hello", "content": "World"})).dispatch().await;
+    assert_denied(response).await;
+}
+
+#[rocket::async_test]
+async fn delete_removes_everything_the_user_left_behind() {
+    let client = client().await;
+    seed_user(&client, "alice", UserPermissionLevel::Author).await;
+    seed_user(&client, "bob", UserPermissionLevel::Author).await;
+    seed_user(&client, "admin", UserPermissionLevel::Admin).await;
+    let alice = sign_in(&client, "alice").await;
+    let bob = sign_in(&client, "bob").await;
+
+    let alices = create_post(&client, &alice, "Alice's post", &[]).await;
+    publish(&client, &alice, &alices).await;
+    let bobs = create_post(&client, &bob, "Bob's post", &[]).await;
+    publish(&client, &bob, &bobs).await;
+
+    // Bob comments under Alice's post, and Alice replies to a comment under Bob's.
+    let response = client.post(format!("/posts/{}/comments", alices))
+        .header(bearer(&bob))
+        .json(&json!({"content": "Nice."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client.post(format!("/posts/{}/comments", bobs))
+        .header(bearer(&bob))
+        .json(&json!({"content": "Anyone?"}))
+        .dispatch().await;
+    let question = body(response).await["_id"].as_str().unwrap().to_string();
+
+    let response = client.post(format!("/posts/{}/comments", bobs))
+        .header(bearer(&alice))
+        .json(&json!({"content": "Me!", "parent": question}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client.post("/keys")
+        .header(bearer(&alice))
+        .json(&json!({"name": "Scripts", "capabilities": ["post:create"]}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let key = body(response).await["key"].as_str().unwrap().to_string();
+
+    let posts = client.rocket().state::<Arc<dyn PostRepository>>().unwrap();
+    let alices_id = posts.find(&alices).await.unwrap().unwrap()._id;
+    let bobs_id = posts.find(&bobs).await.unwrap().unwrap()._id;
+
+    let response = client.delete("/users/alice").header(bearer(&bob)).dispatch().await;
+    assert_denied(response).await;
+
+    let admin = sign_in(&client, "admin").await;
+    let response = client.delete("/users/alice").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    assert!(users(&client).find_by_name("alice").await.unwrap().is_none());
+    assert!(posts.find(&alices).await.unwrap().is_none());
+
+    let comments = client.rocket().state::<Arc<dyn CommentRepository>>().unwrap();
+    assert!(comments.list(&alices_id).await.unwrap().is_empty());
+    // Only Alice's reply is gone, the comment it answered stays.
+    assert_eq!(comments.list(&bobs_id).await.unwrap().len(), 1);
+
+    let revisions = client.rocket().state::<Arc<dyn RevisionRepository>>().unwrap();
+    assert!(revisions.list(&alices_id).await.unwrap().is_empty());
+    assert!(!revisions.list(&bobs_id).await.unwrap().is_empty());
+
+    let response = client.post("/auth/logout").header(bearer(&alice)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/posts")
+        .header(Header::new("X-Api-Key", key))
+        .json(&json!({"title": "From beyond", "content": "Boo."}))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.delete("/users/alice").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}